@@ -0,0 +1,524 @@
+//! The audit log: an append-only record of every change made to the store,
+//! with who made it, when, and the values before and after.
+
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+use std::str::FromStr;
+
+use chrono::{DateTime, Local, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::costing::CostingMethod;
+use crate::inventory::{Inventory, Product};
+use crate::output::{self, OutputFormat, Section};
+use crate::permission::Role;
+use crate::session::Session;
+use crate::transaction::{self, TransactionManager};
+use crate::util;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AuditAction {
+    ProductAdded,
+    ProductEdited,
+    ProductDeleted,
+    TransactionRecorded,
+    SettingChanged,
+    UserChanged,
+    DayClosed,
+}
+
+impl fmt::Display for AuditAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(match self {
+            AuditAction::ProductAdded => "Product Added",
+            AuditAction::ProductEdited => "Product Edited",
+            AuditAction::ProductDeleted => "Product Deleted",
+            AuditAction::TransactionRecorded => "Transaction Recorded",
+            AuditAction::SettingChanged => "Setting Changed",
+            AuditAction::UserChanged => "User Changed",
+            AuditAction::DayClosed => "Day Closed",
+        })
+    }
+}
+
+impl FromStr for AuditAction {
+    type Err = String;
+
+    /// Parses the names printed by `Display`.
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        match input {
+            "Product Added" => Ok(AuditAction::ProductAdded),
+            "Product Edited" => Ok(AuditAction::ProductEdited),
+            "Product Deleted" => Ok(AuditAction::ProductDeleted),
+            "Transaction Recorded" => Ok(AuditAction::TransactionRecorded),
+            "Setting Changed" => Ok(AuditAction::SettingChanged),
+            "User Changed" => Ok(AuditAction::UserChanged),
+            "Day Closed" => Ok(AuditAction::DayClosed),
+            other => Err(format!("Unknown audit action '{}'", other)),
+        }
+    }
+}
+
+/// A change about to be logged: what was done to which subject, and the
+/// values before and after. Additions have nothing before and deletions
+/// nothing after.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Change {
+    pub action: AuditAction,
+    /// The SKU, setting, username or business day the change is about
+    pub subject: String,
+    /// What changed, such as `price 10.00 -> 12.00`
+    pub details: String,
+    pub before: Option<Value>,
+    pub after: Option<Value>,
+}
+
+impl Change {
+    /// A user's role going from `before` to `after`.
+    pub fn role(username: &str, before: Role, after: Role) -> Self {
+        Change {
+            action: AuditAction::UserChanged,
+            subject: username.to_string(),
+            details: format!("role {} -> {}", before, after),
+            before: Some(json!({ "role": before.to_string() })),
+            after: Some(json!({ "role": after.to_string() })),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditEntry {
+    pub id: u64,
+    pub recorded_at: DateTime<Utc>,
+    pub username: String,
+    pub action: AuditAction,
+    pub subject: String,
+    pub details: String,
+    pub before: Option<Value>,
+    pub after: Option<Value>,
+}
+
+/// Every logged change, oldest first. Entries are only ever appended.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AuditLog {
+    pub entries: Vec<AuditEntry>,
+}
+
+/// Narrows the log down. Unset fields match everything, and the dates are
+/// inclusive days in the store's local time zone.
+#[derive(Debug, Clone, Default)]
+pub struct AuditFilter {
+    pub subject: Option<String>,
+    pub username: Option<String>,
+    pub from: Option<NaiveDate>,
+    pub to: Option<NaiveDate>,
+}
+
+impl AuditFilter {
+    pub fn matches(&self, entry: &AuditEntry) -> bool {
+        let day = entry.recorded_at.with_timezone(&Local).date_naive();
+        self.subject.as_ref().is_none_or(|s| entry.subject == *s)
+            && self.username.as_ref().is_none_or(|u| entry.username == *u)
+            && self.from.is_none_or(|from| day >= from)
+            && self.to.is_none_or(|to| day <= to)
+    }
+}
+
+impl AuditLog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Logs `change` as made by the user in `session`, now.
+    pub fn record(&mut self, session: &Session, change: Change) {
+        let id = self.entries.last().map_or(0, |entry| entry.id) + 1;
+        self.entries.push(AuditEntry {
+            id,
+            recorded_at: Utc::now(),
+            username: session.username().to_string(),
+            action: change.action,
+            subject: change.subject,
+            details: change.details,
+            before: change.before,
+            after: change.after,
+        });
+    }
+
+    /// The entries `filter` matches, oldest first.
+    pub fn query(&self, filter: &AuditFilter) -> Vec<&AuditEntry> {
+        self.entries
+            .iter()
+            .filter(|entry| filter.matches(entry))
+            .collect()
+    }
+}
+
+/// The state of the store before an action, to compare with what it left.
+pub struct Snapshot {
+    products: HashMap<String, Product>,
+    costing_method: CostingMethod,
+    transactions: usize,
+    closings: usize,
+}
+
+impl Snapshot {
+    pub fn take(inventory: &Inventory, transaction_manager: &TransactionManager) -> Self {
+        Snapshot {
+            products: inventory.products.clone(),
+            costing_method: inventory.costing_method,
+            transactions: transaction_manager.transactions.len(),
+            closings: transaction_manager.closings.len(),
+        }
+    }
+
+    /// Everything that differs between the snapshot and the store now:
+    /// products by SKU, then the costing method, then new transactions, then
+    /// closed days.
+    pub fn changes(
+        &self,
+        inventory: &Inventory,
+        transaction_manager: &TransactionManager,
+    ) -> Vec<Change> {
+        let mut changes = Vec::new();
+        let new_transactions = &transaction_manager.transactions[self
+            .transactions
+            .min(transaction_manager.transactions.len())..];
+        let moved: BTreeSet<&str> = new_transactions
+            .iter()
+            .map(|transaction| transaction.sku.as_str())
+            .collect();
+
+        let skus: BTreeSet<&String> = self
+            .products
+            .keys()
+            .chain(inventory.products.keys())
+            .collect();
+        for sku in skus {
+            let change = match (self.products.get(sku), inventory.products.get(sku)) {
+                (None, Some(after)) => Change {
+                    action: AuditAction::ProductAdded,
+                    subject: sku.clone(),
+                    details: format!(
+                        "added at price {}, cost {}, quantity {}",
+                        after.price, after.cost, after.quantity
+                    ),
+                    before: None,
+                    after: Some(json!(after)),
+                },
+                (Some(before), None) => Change {
+                    action: AuditAction::ProductDeleted,
+                    subject: sku.clone(),
+                    details: format!("deleted with quantity {}", before.quantity),
+                    before: Some(json!(before)),
+                    after: None,
+                },
+                (Some(before), Some(after)) => {
+                    // Cost layers shifting on their own are bookkeeping, not an edit,
+                    // and the stock and cost a transaction moved are logged with it
+                    let details = if moved.contains(sku.as_str()) {
+                        let unmoved = Product {
+                            quantity: before.quantity,
+                            cost: before.cost,
+                            ..after.clone()
+                        };
+                        describe_changes(before, &unmoved)
+                    } else {
+                        describe_changes(before, after)
+                    };
+                    if details.is_empty() {
+                        continue;
+                    }
+                    Change {
+                        action: AuditAction::ProductEdited,
+                        subject: sku.clone(),
+                        details,
+                        before: Some(json!(before)),
+                        after: Some(json!(after)),
+                    }
+                }
+                (None, None) => continue,
+            };
+            changes.push(change);
+        }
+
+        if self.costing_method != inventory.costing_method {
+            changes.push(Change {
+                action: AuditAction::SettingChanged,
+                subject: "costing_method".to_string(),
+                details: format!(
+                    "costing method {} -> {}",
+                    self.costing_method, inventory.costing_method
+                ),
+                before: Some(json!(self.costing_method)),
+                after: Some(json!(inventory.costing_method)),
+            });
+        }
+
+        for transaction in new_transactions {
+            changes.push(Change {
+                action: AuditAction::TransactionRecorded,
+                subject: transaction.sku.clone(),
+                details: format!(
+                    "{} #{}: {} at {}, total {}",
+                    transaction.transaction_type,
+                    transaction.id,
+                    transaction.quantity,
+                    transaction.price,
+                    transaction.total
+                ),
+                before: None,
+                after: Some(json!(transaction)),
+            });
+        }
+
+        for closing in transaction_manager.closings.iter().skip(self.closings) {
+            changes.push(Change {
+                action: AuditAction::DayClosed,
+                subject: closing.day.to_string(),
+                details: format!(
+                    "counted {}, expected {}, over/short {}",
+                    closing.counted_cash, closing.expected_cash, closing.over_short
+                ),
+                before: None,
+                after: Some(json!(closing)),
+            });
+        }
+
+        changes
+    }
+}
+
+/// Describes the fields that differ between two versions of a product.
+fn describe_changes(before: &Product, after: &Product) -> String {
+    let mut changes = Vec::new();
+    if before.name != after.name {
+        changes.push(format!("name '{}' -> '{}'", before.name, after.name));
+    }
+    if before.description != after.description {
+        changes.push(format!(
+            "description '{}' -> '{}'",
+            before.description, after.description
+        ));
+    }
+    if before.price != after.price {
+        changes.push(format!("price {} -> {}", before.price, after.price));
+    }
+    if before.cost != after.cost {
+        changes.push(format!("cost {} -> {}", before.cost, after.cost));
+    }
+    if before.quantity != after.quantity {
+        changes.push(format!(
+            "quantity {} -> {}",
+            before.quantity, after.quantity
+        ));
+    }
+    changes.join(", ")
+}
+
+/// Formats the entries as a table, newest last.
+fn entries_section(entries: &[&AuditEntry]) -> Section {
+    let headers = ["ID", "Recorded At", "User", "Action", "Subject", "Change"];
+    let rows = entries
+        .iter()
+        .map(|entry| {
+            vec![
+                entry.id.to_string(),
+                transaction::format_timestamp(&entry.recorded_at),
+                entry.username.clone(),
+                entry.action.to_string(),
+                entry.subject.clone(),
+                entry.details.clone(),
+            ]
+        })
+        .collect();
+    Section::new("Audit Entries", &headers, rows)
+}
+
+/// Public function to search the audit log by product, user and date range
+pub fn show_audit_log(audit_log: &AuditLog) {
+    println!("\n--- Audit Log ---");
+    let optional = |input: String| (!input.is_empty()).then_some(input);
+    let filter = AuditFilter {
+        subject: optional(util::get_user_input(
+            "Enter a SKU, setting or username changed (leave blank for all): ",
+        )),
+        username: optional(util::get_user_input(
+            "Enter the user who made the change (leave blank for all): ",
+        )),
+        from: util::get_date("Enter the first day, YYYY-MM-DD (leave blank for no limit): "),
+        to: util::get_date("Enter the last day, YYYY-MM-DD (leave blank for no limit): "),
+    };
+
+    let entries = audit_log.query(&filter);
+    if entries.is_empty() {
+        println!("No audit entries match.");
+        return;
+    }
+    let format = output::ask_format().unwrap_or(OutputFormat::Table);
+    println!("{}", output::render(format, &[entries_section(&entries)]));
+
+    loop {
+        let input =
+            util::get_user_input("Enter an entry ID to see its values (leave blank to finish): ");
+        if input.is_empty() {
+            return;
+        }
+        let entry = input
+            .parse::<u64>()
+            .ok()
+            .and_then(|id| entries.iter().find(|entry| entry.id == id));
+        match entry {
+            Some(entry) => {
+                let show = |value: &Option<Value>| {
+                    value
+                        .as_ref()
+                        .and_then(|value| serde_json::to_string_pretty(value).ok())
+                        .unwrap_or_else(|| "(none)".to_string())
+                };
+                println!("Before: {}", show(&entry.before));
+                println!("After: {}", show(&entry.after));
+            }
+            None => println!("No entry with ID '{}' in the results.", input),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::User;
+    use crate::money::Money;
+    use chrono::TimeZone;
+
+    fn session_for(username: &str) -> Session {
+        Session::new(User {
+            username: username.to_string(),
+            role: Role::Manager,
+        })
+    }
+
+    fn tablet() -> Product {
+        Product {
+            sku: "TAB-001".to_string(),
+            name: "Tablet".to_string(),
+            description: "A basic tablet".to_string(),
+            price: Money::from_cents(30000),
+            cost: Money::from_cents(18000),
+            quantity: 50,
+            layers: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn describes_what_an_edit_changed() {
+        let before = tablet();
+        let mut after = before.clone();
+        after.price = Money::from_cents(28000);
+        after.quantity = 45;
+
+        assert_eq!(
+            describe_changes(&before, &after),
+            "price 300.00 -> 280.00, quantity 50 -> 45"
+        );
+        assert_eq!(describe_changes(&before, &before), "");
+    }
+
+    #[test]
+    fn finds_every_change_since_the_snapshot() {
+        let mut inventory = Inventory::new();
+        inventory.products.insert("TAB-001".to_string(), tablet());
+        let transaction_manager = TransactionManager::new();
+        let snapshot = Snapshot::take(&inventory, &transaction_manager);
+
+        inventory.products.get_mut("TAB-001").unwrap().price = Money::from_cents(28000);
+        inventory.products.insert(
+            "LAP-001".to_string(),
+            Product {
+                sku: "LAP-001".to_string(),
+                ..tablet()
+            },
+        );
+        inventory.costing_method = CostingMethod::Lifo;
+
+        let changes = snapshot.changes(&inventory, &transaction_manager);
+        let actions: Vec<AuditAction> = changes.iter().map(|change| change.action).collect();
+        assert_eq!(
+            actions,
+            [
+                AuditAction::ProductAdded,
+                AuditAction::ProductEdited,
+                AuditAction::SettingChanged
+            ]
+        );
+        assert_eq!(changes[1].before.as_ref().unwrap()["price"], 30000);
+        assert_eq!(changes[1].after.as_ref().unwrap()["price"], 28000);
+        assert_eq!(changes[0].before, None);
+    }
+
+    #[test]
+    fn leaves_stock_moves_to_the_transaction_entry() {
+        let mut inventory = Inventory::new();
+        inventory.products.insert("TAB-001".to_string(), tablet());
+        let mut transaction_manager = TransactionManager::new();
+        let snapshot = Snapshot::take(&inventory, &transaction_manager);
+
+        transaction_manager
+            .record_purchase(
+                &session_for("clerk"),
+                &mut inventory,
+                "TAB-001",
+                10,
+                Money::from_cents(20000),
+            )
+            .unwrap();
+
+        let changes = snapshot.changes(&inventory, &transaction_manager);
+        let actions: Vec<AuditAction> = changes.iter().map(|change| change.action).collect();
+        assert_eq!(actions, [AuditAction::TransactionRecorded]);
+        assert_eq!(inventory.products["TAB-001"].quantity, 60);
+    }
+
+    #[test]
+    fn queries_by_subject_user_and_day() {
+        let mut log = AuditLog::new();
+        log.record(
+            &session_for("manager"),
+            Change::role("till", Role::Cashier, Role::Manager),
+        );
+        log.record(
+            &session_for("boss"),
+            Change::role("manager", Role::Manager, Role::Admin),
+        );
+        log.entries[0].recorded_at = Local
+            .with_ymd_and_hms(2026, 1, 10, 12, 0, 0)
+            .unwrap()
+            .with_timezone(&Utc);
+        log.entries[1].recorded_at = Local
+            .with_ymd_and_hms(2026, 1, 12, 12, 0, 0)
+            .unwrap()
+            .with_timezone(&Utc);
+
+        let by_user = AuditFilter {
+            username: Some("boss".to_string()),
+            ..AuditFilter::default()
+        };
+        assert_eq!(log.query(&by_user)[0].id, 2);
+
+        let by_subject = AuditFilter {
+            subject: Some("till".to_string()),
+            ..AuditFilter::default()
+        };
+        assert_eq!(log.query(&by_subject)[0].details, "role cashier -> manager");
+
+        let by_day = AuditFilter {
+            from: NaiveDate::from_ymd_opt(2026, 1, 11),
+            to: NaiveDate::from_ymd_opt(2026, 1, 12),
+            ..AuditFilter::default()
+        };
+        let entries = log.query(&by_day);
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].subject, "manager");
+        assert_eq!(log.query(&AuditFilter::default()).len(), 2);
+    }
+}
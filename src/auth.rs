@@ -1,11 +1,69 @@
 use bcrypt::{hash, verify, DEFAULT_COST};
+use chrono::{DateTime, Duration, Utc};
+use serde_json::json;
 use sqlx::sqlite::SqlitePool;
 use sqlx::Row;
 
-use crate::util;
+use crate::audit::{AuditAction, Change};
+use crate::output::{self, OutputFormat, Section};
+use crate::password::PasswordPolicy;
+use crate::permission::Role;
+use crate::transaction;
+use crate::{migration, totp, util};
+
+/// How many logins in a row may fail before an account locks, and for how
+/// long it then stays locked.
+#[derive(Debug, Clone, Copy)]
+pub struct LockoutPolicy {
+    pub max_attempts: u32,
+    pub cooldown: Duration,
+}
+
+impl Default for LockoutPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 5,
+            cooldown: Duration::minutes(15),
+        }
+    }
+}
+
+/// How many times `login` asks again before giving up.
+const LOGIN_TRIES: u32 = 3;
+
+/// The name authenticator apps list two-factor accounts under.
+const TOTP_ISSUER: &str = "Rusty Store";
+
+/// Invitation codes use letters and digits that cannot be mistaken for one
+/// another. There are 32 of them, so each random byte maps onto one evenly.
+const INVITATION_ALPHABET: &[u8] = b"ABCDEFGHJKLMNPQRSTUVWXYZ23456789";
+
+/// How long an invitation code can be used for unless the manager is told
+/// otherwise.
+const INVITATION_LIFETIME_DAYS: i64 = 7;
 
 pub struct AuthManager {
     db: SqlitePool,
+    lockout_policy: LockoutPolicy,
+    password_policy: PasswordPolicy,
+    invitation_lifetime: Duration,
+}
+
+/// A store user who has logged in.
+#[derive(Debug, Clone, PartialEq)]
+pub struct User {
+    pub username: String,
+    pub role: Role,
+}
+
+/// A user account as an admin sees it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Account {
+    pub username: String,
+    pub role: Role,
+    pub disabled: bool,
+    pub failed_attempts: u32,
+    pub locked_until: Option<DateTime<Utc>>,
 }
 
 impl AuthManager {
@@ -13,81 +71,590 @@ impl AuthManager {
         let db = SqlitePool::connect(database_url)
             .await
             .expect("Failed to connect to DB!");
-        Self { db }
+        Self {
+            db,
+            lockout_policy: LockoutPolicy::default(),
+            password_policy: PasswordPolicy::default(),
+            invitation_lifetime: Duration::days(INVITATION_LIFETIME_DAYS),
+        }
+    }
+
+    /// Replaces the default lockout policy.
+    pub fn with_lockout_policy(mut self, lockout_policy: LockoutPolicy) -> Self {
+        self.lockout_policy = lockout_policy;
+        self
+    }
+
+    /// Replaces the default password policy.
+    pub fn with_password_policy(mut self, password_policy: PasswordPolicy) -> Self {
+        self.password_policy = password_policy;
+        self
+    }
+
+    /// Replaces how long new invitation codes stay valid.
+    pub fn with_invitation_lifetime(mut self, invitation_lifetime: Duration) -> Self {
+        self.invitation_lifetime = invitation_lifetime;
+        self
+    }
+
+    /// Checks a new password against the policy and hashes it.
+    fn hash_password(&self, username: &str, password: &str) -> Result<String, String> {
+        self.password_policy.check(username, password)?;
+        hash(password, DEFAULT_COST).map_err(|_| "Failed to hash password".to_string())
     }
 
     pub async fn initialize(&self) {
+        migration::migrate_sqlite(&self.db)
+            .await
+            .expect("Failed to migrate database");
+    }
+
+    pub async fn create_user(
+        &self,
+        username: &str,
+        password: &str,
+        role: Role,
+    ) -> Result<(), String> {
+        let hashed_password = self.hash_password(username, password)?;
+        sqlx::query("INSERT INTO users (username, hashed_password, role) VALUES (?, ?, ?)")
+            .bind(username)
+            .bind(hashed_password)
+            .bind(role.to_string())
+            .execute(&self.db)
+            .await
+            .map_err(|e| format!("Failed to insert user: {}", e))?;
+        Ok(())
+    }
+
+    pub async fn has_users(&self) -> Result<bool, String> {
+        let count: i64 = sqlx::query("SELECT COUNT(*) FROM users")
+            .fetch_one(&self.db)
+            .await
+            .map_err(|e| format!("Failed to count users: {}", e))?
+            .get(0);
+        Ok(count > 0)
+    }
+
+    /// Creates the store's first account, an admin. Refuses once any user
+    /// exists, so it cannot be used to grant anyone else admin rights.
+    pub async fn create_first_admin(&self, username: &str, password: &str) -> Result<(), String> {
+        let hashed_password = self.hash_password(username, password)?;
+        let result = sqlx::query(
+            "INSERT INTO users (username, hashed_password, role) SELECT ?, ?, ? WHERE NOT EXISTS (SELECT 1 FROM users)",
+        )
+        .bind(username)
+        .bind(hashed_password)
+        .bind(Role::Admin.to_string())
+        .execute(&self.db)
+        .await
+        .map_err(|e| format!("Failed to insert user: {}", e))?;
+        if result.rows_affected() == 0 {
+            return Err("The store already has users. Ask an admin for an account.".into());
+        }
+        Ok(())
+    }
+
+    /// Generates a one-time code that lets someone register as `role` until
+    /// the invitation lifetime has passed. Returns the code and when it
+    /// expires.
+    pub async fn create_invitation(
+        &self,
+        role: Role,
+        created_by: &str,
+    ) -> Result<(String, DateTime<Utc>), String> {
+        let mut bytes = [0u8; 12];
+        getrandom::getrandom(&mut bytes).map_err(|e| format!("Failed to generate code: {}", e))?;
+        let code = bytes
+            .chunks(4)
+            .map(|chunk| {
+                chunk
+                    .iter()
+                    .map(|byte| {
+                        INVITATION_ALPHABET[*byte as usize % INVITATION_ALPHABET.len()] as char
+                    })
+                    .collect::<String>()
+            })
+            .collect::<Vec<_>>()
+            .join("-");
+
+        let created_at = Utc::now();
+        let expires_at = created_at + self.invitation_lifetime;
         sqlx::query(
-            r#"
-            CREATE TABLE IF NOT EXISTS users (
-                id INTEGER PRIMARY KEY AUTOINCREMENT,
-                username TEXT UNIQUE NOT NULL,
-                hashed_password TEXT NOT NULL
-            );
-            "#,
+            "INSERT INTO invitations (code, role, created_by, created_at, expires_at) VALUES (?, ?, ?, ?, ?)",
         )
+        .bind(&code)
+        .bind(role.to_string())
+        .bind(created_by)
+        .bind(created_at)
+        .bind(expires_at)
         .execute(&self.db)
         .await
-        .expect("Failed to create users table");
+        .map_err(|e| format!("Failed to save invitation: {}", e))?;
+        Ok((code, expires_at))
     }
 
-    pub async fn create_user(&self, username: &str, password: &str) -> Result<(), String> {
-        let hashed_password =
-            hash(password, DEFAULT_COST).map_err(|_| "Failed to hash password")?;
-        sqlx::query("INSERT INTO users (username, hashed_password) VALUES (?, ?)")
+    /// Withdraws an unused invitation `code` so nobody can register with it.
+    pub async fn revoke_invitation(&self, code: &str) -> Result<(), String> {
+        let result = sqlx::query("DELETE FROM invitations WHERE code = ? AND used_by IS NULL")
+            .bind(code.trim().to_ascii_uppercase())
+            .execute(&self.db)
+            .await
+            .map_err(|e| format!("Failed to revoke invitation: {}", e))?;
+        if result.rows_affected() == 0 {
+            return Err("The invitation code is not valid or has already been used.".into());
+        }
+        Ok(())
+    }
+
+    /// Creates an account with the role an unused, unexpired invitation
+    /// `code` grants, and uses the code up. Returns that role.
+    pub async fn register_with_invitation(
+        &self,
+        code: &str,
+        username: &str,
+        password: &str,
+    ) -> Result<Role, String> {
+        let code = code.trim().to_ascii_uppercase();
+        let mut tx = self
+            .db
+            .begin()
+            .await
+            .map_err(|e| format!("Failed to start transaction: {}", e))?;
+
+        let invitation = sqlx::query(
+            "SELECT role, expires_at FROM invitations WHERE code = ? AND used_by IS NULL",
+        )
+        .bind(&code)
+        .fetch_optional(&mut *tx)
+        .await
+        .map_err(|e| format!("Failed to read invitation: {}", e))?
+        .ok_or("The invitation code is not valid or has already been used.")?;
+        let expires_at: Option<DateTime<Utc>> = invitation.get("expires_at");
+        if expires_at.is_none_or(|expires_at| expires_at <= Utc::now()) {
+            return Err("The invitation code has expired. Ask an admin for a new one.".into());
+        }
+        let role: Role = invitation.get::<String, _>("role").parse()?;
+
+        let hashed_password = self.hash_password(username, password)?;
+        sqlx::query("INSERT INTO users (username, hashed_password, role) VALUES (?, ?, ?)")
             .bind(username)
             .bind(hashed_password)
-            .execute(&self.db)
+            .bind(role.to_string())
+            .execute(&mut *tx)
             .await
             .map_err(|e| format!("Failed to insert user: {}", e))?;
+        sqlx::query("UPDATE invitations SET used_by = ?, used_at = ? WHERE code = ?")
+            .bind(username)
+            .bind(Utc::now())
+            .bind(&code)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| format!("Failed to use invitation: {}", e))?;
+
+        tx.commit()
+            .await
+            .map_err(|e| format!("Failed to commit registration: {}", e))?;
+        Ok(role)
+    }
+
+    /// Checks the password of `username`. Each failure counts against the
+    /// account, and once the lockout policy's limit is reached it refuses
+    /// every password until the cooldown has passed. For accounts with
+    /// two-factor login the count is only cleared once `verify_totp` passes.
+    pub async fn authenticate(&self, username: &str, password: &str) -> Result<User, String> {
+        let row = sqlx::query(
+            "SELECT hashed_password, role, disabled, locked_until, totp_secret FROM users WHERE username = ?",
+        )
+        .bind(username)
+        .fetch_optional(&self.db)
+        .await
+        .map_err(|_| "Failed to query database")?;
+        let Some(row) = row else {
+            return Err("Invalid username or password".into());
+        };
+
+        check_not_locked(username, row.get("locked_until"))?;
+
+        let hashed_password: String = row.get("hashed_password");
+        if verify(password, &hashed_password).map_err(|_| "Failed to verify password")? {
+            // Only someone who knows the password learns the account is disabled
+            if row.get::<bool, _>("disabled") {
+                return Err(format!("Account '{}' is disabled.", username));
+            }
+            if row.get::<Option<String>, _>("totp_secret").is_none() {
+                self.clear_failures(username).await?;
+            }
+            return Ok(User {
+                username: username.to_string(),
+                role: row.get::<String, _>("role").parse()?,
+            });
+        }
+
+        if self.record_failure(username).await? {
+            Err(format!(
+                "Invalid username or password. Account '{}' is now locked for {} minute(s).",
+                username,
+                self.lockout_policy.cooldown.num_minutes()
+            ))
+        } else {
+            Err("Invalid username or password".into())
+        }
+    }
+
+    /// Whether `username` has to give a one-time code after the password.
+    pub async fn has_totp(&self, username: &str) -> Result<bool, String> {
+        let secret: Option<String> =
+            sqlx::query("SELECT totp_secret FROM users WHERE username = ?")
+                .bind(username)
+                .fetch_optional(&self.db)
+                .await
+                .map_err(|e| format!("Failed to read user: {}", e))?
+                .ok_or_else(|| format!("User '{}' not found.", username))?
+                .get("totp_secret");
+        Ok(secret.is_some())
+    }
+
+    /// Turns on two-factor login with `secret` once `code` shows the user's
+    /// authenticator app has it.
+    pub async fn enable_totp(
+        &self,
+        username: &str,
+        secret: &[u8],
+        code: &str,
+    ) -> Result<(), String> {
+        let Some(step) = totp::verify(secret, code, unix_now()) else {
+            return Err("The code does not match. Check the device clock and try again.".into());
+        };
+        // The code that turned it on cannot also log in
+        let result =
+            sqlx::query("UPDATE users SET totp_secret = ?, totp_last_step = ? WHERE username = ?")
+                .bind(totp::encode_base32(secret))
+                .bind(step as i64)
+                .bind(username)
+                .execute(&self.db)
+                .await
+                .map_err(|e| format!("Failed to enable two-factor login: {}", e))?;
+        if result.rows_affected() == 0 {
+            return Err(format!("User '{}' not found.", username));
+        }
         Ok(())
     }
 
-    pub async fn authenticate(&self, username: &str, password: &str) -> Result<(), String> {
-        let row = sqlx::query("SELECT hashed_password FROM users WHERE username = ?")
+    /// Turns off two-factor login for `username`.
+    pub async fn disable_totp(&self, username: &str) -> Result<(), String> {
+        let result = sqlx::query(
+            "UPDATE users SET totp_secret = NULL, totp_last_step = NULL WHERE username = ?",
+        )
+        .bind(username)
+        .execute(&self.db)
+        .await
+        .map_err(|e| format!("Failed to disable two-factor login: {}", e))?;
+        if result.rows_affected() == 0 {
+            return Err(format!("User '{}' not found.", username));
+        }
+        Ok(())
+    }
+
+    /// The second login step: checks the one-time `code` of `username`.
+    /// Wrong codes, and codes that were already used, count towards a lockout
+    /// like wrong passwords.
+    pub async fn verify_totp(&self, username: &str, code: &str) -> Result<(), String> {
+        self.verify_totp_at(username, code, unix_now()).await
+    }
+
+    async fn verify_totp_at(
+        &self,
+        username: &str,
+        code: &str,
+        unix_time: u64,
+    ) -> Result<(), String> {
+        let row = sqlx::query("SELECT totp_secret, locked_until FROM users WHERE username = ?")
             .bind(username)
             .fetch_optional(&self.db)
             .await
-            .map_err(|_| "Failed to query database")?;
+            .map_err(|_| "Failed to query database")?
+            .ok_or_else(|| format!("User '{}' not found.", username))?;
+        check_not_locked(username, row.get("locked_until"))?;
 
-        if let Some(row) = row {
-            let hashed_password: String = row.get(0);
-            if verify(password, &hashed_password).map_err(|_| "Failed to verify password")? {
-                return Ok(());
+        let secret: String = row
+            .get::<Option<String>, _>("totp_secret")
+            .ok_or_else(|| format!("Two-factor login is not enabled for '{}'.", username))?;
+        if let Some(step) = totp::verify(&totp::decode_base32(&secret)?, code, unix_time) {
+            if self.use_totp_step(username, step).await? {
+                return self.clear_failures(username).await;
             }
         }
-        Err("Invalid username or password".into())
+
+        if self.record_failure(username).await? {
+            Err(format!(
+                "Invalid authentication code. Account '{}' is now locked for {} minute(s).",
+                username,
+                self.lockout_policy.cooldown.num_minutes()
+            ))
+        } else {
+            Err("Invalid authentication code".into())
+        }
+    }
+
+    /// Marks `step` as used by `username`, unless it or a later step already
+    /// was. Returns whether the step was still unused.
+    async fn use_totp_step(&self, username: &str, step: u64) -> Result<bool, String> {
+        let result = sqlx::query(
+            "UPDATE users SET totp_last_step = ? WHERE username = ? AND (totp_last_step IS NULL OR totp_last_step < ?)",
+        )
+        .bind(step as i64)
+        .bind(username)
+        .bind(step as i64)
+        .execute(&self.db)
+        .await
+        .map_err(|e| format!("Failed to record the code: {}", e))?;
+        Ok(result.rows_affected() == 1)
+    }
+
+    /// Counts a failed login and locks the account when that reaches the
+    /// limit. Returns whether it is now locked. It all happens in one statement,
+    /// so logins failing at the same time each count.
+    async fn record_failure(&self, username: &str) -> Result<bool, String> {
+        // The counter starts again once a lockout is over, so a count of 0
+        // after a failure means this one locked the account
+        let max_attempts = self.lockout_policy.max_attempts;
+        let failed_attempts: Option<i64> = sqlx::query_scalar(
+            "UPDATE users SET failed_attempts = CASE WHEN failed_attempts + 1 >= ? THEN 0 ELSE failed_attempts + 1 END, locked_until = CASE WHEN failed_attempts + 1 >= ? THEN ? ELSE locked_until END WHERE username = ? RETURNING failed_attempts",
+        )
+        .bind(max_attempts)
+        .bind(max_attempts)
+        .bind(Utc::now() + self.lockout_policy.cooldown)
+        .bind(username)
+        .fetch_optional(&self.db)
+        .await
+        .map_err(|e| format!("Failed to count failed login: {}", e))?;
+        Ok(failed_attempts == Some(0))
+    }
+
+    async fn clear_failures(&self, username: &str) -> Result<(), String> {
+        sqlx::query("UPDATE users SET failed_attempts = 0, locked_until = NULL WHERE username = ?")
+            .bind(username)
+            .execute(&self.db)
+            .await
+            .map_err(|e| format!("Failed to reset failed logins: {}", e))?;
+        Ok(())
+    }
+
+    /// Lifts a lockout and clears the failed login count of `username`.
+    pub async fn unlock(&self, username: &str) -> Result<(), String> {
+        let result = sqlx::query(
+            "UPDATE users SET failed_attempts = 0, locked_until = NULL WHERE username = ?",
+        )
+        .bind(username)
+        .execute(&self.db)
+        .await
+        .map_err(|e| format!("Failed to unlock user: {}", e))?;
+        if result.rows_affected() == 0 {
+            return Err(format!("User '{}' not found.", username));
+        }
+        Ok(())
     }
+
+    /// Gives `username` a new role and returns the one they held before.
+    pub async fn set_role(&self, username: &str, role: Role) -> Result<Role, String> {
+        let previous: Role = sqlx::query("SELECT role FROM users WHERE username = ?")
+            .bind(username)
+            .fetch_optional(&self.db)
+            .await
+            .map_err(|e| format!("Failed to read role: {}", e))?
+            .ok_or_else(|| format!("User '{}' not found.", username))?
+            .get::<String, _>("role")
+            .parse()?;
+        sqlx::query("UPDATE users SET role = ? WHERE username = ?")
+            .bind(role.to_string())
+            .bind(username)
+            .execute(&self.db)
+            .await
+            .map_err(|e| format!("Failed to update role: {}", e))?;
+        Ok(previous)
+    }
+
+    /// Every account, ordered by username.
+    pub async fn list_users(&self) -> Result<Vec<Account>, String> {
+        let rows = sqlx::query(
+            "SELECT username, role, disabled, failed_attempts, locked_until FROM users ORDER BY username",
+        )
+        .fetch_all(&self.db)
+        .await
+        .map_err(|e| format!("Failed to list users: {}", e))?;
+
+        let mut accounts = Vec::with_capacity(rows.len());
+        for row in rows {
+            accounts.push(Account {
+                username: row.get("username"),
+                role: row.get::<String, _>("role").parse()?,
+                disabled: row.get("disabled"),
+                failed_attempts: row.get("failed_attempts"),
+                locked_until: row.get("locked_until"),
+            });
+        }
+        Ok(accounts)
+    }
+
+    /// Disables or re-enables `username`. A disabled account cannot log in.
+    pub async fn set_disabled(&self, username: &str, disabled: bool) -> Result<(), String> {
+        let result = sqlx::query("UPDATE users SET disabled = ? WHERE username = ?")
+            .bind(disabled)
+            .bind(username)
+            .execute(&self.db)
+            .await
+            .map_err(|e| format!("Failed to update user: {}", e))?;
+        if result.rows_affected() == 0 {
+            return Err(format!("User '{}' not found.", username));
+        }
+        Ok(())
+    }
+
+    /// Gives `username` a new password without knowing the old one, and lifts
+    /// any lockout.
+    pub async fn reset_password(&self, username: &str, new_password: &str) -> Result<(), String> {
+        let hashed_password = self.hash_password(username, new_password)?;
+        let result = sqlx::query(
+            "UPDATE users SET hashed_password = ?, failed_attempts = 0, locked_until = NULL WHERE username = ?",
+        )
+        .bind(hashed_password)
+        .bind(username)
+        .execute(&self.db)
+        .await
+        .map_err(|e| format!("Failed to reset password: {}", e))?;
+        if result.rows_affected() == 0 {
+            return Err(format!("User '{}' not found.", username));
+        }
+        Ok(())
+    }
+
+    /// Changes the password of `username` once `current_password` checks out.
+    pub async fn change_password(
+        &self,
+        username: &str,
+        current_password: &str,
+        new_password: &str,
+    ) -> Result<(), String> {
+        let hashed_password: String =
+            sqlx::query("SELECT hashed_password FROM users WHERE username = ?")
+                .bind(username)
+                .fetch_optional(&self.db)
+                .await
+                .map_err(|_| "Failed to query database")?
+                .ok_or_else(|| format!("User '{}' not found.", username))?
+                .get("hashed_password");
+        if !verify(current_password, &hashed_password).map_err(|_| "Failed to verify password")? {
+            return Err("The current password is incorrect.".into());
+        }
+        self.reset_password(username, new_password).await
+    }
+}
+
+/// Refuses while a lockout of `username` is still running.
+fn check_not_locked(username: &str, locked_until: Option<DateTime<Utc>>) -> Result<(), String> {
+    let now = Utc::now();
+    match locked_until {
+        Some(locked_until) if locked_until > now => {
+            let minutes = (locked_until - now).num_minutes() + 1;
+            Err(format!(
+                "Account '{}' is locked after too many failed logins. Try again in {} minute(s) or ask an admin to unlock it.",
+                username, minutes
+            ))
+        }
+        _ => Ok(()),
+    }
+}
+
+fn unix_now() -> u64 {
+    Utc::now().timestamp().max(0) as u64
 }
 
-pub async fn login(auth_manager: &AuthManager) {
-    let username = util::get_user_input("Enter your username: ");
+/// Asks for a username and password, allowing a few tries with a growing
+/// pause between them. Returns `None` once the tries are used up.
+pub async fn login(auth_manager: &AuthManager) -> Option<User> {
+    for attempt in 1..=LOGIN_TRIES {
+        let username = util::get_user_input("Enter your username: ");
+
+        println!("Enter your password:");
+        let password = rpassword::read_password().expect("Failed to read password"); // Secure password input
+
+        let result = match auth_manager.authenticate(&username, &password).await {
+            Ok(user) => match auth_manager.has_totp(&user.username).await {
+                Ok(true) => {
+                    let code = util::get_user_input(
+                        "Enter the 6-digit code from your authenticator app: ",
+                    );
+                    auth_manager
+                        .verify_totp(&user.username, &code)
+                        .await
+                        .map(|_| user)
+                }
+                Ok(false) => {
+                    if user.role != Role::Cashier {
+                        println!(
+                            "Tip: protect your {} account with Two-Factor Login from the menu.",
+                            user.role
+                        );
+                    }
+                    Ok(user)
+                }
+                Err(e) => Err(e),
+            },
+            Err(e) => Err(e),
+        };
+
+        match result {
+            Ok(user) => {
+                println!(
+                    "Login successful! Signed in as {} ({}).",
+                    user.username, user.role
+                );
+                return Some(user);
+            }
+            Err(e) => {
+                println!("Login failed: {}", e);
+                if attempt < LOGIN_TRIES {
+                    tokio::time::sleep(std::time::Duration::from_secs(attempt.into())).await;
+                }
+            }
+        }
+    }
+    println!("Too many failed login attempts.");
+    None
+}
 
-    println!("Enter your password:");
-    let password = rpassword::read_password().expect("Failed to read password"); // Secure password input
+/// Sets up the admin account of a new store and logs it in.
+pub async fn create_first_admin(auth_manager: &AuthManager) -> Option<User> {
+    println!("Welcome! The store has no users yet, so create the admin account.");
+    let username = util::get_user_input("Choose an admin username: ");
+    let password = read_new_password()?;
 
-    match auth_manager.authenticate(&username, &password).await {
+    match auth_manager.create_first_admin(&username, &password).await {
         Ok(_) => {
-            println!("Login successful!");
-            // start_store_operations().await; // Proceed to store operations
+            println!("Admin account created! You can now log in.");
+            login(auth_manager).await
         }
         Err(e) => {
-            println!("Login failed: {}", e);
-            std::process::exit(1);
+            println!("Setup failed: {}", e);
+            None
         }
     }
 }
 
-pub async fn register(auth_manager: &AuthManager) {
+/// Registers a new user with an invitation code from an admin and logs them
+/// in. The code decides the user's role.
+pub async fn register(auth_manager: &AuthManager) -> Option<User> {
+    let code = util::get_user_input("Enter your invitation code: ");
     let username = util::get_user_input("Choose a unique username: ");
+    let password = read_new_password()?;
 
-    println!("Choose a password:");
-    let password = rpassword::read_password().expect("Failed to read password"); // Secure password input
-
-    match auth_manager.create_user(&username, &password).await {
-        Ok(_) => {
-            println!("Registration successful! You can now log in.");
-            login(auth_manager).await;
+    match auth_manager
+        .register_with_invitation(&code, &username, &password)
+        .await
+    {
+        Ok(role) => {
+            println!("Registration successful as {}! You can now log in.", role);
+            login(auth_manager).await
         }
         Err(e) => {
             if e.contains("UNIQUE constraint failed") {
@@ -95,7 +662,717 @@ pub async fn register(auth_manager: &AuthManager) {
             } else {
                 println!("Registration failed: {}", e);
             }
-            std::process::exit(1);
+            None
+        }
+    }
+}
+
+/// Lets an admin create an account directly.
+async fn create_user(auth_manager: &AuthManager) -> Option<Change> {
+    let username = util::get_user_input("Enter the new username: ");
+    let role = match read_role() {
+        Ok(role) => role,
+        Err(e) => {
+            println!("{}", e);
+            return None;
+        }
+    };
+    let password = read_new_password()?;
+
+    match auth_manager.create_user(&username, &password, role).await {
+        Ok(_) => {
+            println!("{} created as a {}.", username, role);
+            Some(Change {
+                after: Some(json!({ "role": role.to_string() })),
+                ..account_change(&username, &format!("created as {}", role))
+            })
+        }
+        Err(e) => {
+            println!("Error creating user: {}", e);
+            None
+        }
+    }
+}
+
+/// Lets an admin generate a one-time invitation code for a new user.
+async fn invite_user(auth_manager: &AuthManager, user: &User) -> Option<Change> {
+    let role = match read_role() {
+        Ok(role) => role,
+        Err(e) => {
+            println!("{}", e);
+            return None;
+        }
+    };
+
+    match auth_manager.create_invitation(role, &user.username).await {
+        Ok((code, expires_at)) => {
+            println!("Invitation code for a new {}: {}", role, code);
+            println!(
+                "It can be used once, from Register on the login screen, until {}.",
+                transaction::format_timestamp(&expires_at)
+            );
+            Some(account_change("invitation", &format!("invited a {}", role)))
         }
+        Err(e) => {
+            println!("Error creating invitation: {}", e);
+            None
+        }
+    }
+}
+
+/// Lets an admin withdraw an invitation code that has not been used yet.
+async fn revoke_invitation(auth_manager: &AuthManager) -> Option<Change> {
+    let code = util::get_user_input("Enter the invitation code to revoke: ");
+    match auth_manager.revoke_invitation(&code).await {
+        Ok(_) => {
+            println!("The invitation code is revoked.");
+            Some(account_change("invitation", "revoked an invitation"))
+        }
+        Err(e) => {
+            println!("Error revoking invitation: {}", e);
+            None
+        }
+    }
+}
+
+fn read_role() -> Result<Role, String> {
+    util::get_user_input("Enter the role (admin, manager, cashier): ").parse()
+}
+
+/// Lets an admin change the role of another user.
+async fn set_user_role(auth_manager: &AuthManager, user: &User) -> Option<Change> {
+    let username = util::get_user_input("Enter the username to change: ");
+    if username == user.username {
+        println!("You cannot change your own role.");
+        return None;
+    }
+    let role = match read_role() {
+        Ok(role) => role,
+        Err(e) => {
+            println!("{}", e);
+            return None;
+        }
+    };
+
+    match auth_manager.set_role(&username, role).await {
+        Ok(previous) => {
+            println!("{} is now a {}.", username, role);
+            Some(Change::role(&username, previous, role))
+        }
+        Err(e) => {
+            println!("Error changing role: {}", e);
+            None
+        }
+    }
+}
+
+/// Lets an admin lift the lockout on an account.
+async fn unlock_user(auth_manager: &AuthManager) -> Option<Change> {
+    let username = util::get_user_input("Enter the username to unlock: ");
+    match auth_manager.unlock(&username).await {
+        Ok(_) => {
+            println!("{} is unlocked.", username);
+            Some(account_change(&username, "unlocked"))
+        }
+        Err(e) => {
+            println!("Error unlocking user: {}", e);
+            None
+        }
+    }
+}
+
+/// Public function to list every account with its role and status
+pub async fn list_users(auth_manager: &AuthManager) {
+    let accounts = match auth_manager.list_users().await {
+        Ok(accounts) => accounts,
+        Err(e) => {
+            println!("Error listing users: {}", e);
+            return;
+        }
+    };
+
+    let now = Utc::now();
+    let headers = ["Username", "Role", "Status", "Failed Logins"];
+    let rows = accounts
+        .iter()
+        .map(|account| {
+            let status = if account.disabled {
+                "disabled".to_string()
+            } else {
+                match account.locked_until {
+                    Some(until) if until > now => {
+                        format!("locked until {}", transaction::format_timestamp(&until))
+                    }
+                    _ => "active".to_string(),
+                }
+            };
+            vec![
+                account.username.clone(),
+                account.role.to_string(),
+                status,
+                account.failed_attempts.to_string(),
+            ]
+        })
+        .collect();
+    let format = output::ask_format().unwrap_or(OutputFormat::Table);
+    println!(
+        "{}",
+        output::render(format, &[Section::new("Users", &headers, rows)])
+    );
+}
+
+/// Lets an admin disable or re-enable another user's account.
+async fn set_user_disabled(
+    auth_manager: &AuthManager,
+    user: &User,
+    disabled: bool,
+) -> Option<Change> {
+    let username = util::get_user_input("Enter the username: ");
+    if disabled && username == user.username {
+        println!("You cannot disable your own account.");
+        return None;
+    }
+    let verb = if disabled { "disabled" } else { "enabled" };
+    match auth_manager.set_disabled(&username, disabled).await {
+        Ok(_) => {
+            println!("{} is {}.", username, verb);
+            Some(Change {
+                before: Some(json!({ "disabled": !disabled })),
+                after: Some(json!({ "disabled": disabled })),
+                ..account_change(&username, verb)
+            })
+        }
+        Err(e) => {
+            println!("Error updating user: {}", e);
+            None
+        }
+    }
+}
+
+/// Lets an admin set a new password for a user who has lost theirs.
+async fn reset_user_password(auth_manager: &AuthManager) -> Option<Change> {
+    let username = util::get_user_input("Enter the username to reset: ");
+    let password = read_new_password()?;
+    match auth_manager.reset_password(&username, &password).await {
+        Ok(_) => {
+            println!("The password of {} has been reset.", username);
+            Some(account_change(&username, "password reset"))
+        }
+        Err(e) => {
+            println!("Error resetting password: {}", e);
+            None
+        }
+    }
+}
+
+/// Lets the logged-in user change their own password.
+pub async fn change_own_password(auth_manager: &AuthManager, user: &User) -> Option<Change> {
+    println!("Enter your current password:");
+    let current = rpassword::read_password().expect("Failed to read password");
+    let password = read_new_password()?;
+    match auth_manager
+        .change_password(&user.username, &current, &password)
+        .await
+    {
+        Ok(_) => {
+            println!("Password changed.");
+            Some(account_change(&user.username, "password changed"))
+        }
+        Err(e) => {
+            println!("Error changing password: {}", e);
+            None
+        }
+    }
+}
+
+/// Lets the logged-in user turn two-factor login on, or off again after
+/// giving a current code.
+pub async fn two_factor(auth_manager: &AuthManager, user: &User) -> Option<Change> {
+    let enabled = match auth_manager.has_totp(&user.username).await {
+        Ok(enabled) => enabled,
+        Err(e) => {
+            println!("Error reading two-factor login: {}", e);
+            return None;
+        }
+    };
+
+    if enabled {
+        let answer = util::get_user_input("Two-factor login is on. Turn it off? (y/N): ");
+        if !answer.eq_ignore_ascii_case("y") {
+            return None;
+        }
+        let code = util::get_user_input("Enter the 6-digit code from your authenticator app: ");
+        let result = match auth_manager.verify_totp(&user.username, &code).await {
+            Ok(_) => auth_manager.disable_totp(&user.username).await,
+            Err(e) => Err(e),
+        };
+        return match result {
+            Ok(_) => {
+                println!("Two-factor login is off.");
+                Some(two_factor_change(&user.username, false))
+            }
+            Err(e) => {
+                println!("Error turning off two-factor login: {}", e);
+                None
+            }
+        };
+    }
+
+    let secret = match totp::generate_secret() {
+        Ok(secret) => secret,
+        Err(e) => {
+            println!("Error setting up two-factor login: {}", e);
+            return None;
+        }
+    };
+    println!("Add this account to your authenticator app with the URI or the key below.");
+    println!(
+        "URI: {}",
+        totp::provisioning_uri(TOTP_ISSUER, &user.username, &secret)
+    );
+    println!("Key: {}", totp::encode_base32(&secret));
+    let code = util::get_user_input("Enter the 6-digit code the app shows to confirm: ");
+
+    match auth_manager
+        .enable_totp(&user.username, &secret, &code)
+        .await
+    {
+        Ok(_) => {
+            println!("Two-factor login is on. You will be asked for a code at each login.");
+            Some(two_factor_change(&user.username, true))
+        }
+        Err(e) => {
+            println!("Error turning on two-factor login: {}", e);
+            None
+        }
+    }
+}
+
+/// Lets an admin turn off two-factor login for a user who lost their device.
+async fn reset_two_factor(auth_manager: &AuthManager) -> Option<Change> {
+    let username = util::get_user_input("Enter the username: ");
+    match auth_manager.disable_totp(&username).await {
+        Ok(_) => {
+            println!("Two-factor login is off for {}.", username);
+            Some(two_factor_change(&username, false))
+        }
+        Err(e) => {
+            println!("Error resetting two-factor login: {}", e);
+            None
+        }
+    }
+}
+
+/// The secret itself never goes in the audit log, only whether it is set.
+fn two_factor_change(username: &str, enabled: bool) -> Change {
+    let details = if enabled {
+        "two-factor login on"
+    } else {
+        "two-factor login off"
+    };
+    Change {
+        before: Some(json!({ "two_factor": !enabled })),
+        after: Some(json!({ "two_factor": enabled })),
+        ..account_change(username, details)
+    }
+}
+
+/// Asks for a new password twice. Returns `None` if the two entries differ.
+/// Whether it is strong enough is up to the password policy.
+fn read_new_password() -> Option<String> {
+    println!("Enter the new password:");
+    let password = rpassword::read_password().expect("Failed to read password");
+    println!("Confirm the new password:");
+    let confirmation = rpassword::read_password().expect("Failed to read password");
+    if password != confirmation {
+        println!("The passwords do not match.");
+        return None;
+    }
+    Some(password)
+}
+
+/// A change to an account whose values are not worth keeping, such as a new
+/// password hash.
+fn account_change(username: &str, details: &str) -> Change {
+    Change {
+        action: AuditAction::UserChanged,
+        subject: username.to_string(),
+        details: details.to_string(),
+        before: None,
+        after: None,
+    }
+}
+
+/// Public function for the admin's user management submenu. Returns the
+/// change made, if any, for the audit log.
+pub async fn manage_users(auth_manager: &AuthManager, user: &User) -> Option<Change> {
+    println!("\n--- Manage Users ---");
+    let choice = util::get_user_input(
+        "Choose an action: \n 1. List Users, \n 2. Set Role, \n 3. Disable Account, \n 4. Enable Account, \n 5. Reset Password, \n 6. Unlock Account, \n 7. Create User, \n 8. Invite User, \n 9. Reset Two-Factor Login, \n 10. Revoke Invitation: ",
+    );
+
+    match choice.as_str() {
+        "1" => {
+            list_users(auth_manager).await;
+            None
+        }
+        "2" => set_user_role(auth_manager, user).await,
+        "3" => set_user_disabled(auth_manager, user, true).await,
+        "4" => set_user_disabled(auth_manager, user, false).await,
+        "5" => reset_user_password(auth_manager).await,
+        "6" => unlock_user(auth_manager).await,
+        "7" => create_user(auth_manager).await,
+        "8" => invite_user(auth_manager, user).await,
+        "9" => reset_two_factor(auth_manager).await,
+        "10" => revoke_invitation(auth_manager).await,
+        _ => {
+            println!("Invalid choice.");
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    async fn auth_manager() -> AuthManager {
+        let auth_manager = AuthManager::new("sqlite::memory:").await;
+        auth_manager.initialize().await;
+        auth_manager
+    }
+
+    #[tokio::test]
+    async fn authenticates_with_the_stored_role() {
+        let auth_manager = auth_manager().await;
+        assert!(!auth_manager.has_users().await.unwrap());
+        auth_manager
+            .create_user("till", "Secret-123", Role::Cashier)
+            .await
+            .unwrap();
+
+        let user = auth_manager
+            .authenticate("till", "Secret-123")
+            .await
+            .unwrap();
+        assert_eq!(user.role, Role::Cashier);
+        assert!(auth_manager.authenticate("till", "wrong").await.is_err());
+
+        assert_eq!(
+            auth_manager.set_role("till", Role::Manager).await,
+            Ok(Role::Cashier)
+        );
+        let user = auth_manager
+            .authenticate("till", "Secret-123")
+            .await
+            .unwrap();
+        assert_eq!(user.role, Role::Manager);
+        assert!(auth_manager.set_role("nobody", Role::Admin).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn locks_after_repeated_failures_until_unlocked() {
+        let auth_manager = auth_manager().await.with_lockout_policy(LockoutPolicy {
+            max_attempts: 3,
+            cooldown: Duration::minutes(15),
+        });
+        auth_manager
+            .create_user("till", "Secret-123", Role::Cashier)
+            .await
+            .unwrap();
+
+        for _ in 0..2 {
+            assert_eq!(
+                auth_manager
+                    .authenticate("till", "wrong")
+                    .await
+                    .unwrap_err(),
+                "Invalid username or password"
+            );
+        }
+        let err = auth_manager
+            .authenticate("till", "wrong")
+            .await
+            .unwrap_err();
+        assert!(err.contains("now locked for 15 minute(s)"));
+
+        let err = auth_manager
+            .authenticate("till", "Secret-123")
+            .await
+            .unwrap_err();
+        assert!(err.contains("is locked"));
+
+        auth_manager.unlock("till").await.unwrap();
+        assert!(auth_manager
+            .authenticate("till", "Secret-123")
+            .await
+            .is_ok());
+        assert!(auth_manager.unlock("nobody").await.is_err());
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn counts_failures_made_at_the_same_time() {
+        let auth_manager = Arc::new(auth_manager().await.with_lockout_policy(LockoutPolicy {
+            max_attempts: 2,
+            cooldown: Duration::minutes(15),
+        }));
+        auth_manager
+            .create_user("till", "Secret-123", Role::Cashier)
+            .await
+            .unwrap();
+
+        // Both check the password, which takes a while, before either counts
+        // its failure
+        let attempts: Vec<_> = (0..2)
+            .map(|_| {
+                let auth_manager = Arc::clone(&auth_manager);
+                tokio::spawn(async move { auth_manager.authenticate("till", "wrong").await })
+            })
+            .collect();
+        for attempt in attempts {
+            assert!(attempt.await.unwrap().is_err());
+        }
+        let err = auth_manager
+            .authenticate("till", "Secret-123")
+            .await
+            .unwrap_err();
+        assert!(err.contains("is locked"));
+    }
+
+    #[tokio::test]
+    async fn lockout_ends_after_the_cooldown() {
+        let auth_manager = auth_manager().await.with_lockout_policy(LockoutPolicy {
+            max_attempts: 1,
+            cooldown: Duration::zero(),
+        });
+        auth_manager
+            .create_user("till", "Secret-123", Role::Cashier)
+            .await
+            .unwrap();
+
+        assert!(auth_manager.authenticate("till", "wrong").await.is_err());
+        assert!(auth_manager
+            .authenticate("till", "Secret-123")
+            .await
+            .is_ok());
+    }
+
+    #[tokio::test]
+    async fn manages_accounts() {
+        let auth_manager = auth_manager().await;
+        for username in ["till", "boss"] {
+            auth_manager
+                .create_user(username, "Secret-123", Role::Cashier)
+                .await
+                .unwrap();
+        }
+
+        auth_manager.set_disabled("till", true).await.unwrap();
+        let err = auth_manager
+            .authenticate("till", "Secret-123")
+            .await
+            .unwrap_err();
+        assert_eq!(err, "Account 'till' is disabled.");
+        assert_eq!(
+            auth_manager
+                .authenticate("till", "wrong")
+                .await
+                .unwrap_err(),
+            "Invalid username or password"
+        );
+        let accounts = auth_manager.list_users().await.unwrap();
+        assert_eq!(accounts[0].username, "boss");
+        assert!(!accounts[0].disabled);
+        assert!(accounts[1].disabled);
+
+        auth_manager.set_disabled("till", false).await.unwrap();
+        auth_manager
+            .reset_password("till", "Fresh-456")
+            .await
+            .unwrap();
+        assert!(auth_manager
+            .authenticate("till", "Secret-123")
+            .await
+            .is_err());
+        assert!(auth_manager.authenticate("till", "Fresh-456").await.is_ok());
+
+        assert_eq!(
+            auth_manager
+                .change_password("boss", "wrong", "Newer-789")
+                .await,
+            Err("The current password is incorrect.".to_string())
+        );
+        auth_manager
+            .change_password("boss", "Secret-123", "Newer-789")
+            .await
+            .unwrap();
+        assert!(auth_manager.authenticate("boss", "Newer-789").await.is_ok());
+        assert!(auth_manager.set_disabled("nobody", true).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn only_the_first_account_bootstraps_as_admin() {
+        let auth_manager = auth_manager().await;
+        auth_manager
+            .create_first_admin("boss", "Secret-123")
+            .await
+            .unwrap();
+        let user = auth_manager
+            .authenticate("boss", "Secret-123")
+            .await
+            .unwrap();
+        assert_eq!(user.role, Role::Admin);
+        assert!(auth_manager
+            .create_first_admin("intruder", "Secret-123")
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn invitation_codes_work_once() {
+        let auth_manager = auth_manager().await;
+        let (code, expires_at) = auth_manager
+            .create_invitation(Role::Manager, "boss")
+            .await
+            .unwrap();
+        assert_eq!(code.len(), 14);
+        assert!(expires_at > Utc::now() + Duration::days(6));
+
+        let role = auth_manager
+            .register_with_invitation(&code.to_lowercase(), "floor", "Secret-123")
+            .await
+            .unwrap();
+        assert_eq!(role, Role::Manager);
+        assert_eq!(
+            auth_manager
+                .authenticate("floor", "Secret-123")
+                .await
+                .unwrap()
+                .role,
+            Role::Manager
+        );
+        assert!(auth_manager
+            .register_with_invitation(&code, "again", "Secret-123")
+            .await
+            .is_err());
+        assert!(auth_manager
+            .register_with_invitation("NOPE-NOPE-NOPE", "guess", "Secret-123")
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn expired_and_revoked_invitations_are_refused() {
+        let auth_manager = AuthManager::new("sqlite::memory:")
+            .await
+            .with_invitation_lifetime(Duration::zero());
+        auth_manager.initialize().await;
+        let (code, _) = auth_manager
+            .create_invitation(Role::Admin, "boss")
+            .await
+            .unwrap();
+        assert_eq!(
+            auth_manager
+                .register_with_invitation(&code, "late", "Secret-123")
+                .await
+                .unwrap_err(),
+            "The invitation code has expired. Ask an admin for a new one."
+        );
+        assert!(!auth_manager.has_users().await.unwrap());
+
+        let auth_manager = auth_manager.with_invitation_lifetime(Duration::days(1));
+        let (code, _) = auth_manager
+            .create_invitation(Role::Admin, "boss")
+            .await
+            .unwrap();
+        auth_manager.revoke_invitation(&code).await.unwrap();
+        assert!(auth_manager
+            .register_with_invitation(&code, "late", "Secret-123")
+            .await
+            .is_err());
+        assert!(auth_manager.revoke_invitation(&code).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn enforces_the_password_policy() {
+        let auth_manager = auth_manager().await;
+        let err = auth_manager
+            .create_user("till", "", Role::Cashier)
+            .await
+            .unwrap_err();
+        assert!(err.starts_with("The password must be at least 8 characters long"));
+        assert!(!auth_manager.has_users().await.unwrap());
+
+        auth_manager
+            .create_user("till", "Secret-123", Role::Cashier)
+            .await
+            .unwrap();
+        assert_eq!(
+            auth_manager.reset_password("till", "Till-1234").await,
+            Err("The password must not contain the username.".to_string())
+        );
+    }
+
+    #[tokio::test]
+    async fn asks_for_a_code_once_two_factor_is_on() {
+        let auth_manager = auth_manager().await.with_lockout_policy(LockoutPolicy {
+            max_attempts: 2,
+            cooldown: Duration::minutes(15),
+        });
+        auth_manager
+            .create_user("floor", "Secret-123", Role::Manager)
+            .await
+            .unwrap();
+        assert!(!auth_manager.has_totp("floor").await.unwrap());
+
+        let secret = b"12345678901234567890";
+        let now = unix_now();
+        assert!(auth_manager
+            .enable_totp("floor", secret, "000000x")
+            .await
+            .is_err());
+        let code = format!("{:06}", totp::totp(secret, now, totp::DIGITS));
+        auth_manager
+            .enable_totp("floor", secret, &code)
+            .await
+            .unwrap();
+        assert!(auth_manager.has_totp("floor").await.unwrap());
+
+        // A right password alone does not reset the count of failed logins
+        assert!(auth_manager.authenticate("floor", "wrong").await.is_err());
+        assert!(auth_manager
+            .authenticate("floor", "Secret-123")
+            .await
+            .is_ok());
+        let wrong = format!(
+            "{:06}",
+            (totp::totp(secret, now, totp::DIGITS) + 1) % 1_000_000
+        );
+        let err = auth_manager
+            .verify_totp_at("floor", &wrong, now + 10 * totp::STEP)
+            .await
+            .unwrap_err();
+        assert!(err.contains("now locked"));
+
+        auth_manager.unlock("floor").await.unwrap();
+        // The code that turned two-factor login on is already used up
+        assert!(auth_manager
+            .verify_totp_at("floor", &code, now)
+            .await
+            .is_err());
+        let next = format!("{:06}", totp::totp(secret, now + totp::STEP, totp::DIGITS));
+        assert!(auth_manager
+            .verify_totp_at("floor", &next, now + totp::STEP)
+            .await
+            .is_ok());
+        assert_eq!(
+            auth_manager
+                .verify_totp_at("floor", &next, now + totp::STEP)
+                .await,
+            Err("Invalid authentication code".to_string())
+        );
+        auth_manager.disable_totp("floor").await.unwrap();
+        assert!(!auth_manager.has_totp("floor").await.unwrap());
     }
 }
@@ -0,0 +1,711 @@
+//! Subcommands for running the store from scripts and cron jobs, without the
+//! menu. Each command logs in with credentials from flags or environment
+//! variables, checks the role may run it and exits with a code scripts can
+//! test: 0 on success, 1 when the store refuses the operation, 2 for usage
+//! errors, 3 when logging in fails and 4 when the role is not allowed.
+
+use std::fmt;
+use std::process::ExitCode;
+
+use chrono::{Local, NaiveDate};
+use clap::{Args, Parser, Subcommand};
+use serde_json::json;
+
+use crate::audit::{AuditAction, Change, Snapshot};
+use crate::auth::{AuthManager, User};
+use crate::inventory::{self, InventoryManagement, Product};
+use crate::money::Money;
+use crate::output::{self, OutputFormat};
+use crate::permission::{Permission, Role};
+use crate::reporting::{self, Period, Preset, Rendered, Report};
+use crate::session::Session;
+use crate::storage::Storage;
+use crate::transaction::{OrderLine, PaymentMethod};
+use crate::{closing, transaction, Store};
+
+pub const DEFAULT_DATABASE_URL: &str = "sqlite:db/rusty_store.db";
+
+#[derive(Debug, Parser)]
+#[command(
+    name = "rusty_store",
+    version,
+    about = "Inventory, sales and purchases for a small store. Run without a command for the menu."
+)]
+pub struct Cli {
+    /// Database to open
+    #[arg(long, env = "RUSTY_STORE_DATABASE", default_value = DEFAULT_DATABASE_URL, global = true)]
+    pub database: String,
+    /// How to print listings and reports: table, list, json, csv, markdown or html
+    #[arg(long, global = true)]
+    pub format: Option<OutputFormat>,
+    #[command(flatten)]
+    pub credentials: Credentials,
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+/// Who runs the command. Prefer the environment variables for the password,
+/// as flags show up in the process list.
+#[derive(Debug, Default, Args)]
+pub struct Credentials {
+    #[arg(long, env = "RUSTY_STORE_USERNAME", global = true)]
+    pub username: Option<String>,
+    #[arg(
+        long,
+        env = "RUSTY_STORE_PASSWORD",
+        hide_env_values = true,
+        global = true
+    )]
+    pub password: Option<String>,
+    /// Code from the authenticator app, for accounts with two-factor login
+    #[arg(
+        long,
+        env = "RUSTY_STORE_TOTP_CODE",
+        hide_env_values = true,
+        global = true
+    )]
+    pub totp_code: Option<String>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Add, edit, delete or list products
+    #[command(subcommand)]
+    Product(ProductCommand),
+    /// Record sales
+    #[command(subcommand)]
+    Sale(SaleCommand),
+    /// Record purchases
+    #[command(subcommand)]
+    Purchase(PurchaseCommand),
+    /// List transactions
+    #[command(subcommand)]
+    Transaction(TransactionCommand),
+    /// Print a report
+    Report {
+        /// inventory, sales, purchases, cashiers, profit-loss or all
+        report: Report,
+        /// today, this-week, this-month or last-month
+        #[arg(long, conflicts_with_all = ["from", "to"])]
+        period: Option<Preset>,
+        /// First day to include, as YYYY-MM-DD
+        #[arg(long)]
+        from: Option<NaiveDate>,
+        /// Last day to include, as YYYY-MM-DD
+        #[arg(long)]
+        to: Option<NaiveDate>,
+    },
+    /// Manage user accounts
+    #[command(subcommand)]
+    User(UserCommand),
+    /// Count the till, print the Z-report and lock the business day
+    CloseDay {
+        /// Cash counted in the drawer
+        #[arg(long)]
+        counted_cash: Money,
+        /// Day to close, as YYYY-MM-DD, today if left out
+        #[arg(long)]
+        day: Option<NaiveDate>,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ProductCommand {
+    Add {
+        #[arg(long)]
+        sku: String,
+        #[arg(long)]
+        name: String,
+        #[arg(long, default_value = "")]
+        description: String,
+        #[arg(long)]
+        price: Money,
+        /// What one unit costs the store
+        #[arg(long, default_value = "0")]
+        cost: Money,
+        #[arg(long, default_value_t = 0)]
+        quantity: u32,
+    },
+    /// Change the given fields of a product
+    Edit {
+        #[arg(long)]
+        sku: String,
+        #[arg(long)]
+        name: Option<String>,
+        #[arg(long)]
+        description: Option<String>,
+        #[arg(long)]
+        price: Option<Money>,
+        #[arg(long)]
+        cost: Option<Money>,
+        #[arg(long)]
+        quantity: Option<u32>,
+    },
+    Delete {
+        #[arg(long)]
+        sku: String,
+    },
+    List,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum SaleCommand {
+    Record {
+        #[arg(long)]
+        sku: String,
+        #[arg(long)]
+        quantity: u32,
+        /// Sale price per unit, the product's list price if left out
+        #[arg(long)]
+        price: Option<Money>,
+        /// cash, card or other
+        #[arg(long, default_value = "cash")]
+        payment: PaymentMethod,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum PurchaseCommand {
+    Record {
+        #[arg(long)]
+        sku: String,
+        #[arg(long)]
+        quantity: u32,
+        /// Purchase price per unit
+        #[arg(long)]
+        price: Money,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum TransactionCommand {
+    List,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum UserCommand {
+    Create {
+        /// Username of the new account
+        #[arg(long)]
+        name: String,
+        /// admin, manager or cashier
+        #[arg(long)]
+        role: Role,
+        #[arg(long, env = "RUSTY_STORE_NEW_PASSWORD", hide_env_values = true)]
+        new_password: String,
+    },
+}
+
+impl Command {
+    /// The permission the command needs and how to name it when refused.
+    fn permission(&self) -> (Permission, &'static str) {
+        match self {
+            Command::Product(ProductCommand::Add { .. }) => {
+                (Permission::EditProducts, "add products")
+            }
+            Command::Product(ProductCommand::Edit { .. }) => {
+                (Permission::EditProducts, "edit products")
+            }
+            Command::Product(ProductCommand::Delete { .. }) => {
+                (Permission::DeleteProducts, "delete products")
+            }
+            Command::Product(ProductCommand::List) => (Permission::ViewInventory, "list products"),
+            Command::Sale(_) => (Permission::RecordSales, "record sales"),
+            Command::Purchase(_) => (Permission::RecordPurchases, "record purchases"),
+            Command::Transaction(_) => (Permission::ViewTransactions, "list transactions"),
+            Command::Report { .. } => (Permission::ViewReports, "generate reports"),
+            Command::User(_) => (Permission::ManageUsers, "manage users"),
+            Command::CloseDay { .. } => (Permission::CloseDay, "close the day"),
+        }
+    }
+
+    /// Whether the command changes the inventory or history, which then has
+    /// to be saved.
+    fn changes_store(&self) -> bool {
+        matches!(
+            self,
+            Command::Product(ProductCommand::Add { .. })
+                | Command::Product(ProductCommand::Edit { .. })
+                | Command::Product(ProductCommand::Delete { .. })
+                | Command::Sale(_)
+                | Command::Purchase(_)
+                | Command::CloseDay { .. }
+        )
+    }
+}
+
+/// Why a command did not run, which decides the exit code.
+#[derive(Debug, PartialEq)]
+pub enum Failure {
+    /// The store refused the operation or could not save it
+    Failed(String),
+    Unauthenticated(String),
+    Denied(String),
+}
+
+impl Failure {
+    pub fn exit_code(&self) -> ExitCode {
+        ExitCode::from(match self {
+            Failure::Failed(_) => 1,
+            Failure::Unauthenticated(_) => 3,
+            Failure::Denied(_) => 4,
+        })
+    }
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Failure::Failed(message)
+            | Failure::Unauthenticated(message)
+            | Failure::Denied(message) => f.write_str(message),
+        }
+    }
+}
+
+/// Runs `command`, printing its output or the reason it failed, and returns
+/// the exit code.
+pub async fn run<S: Storage>(
+    store: &mut Store<S>,
+    auth_manager: &AuthManager,
+    credentials: &Credentials,
+    format: Option<OutputFormat>,
+    command: Command,
+) -> ExitCode {
+    match dispatch(store, auth_manager, credentials, format, command).await {
+        Ok(output) => {
+            print!("{}", output);
+            ExitCode::SUCCESS
+        }
+        Err(failure) => {
+            eprintln!("{}", failure);
+            failure.exit_code()
+        }
+    }
+}
+
+/// Logs in, checks the role and runs `command`, returning what to print.
+/// Listings and reports come out in `format`, or their usual layout.
+pub async fn dispatch<S: Storage>(
+    store: &mut Store<S>,
+    auth_manager: &AuthManager,
+    credentials: &Credentials,
+    format: Option<OutputFormat>,
+    command: Command,
+) -> Result<String, Failure> {
+    let session = Session::new(login(auth_manager, credentials).await?);
+    let (permission, action) = command.permission();
+    if !session.role().can(permission) {
+        return Err(Failure::Denied(format!(
+            "Access denied: a {} cannot {}.",
+            session.role(),
+            action
+        )));
+    }
+
+    let snapshot = Snapshot::take(&store.inventory, &store.transaction_manager);
+    let mut changes = Vec::new();
+    let changes_store = command.changes_store();
+
+    let output = match command {
+        Command::Product(command) => product(store, command, format),
+        Command::Sale(SaleCommand::Record {
+            sku,
+            quantity,
+            price,
+            payment,
+        }) => record_sale(store, &session, &sku, quantity, price, payment),
+        Command::Purchase(PurchaseCommand::Record {
+            sku,
+            quantity,
+            price,
+        }) => store
+            .transaction_manager
+            .record_purchase(&session, &mut store.inventory, &sku, quantity, price)
+            .map(|_| recorded(store)),
+        Command::Transaction(TransactionCommand::List) => {
+            let section =
+                transaction::transactions_section(&store.transaction_manager, &store.inventory);
+            Ok(output::render(
+                format.unwrap_or(OutputFormat::Table),
+                &[section],
+            ))
+        }
+        Command::Report {
+            report,
+            period,
+            from,
+            to,
+        } => match period {
+            Some(preset) => Ok(preset.period()),
+            None => Period::new(from, to),
+        }
+        .map(|period| {
+            reporting::generate(
+                report,
+                format,
+                &store.transaction_manager,
+                &store.inventory,
+                period,
+            )
+        }),
+        Command::User(UserCommand::Create {
+            name,
+            role,
+            new_password,
+        }) => auth_manager
+            .create_user(&name, &new_password, role)
+            .await
+            .map(|_| {
+                changes.push(Change {
+                    action: AuditAction::UserChanged,
+                    subject: name.clone(),
+                    details: format!("created as {}", role),
+                    before: None,
+                    after: Some(json!({ "role": role.to_string() })),
+                });
+                format!("{} created as a {}.\n", name, role)
+            }),
+        Command::CloseDay { counted_cash, day } => closing::close_day(
+            &mut store.transaction_manager,
+            &session,
+            day.unwrap_or_else(|| Local::now().date_naive()),
+            counted_cash,
+        )
+        .map(|report| {
+            let format = format.unwrap_or_else(|| report.usual_format());
+            reporting::render(&[&report], format)
+        }),
+    }
+    .map_err(Failure::Failed)?;
+
+    let logged = store.audit_log.entries.len();
+    store.log_changes(&session, &snapshot, changes);
+    if changes_store || store.audit_log.entries.len() > logged {
+        store.try_save().await.map_err(Failure::Failed)?;
+    }
+    Ok(output)
+}
+
+/// Checks the password, and the one-time code for accounts with two-factor
+/// login.
+async fn login(auth_manager: &AuthManager, credentials: &Credentials) -> Result<User, Failure> {
+    let (Some(username), Some(password)) = (&credentials.username, &credentials.password) else {
+        return Err(Failure::Unauthenticated(
+            "Log in with --username and --password, or set RUSTY_STORE_USERNAME and RUSTY_STORE_PASSWORD.".to_string(),
+        ));
+    };
+    let user = auth_manager
+        .authenticate(username, password)
+        .await
+        .map_err(Failure::Unauthenticated)?;
+    if auth_manager
+        .has_totp(&user.username)
+        .await
+        .map_err(Failure::Failed)?
+    {
+        let Some(code) = &credentials.totp_code else {
+            return Err(Failure::Unauthenticated(format!(
+                "Account '{}' uses two-factor login: pass --totp-code or set RUSTY_STORE_TOTP_CODE.",
+                username
+            )));
+        };
+        auth_manager
+            .verify_totp(&user.username, code)
+            .await
+            .map_err(Failure::Unauthenticated)?;
+    }
+    Ok(user)
+}
+
+fn product<S: Storage>(
+    store: &mut Store<S>,
+    command: ProductCommand,
+    format: Option<OutputFormat>,
+) -> Result<String, String> {
+    let inventory = &mut store.inventory;
+    match command {
+        ProductCommand::Add {
+            sku,
+            name,
+            description,
+            price,
+            cost,
+            quantity,
+        } => {
+            inventory.add_product(Product {
+                sku: sku.clone(),
+                name,
+                description,
+                price,
+                cost,
+                quantity,
+                layers: Vec::new(),
+            })?;
+            Ok(format!("Product {} added.\n", sku))
+        }
+        ProductCommand::Edit {
+            sku,
+            name,
+            description,
+            price,
+            cost,
+            quantity,
+        } => {
+            let renamed = name.is_some();
+            if let Some(name) = name {
+                inventory.rename_product(&sku, &name)?;
+            }
+            if !renamed
+                || description.is_some()
+                || price.is_some()
+                || cost.is_some()
+                || quantity.is_some()
+            {
+                inventory.edit_product(&sku, description, price, cost, quantity)?;
+            }
+            Ok(format!("Product {} edited.\n", sku))
+        }
+        ProductCommand::Delete { sku } => {
+            inventory.delete_product(&sku)?;
+            Ok(format!("Product {} deleted.\n", sku))
+        }
+        ProductCommand::List => Ok(output::render(
+            format.unwrap_or(OutputFormat::Table),
+            &[inventory::products_section(inventory)],
+        )),
+    }
+}
+
+fn record_sale<S: Storage>(
+    store: &mut Store<S>,
+    session: &Session,
+    sku: &str,
+    quantity: u32,
+    price: Option<Money>,
+    payment_method: PaymentMethod,
+) -> Result<String, String> {
+    let price = match price {
+        Some(price) => price,
+        None => {
+            store
+                .inventory
+                .products
+                .get(sku)
+                .ok_or_else(|| format!("Product '{}' not found.", sku))?
+                .price
+        }
+    };
+    let line = OrderLine {
+        sku: sku.to_string(),
+        quantity,
+        price,
+    };
+    store.transaction_manager.record_order(
+        session,
+        &mut store.inventory,
+        &[line],
+        payment_method,
+    )?;
+    Ok(recorded(store))
+}
+
+/// Describes the transaction just recorded.
+fn recorded<S: Storage>(store: &Store<S>) -> String {
+    match store.transaction_manager.transactions.last() {
+        Some(transaction) => format!(
+            "Recorded {} #{}: {} x {} at ${}, total ${}.\n",
+            transaction.transaction_type.to_string().to_lowercase(),
+            transaction.id,
+            transaction.quantity,
+            transaction.sku,
+            transaction.price,
+            transaction.total
+        ),
+        None => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::MemoryStorage;
+
+    fn credentials(username: &str) -> Credentials {
+        Credentials {
+            username: Some(username.to_string()),
+            password: Some("Secret-123".to_string()),
+            totp_code: None,
+        }
+    }
+
+    fn parse(args: &[&str]) -> Command {
+        let cli = Cli::try_parse_from([&["rusty_store"], args].concat()).unwrap();
+        cli.command.unwrap()
+    }
+
+    #[test]
+    fn parses_subcommands_with_global_credentials() {
+        let cli = Cli::try_parse_from([
+            "rusty_store",
+            "sale",
+            "record",
+            "--sku",
+            "WID-001",
+            "--quantity",
+            "2",
+            "--username",
+            "till",
+        ])
+        .unwrap();
+        assert_eq!(cli.credentials.username.as_deref(), Some("till"));
+        assert!(matches!(
+            cli.command,
+            Some(Command::Sale(SaleCommand::Record {
+                quantity: 2,
+                price: None,
+                payment: PaymentMethod::Cash,
+                ..
+            }))
+        ));
+
+        assert!(matches!(
+            parse(&["report", "sales", "--period", "last-month"]),
+            Command::Report {
+                period: Some(Preset::LastMonth),
+                ..
+            }
+        ));
+        assert!(Cli::try_parse_from([
+            "rusty_store",
+            "report",
+            "sales",
+            "--period",
+            "today",
+            "--from",
+            "2025-01-01"
+        ])
+        .is_err());
+        let error = Cli::try_parse_from(["rusty_store", "report", "weekly"]).unwrap_err();
+        assert_eq!(error.exit_code(), 2);
+        assert!(Cli::try_parse_from(["rusty_store", "purchase", "record", "--sku", "X"]).is_err());
+    }
+
+    #[tokio::test]
+    async fn runs_commands_for_roles_allowed_to() {
+        let auth_manager = AuthManager::new("sqlite::memory:").await;
+        auth_manager.initialize().await;
+        auth_manager
+            .create_user("boss", "Secret-123", Role::Manager)
+            .await
+            .unwrap();
+        auth_manager
+            .create_user("till", "Secret-123", Role::Cashier)
+            .await
+            .unwrap();
+        let mut store = Store::new(MemoryStorage::new()).await.unwrap();
+        let add = || {
+            parse(&[
+                "product",
+                "add",
+                "--sku",
+                "WID-001",
+                "--name",
+                "Widget",
+                "--price",
+                "10",
+                "--quantity",
+                "5",
+            ])
+        };
+
+        let result = dispatch(
+            &mut store,
+            &auth_manager,
+            &Credentials::default(),
+            None,
+            add(),
+        )
+        .await;
+        assert!(matches!(result, Err(Failure::Unauthenticated(_))));
+        let result = dispatch(&mut store, &auth_manager, &credentials("till"), None, add()).await;
+        assert_eq!(
+            result,
+            Err(Failure::Denied(
+                "Access denied: a cashier cannot add products.".to_string()
+            ))
+        );
+
+        dispatch(&mut store, &auth_manager, &credentials("boss"), None, add())
+            .await
+            .unwrap();
+        let sale = parse(&["sale", "record", "--sku", "WID-001", "--quantity", "2"]);
+        let output = dispatch(&mut store, &auth_manager, &credentials("till"), None, sale).await;
+        assert_eq!(
+            output.unwrap(),
+            "Recorded sale #1: 2 x WID-001 at $10.00, total $20.00.\n"
+        );
+        let sale = parse(&["sale", "record", "--sku", "WID-001", "--quantity", "9"]);
+        let output = dispatch(&mut store, &auth_manager, &credentials("till"), None, sale).await;
+        assert!(matches!(output, Err(Failure::Failed(_))));
+
+        let report = parse(&["report", "sales", "--from", "2000-01-01"]);
+        let output = dispatch(
+            &mut store,
+            &auth_manager,
+            &credentials("boss"),
+            None,
+            report,
+        )
+        .await;
+        assert!(output.unwrap().contains("Total Sales: $20.00"));
+        let list = parse(&["product", "list"]);
+        let output = dispatch(
+            &mut store,
+            &auth_manager,
+            &credentials("till"),
+            Some(OutputFormat::Csv),
+            list,
+        )
+        .await;
+        assert_eq!(
+            output.unwrap(),
+            "SKU,Name,Description,Price,Cost,Quantity\nWID-001,Widget,,10.00,0.00,3\n"
+        );
+
+        let close = || parse(&["close-day", "--counted-cash", "15"]);
+        let output = dispatch(
+            &mut store,
+            &auth_manager,
+            &credentials("till"),
+            None,
+            close(),
+        )
+        .await;
+        assert!(matches!(output, Err(Failure::Denied(_))));
+        let output = dispatch(
+            &mut store,
+            &auth_manager,
+            &credentials("boss"),
+            None,
+            close(),
+        )
+        .await;
+        assert!(output.unwrap().contains("Over/Short: -$5.00"));
+        let sale = parse(&["sale", "record", "--sku", "WID-001", "--quantity", "1"]);
+        let output = dispatch(&mut store, &auth_manager, &credentials("till"), None, sale).await;
+        assert!(matches!(output, Err(Failure::Failed(_))));
+
+        let usernames: Vec<&str> = store
+            .audit_log
+            .entries
+            .iter()
+            .map(|entry| entry.username.as_str())
+            .collect();
+        assert_eq!(usernames, vec!["boss", "till", "boss"]);
+        assert_eq!(
+            store.audit_log.entries[2].details,
+            "counted 15.00, expected 20.00, over/short -5.00"
+        );
+    }
+}
@@ -0,0 +1,541 @@
+//! Closing the till at the end of the business day: the Z-report totals the
+//! day, the closing user's cash count is reconciled against it, and the day is
+//! locked so nothing recorded on it can change afterwards.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use chrono::{DateTime, Local, NaiveDate, Utc};
+use serde::{Deserialize, Serialize, Serializer};
+use serde_json::{json, Value};
+
+use crate::money::Money;
+use crate::output::{OutputFormat, Section};
+use crate::reporting::{self, decimal, signed, Rendered};
+use crate::session::Session;
+use crate::transaction::{self, PaymentMethod, Transaction, TransactionManager, TransactionType};
+use crate::util;
+
+/// A business day that has been closed, with the cash count it was closed on.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DayClosing {
+    /// The local calendar day that was closed
+    pub day: NaiveDate,
+    pub closed_at: DateTime<Utc>,
+    pub closed_by: String,
+    /// Cash sales less cash refunds, as recorded
+    pub expected_cash: Money,
+    /// What the closing user counted in the drawer
+    pub counted_cash: Money,
+    /// Counted less expected: positive when the drawer is over
+    pub over_short: Money,
+}
+
+/// The end-of-day totals for one business day.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ZReport {
+    pub generated_at: DateTime<Utc>,
+    pub day: NaiveDate,
+    pub orders: usize,
+    #[serde(serialize_with = "decimal")]
+    pub total_sales: Money,
+    pub refunds: usize,
+    #[serde(serialize_with = "decimal")]
+    pub total_refunds: Money,
+    /// Voids recorded on the day, of any kind of transaction
+    pub voids: usize,
+    #[serde(serialize_with = "decimal")]
+    pub total_voided: Money,
+    #[serde(serialize_with = "decimal")]
+    pub net_sales: Money,
+    pub payments: Vec<PaymentLine>,
+    #[serde(serialize_with = "decimal")]
+    pub expected_cash: Money,
+    /// How the day was closed, none while it is still open
+    #[serde(serialize_with = "closing_decimals")]
+    pub closing: Option<DayClosing>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PaymentLine {
+    /// How the customers paid, or `(unknown)` for sales from before payments
+    /// were tracked
+    pub method: String,
+    #[serde(serialize_with = "decimal")]
+    pub sales: Money,
+    #[serde(serialize_with = "decimal")]
+    pub refunds: Money,
+    #[serde(serialize_with = "decimal")]
+    pub net: Money,
+}
+
+/// Writes the closing's amounts as decimals, like the rest of the report,
+/// rather than the cents it is stored in.
+fn closing_decimals<S: Serializer>(
+    closing: &Option<DayClosing>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    closing
+        .as_ref()
+        .map(|closing| {
+            json!({
+                "day": closing.day,
+                "closed_at": closing.closed_at,
+                "closed_by": closing.closed_by,
+                "expected_cash": closing.expected_cash.to_string(),
+                "counted_cash": closing.counted_cash.to_string(),
+                "over_short": closing.over_short.to_string(),
+            })
+        })
+        .serialize(serializer)
+}
+
+/// The Z-report for the local day `day`. Voided sales and refunds are left
+/// out, and the voids of sales and refunds recorded that day are counted on
+/// their own. Voided purchases are supplier business and stay off it.
+pub fn z_report(transactions: &TransactionManager, day: NaiveDate) -> ZReport {
+    #[derive(Default)]
+    struct Totals {
+        sales: Money,
+        refunds: Money,
+    }
+
+    let on_day = transactions.between(Some(day), Some(day));
+    let sales = on_day.sales();
+    let returns = on_day.returns();
+    let voids: Vec<_> = on_day
+        .transactions
+        .iter()
+        .filter(|transaction| {
+            transaction.transaction_type == TransactionType::Void
+                && transaction::business_day(transaction.recorded_at) == day
+                && transaction
+                    .reference_id
+                    .and_then(|id| transactions.find(id))
+                    .is_some_and(|original| {
+                        matches!(
+                            original.transaction_type,
+                            TransactionType::Sale | TransactionType::Return
+                        )
+                    })
+        })
+        .collect();
+
+    let mut payments: BTreeMap<String, Totals> = BTreeMap::new();
+    let method = |transaction: &Transaction| {
+        transaction
+            .payment_method
+            .map_or_else(|| "(unknown)".to_string(), |method| method.to_string())
+    };
+    for sale in &sales {
+        payments.entry(method(sale)).or_default().sales += sale.total;
+    }
+    for refund in &returns {
+        payments.entry(method(refund)).or_default().refunds += refund.total;
+    }
+    let payments: Vec<PaymentLine> = payments
+        .into_iter()
+        .map(|(method, totals)| PaymentLine {
+            method,
+            sales: totals.sales,
+            refunds: totals.refunds,
+            net: totals.sales - totals.refunds,
+        })
+        .collect();
+
+    let orders: BTreeSet<u64> = sales.iter().filter_map(|sale| sale.order_id).collect();
+    let total_sales: Money = sales.iter().map(|sale| sale.total).sum();
+    let total_refunds: Money = returns.iter().map(|refund| refund.total).sum();
+    ZReport {
+        generated_at: Utc::now(),
+        day,
+        orders: orders.len(),
+        total_sales,
+        refunds: returns.len(),
+        total_refunds,
+        voids: voids.len(),
+        total_voided: voids.iter().map(|void| void.total).sum(),
+        net_sales: total_sales - total_refunds,
+        expected_cash: cash_taken(transactions, day),
+        payments,
+        closing: transactions.closing(day).cloned(),
+    }
+}
+
+/// The cash that went into the till on the local day `day`, less what was
+/// paid out. A sale voided later still took its cash that day, while the void
+/// pays it back on the day it is recorded, whenever the sale was.
+fn cash_taken(transactions: &TransactionManager, day: NaiveDate) -> Money {
+    transactions
+        .transactions
+        .iter()
+        .filter(|transaction| {
+            transaction.payment_method == Some(PaymentMethod::Cash)
+                && transaction::business_day(transaction.recorded_at) == day
+        })
+        .map(|transaction| {
+            // A void pays in what the return it cancels paid out, and the reverse
+            let paid_in = if transaction.transaction_type == TransactionType::Void {
+                transaction
+                    .reference_id
+                    .and_then(|id| transactions.find(id))
+                    .is_some_and(|original| original.transaction_type == TransactionType::Return)
+            } else {
+                transaction.transaction_type == TransactionType::Sale
+            };
+            if paid_in {
+                transaction.total
+            } else {
+                -transaction.total
+            }
+        })
+        .sum()
+}
+
+/// Closes the local day `day` on `counted_cash`, recording the over/short
+/// against the cash the Z-report expects, and returns the final report. Once
+/// closed, nothing can be recorded on the day or voided from it.
+pub fn close_day(
+    transactions: &mut TransactionManager,
+    session: &Session,
+    day: NaiveDate,
+    counted_cash: Money,
+) -> Result<ZReport, String> {
+    if let Some(closing) = transactions.closing(day) {
+        return Err(format!(
+            "The business day {} was already closed by {}.",
+            day, closing.closed_by
+        ));
+    }
+    if day > Local::now().date_naive() {
+        return Err(format!("The business day {} has not started yet.", day));
+    }
+    if counted_cash.is_negative() {
+        return Err("The counted cash cannot be negative.".to_string());
+    }
+
+    let mut report = z_report(transactions, day);
+    let closing = DayClosing {
+        day,
+        closed_at: Utc::now(),
+        closed_by: session.username().to_string(),
+        expected_cash: report.expected_cash,
+        counted_cash,
+        over_short: counted_cash - report.expected_cash,
+    };
+    transactions.closings.push(closing.clone());
+    report.closing = Some(closing);
+    Ok(report)
+}
+
+impl Rendered for ZReport {
+    fn sections(&self) -> Vec<Section> {
+        let totals = Section::new(&format!("Z-Report for {}", self.day), &[], Vec::new())
+            .with_total("Orders", self.orders)
+            .with_total("Total Sales", format!("${}", self.total_sales))
+            .with_total("Refunds", self.refunds)
+            .with_total("Total Refunds", signed(-self.total_refunds))
+            .with_total("Voids", self.voids)
+            .with_total("Total Voided", format!("${}", self.total_voided))
+            .with_total("Net Sales", signed(self.net_sales));
+
+        let rows = self
+            .payments
+            .iter()
+            .map(|line| {
+                vec![
+                    line.method.clone(),
+                    format!("${}", line.sales),
+                    signed(-line.refunds),
+                    signed(line.net),
+                ]
+            })
+            .collect();
+        let payments = Section::new("Payments", &["Method", "Sales", "Refunds", "Net"], rows);
+
+        let mut drawer = Section::new("Cash Drawer", &[], Vec::new())
+            .with_total("Expected Cash", signed(self.expected_cash));
+        drawer = match &self.closing {
+            Some(closing) => drawer
+                .with_total("Counted Cash", format!("${}", closing.counted_cash))
+                .with_total("Over/Short", signed(closing.over_short))
+                .with_total("Closed By", &closing.closed_by)
+                .with_total(
+                    "Closed At",
+                    transaction::format_timestamp(&closing.closed_at),
+                ),
+            None => drawer.with_total("Status", "Open"),
+        };
+
+        vec![totals, payments, drawer]
+    }
+
+    fn to_json(&self) -> Value {
+        serde_json::to_value(self).unwrap_or_default()
+    }
+
+    fn usual_format(&self) -> OutputFormat {
+        OutputFormat::Table
+    }
+}
+
+/// Handles closing a business day. A day that is already closed just shows
+/// its Z-report again.
+///
+/// # Arguments
+///
+/// * transaction_manager - A mutable instance of `TransactionManager`
+/// * session - The logged-in user the closing is recorded under
+///
+pub fn handle_close_day(transaction_manager: &mut TransactionManager, session: &Session) {
+    println!("\n--- Close Day ---");
+
+    let day =
+        util::get_date("Enter the business day to close (YYYY-MM-DD, leave blank for today): ")
+            .unwrap_or_else(|| Local::now().date_naive());
+    let report = z_report(transaction_manager, day);
+    println!("{}", reporting::render(&[&report], OutputFormat::Table));
+    if report.closing.is_some() {
+        println!("The business day {} is already closed.", day);
+        return;
+    }
+
+    let counted_cash =
+        match util::get_user_input("Enter the cash counted in the drawer: ").parse::<Money>() {
+            Ok(counted_cash) => counted_cash,
+            Err(_) => {
+                println!("Invalid amount. The day was not closed.");
+                return;
+            }
+        };
+    if !util::get_user_input(&format!(
+        "Close {}? Nothing more can be recorded on it or voided from it. (y/N): ",
+        day
+    ))
+    .eq_ignore_ascii_case("y")
+    {
+        println!("Closing cancelled.");
+        return;
+    }
+
+    match close_day(transaction_manager, session, day, counted_cash) {
+        Ok(report) => {
+            println!("{}", reporting::render(&[&report], OutputFormat::Table));
+            println!("The business day {} is closed.", day);
+        }
+        Err(e) => println!("Error closing the day: {}", e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::User;
+    use crate::inventory::{Inventory, Product};
+    use crate::permission::Role;
+    use crate::transaction::OrderLine;
+    use chrono::Duration;
+
+    fn session() -> Session {
+        Session::new(User {
+            username: "boss".to_string(),
+            role: Role::Manager,
+        })
+    }
+
+    fn stocked() -> Inventory {
+        let mut inventory = Inventory::new();
+        inventory.products.insert(
+            "WID-001".to_string(),
+            Product {
+                sku: "WID-001".to_string(),
+                name: "Widget".to_string(),
+                description: "A test widget".to_string(),
+                price: Money::from_cents(1000),
+                cost: Money::from_cents(600),
+                quantity: 20,
+                layers: Vec::new(),
+            },
+        );
+        inventory
+    }
+
+    fn sell(
+        transactions: &mut TransactionManager,
+        inventory: &mut Inventory,
+        quantity: u32,
+        payment_method: PaymentMethod,
+    ) -> u64 {
+        let line = OrderLine {
+            sku: "WID-001".to_string(),
+            quantity,
+            price: Money::from_cents(1000),
+        };
+        transactions
+            .record_order(&session(), inventory, &[line], payment_method)
+            .unwrap();
+        transactions.transactions.last().unwrap().id
+    }
+
+    #[test]
+    fn totals_the_day_by_payment_method() {
+        let mut inventory = stocked();
+        let mut transactions = TransactionManager::new();
+        let cash = sell(&mut transactions, &mut inventory, 3, PaymentMethod::Cash);
+        sell(&mut transactions, &mut inventory, 2, PaymentMethod::Card);
+        let voided = sell(&mut transactions, &mut inventory, 4, PaymentMethod::Cash);
+        transactions
+            .record_return(&session(), &mut inventory, cash, 1, false)
+            .unwrap();
+        transactions
+            .void_transaction(&session(), &mut inventory, voided)
+            .unwrap();
+
+        let today = Local::now().date_naive();
+        let report = z_report(&transactions, today);
+
+        assert_eq!(report.orders, 2);
+        assert_eq!(report.total_sales, Money::from_cents(5000));
+        assert_eq!(
+            (report.refunds, report.total_refunds),
+            (1, Money::from_cents(1000))
+        );
+        assert_eq!(
+            (report.voids, report.total_voided),
+            (1, Money::from_cents(4000))
+        );
+        assert_eq!(report.net_sales, Money::from_cents(4000));
+        assert_eq!(
+            report.payments,
+            vec![
+                PaymentLine {
+                    method: "Card".to_string(),
+                    sales: Money::from_cents(2000),
+                    refunds: Money::ZERO,
+                    net: Money::from_cents(2000),
+                },
+                PaymentLine {
+                    method: "Cash".to_string(),
+                    sales: Money::from_cents(3000),
+                    refunds: Money::from_cents(1000),
+                    net: Money::from_cents(2000),
+                },
+            ]
+        );
+        assert_eq!(report.expected_cash, Money::from_cents(2000));
+        assert!(report.closing.is_none());
+
+        let closed = close_day(
+            &mut transactions,
+            &session(),
+            today,
+            Money::from_cents(1950),
+        )
+        .unwrap()
+        .closing
+        .unwrap();
+        assert_eq!(closed.over_short, Money::from_cents(-50));
+        assert_eq!(closed.closed_by, "boss");
+        let report = z_report(&transactions, today);
+        assert_eq!(report.to_json()["closing"]["over_short"], "-0.50");
+        assert_eq!(report.closing, Some(closed));
+    }
+
+    #[test]
+    fn leaves_voided_purchases_off_the_report() {
+        let mut inventory = stocked();
+        let mut transactions = TransactionManager::new();
+        transactions
+            .record_purchase(
+                &session(),
+                &mut inventory,
+                "WID-001",
+                5,
+                Money::from_cents(600),
+            )
+            .unwrap();
+        transactions
+            .void_transaction(&session(), &mut inventory, 1)
+            .unwrap();
+        let sale = sell(&mut transactions, &mut inventory, 1, PaymentMethod::Card);
+        transactions
+            .void_transaction(&session(), &mut inventory, sale)
+            .unwrap();
+
+        let report = z_report(&transactions, Local::now().date_naive());
+        assert_eq!(
+            (report.voids, report.total_voided),
+            (1, Money::from_cents(1000))
+        );
+    }
+
+    #[test]
+    fn pays_out_voided_cash_sales_on_the_day_of_the_void() {
+        let mut inventory = stocked();
+        let mut transactions = TransactionManager::new();
+        let sale = sell(&mut transactions, &mut inventory, 2, PaymentMethod::Cash);
+        transactions.transactions[0].recorded_at -= Duration::days(1);
+        transactions
+            .void_transaction(&session(), &mut inventory, sale)
+            .unwrap();
+
+        let today = Local::now().date_naive();
+        let yesterday = today.pred_opt().unwrap();
+        assert_eq!(
+            z_report(&transactions, yesterday).expected_cash,
+            Money::from_cents(2000)
+        );
+        let report = z_report(&transactions, today);
+        assert_eq!(report.total_sales, Money::ZERO);
+        assert_eq!(report.expected_cash, Money::from_cents(-2000));
+    }
+
+    #[test]
+    fn locks_a_closed_day() {
+        let mut inventory = stocked();
+        let mut transactions = TransactionManager::new();
+        let sale = sell(&mut transactions, &mut inventory, 1, PaymentMethod::Cash);
+        let today = Local::now().date_naive();
+        close_day(
+            &mut transactions,
+            &session(),
+            today,
+            Money::from_cents(1000),
+        )
+        .unwrap();
+
+        assert_eq!(
+            transactions
+                .record_sale(
+                    &session(),
+                    &mut inventory,
+                    "WID-001",
+                    1,
+                    Money::from_cents(1000),
+                    PaymentMethod::Cash
+                )
+                .unwrap_err(),
+            format!(
+                "The business day {} has been closed, so it cannot be changed.",
+                today
+            )
+        );
+        assert!(transactions
+            .void_transaction(&session(), &mut inventory, sale)
+            .is_err());
+        assert!(transactions
+            .record_return(&session(), &mut inventory, sale, 1, false)
+            .is_err());
+        assert_eq!(
+            close_day(&mut transactions, &session(), today, Money::ZERO).unwrap_err(),
+            format!("The business day {} was already closed by boss.", today)
+        );
+        assert!(close_day(
+            &mut transactions,
+            &session(),
+            today.succ_opt().unwrap(),
+            Money::ZERO
+        )
+        .is_err());
+        assert_eq!(inventory.products["WID-001"].quantity, 19);
+    }
+}
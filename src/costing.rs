@@ -0,0 +1,278 @@
+//! Inventory costing: how the cost of stock on hand is tracked and which units
+//! a sale is taken from.
+//!
+//! Every receipt of stock becomes a cost layer, oldest first. FIFO sells from
+//! the oldest layer and LIFO from the newest. The moving weighted average
+//! keeps a single layer whose unit cost is re-averaged on every receipt.
+
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use crate::money::Money;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CostingMethod {
+    /// First in, first out
+    #[default]
+    Fifo,
+    /// Last in, first out
+    Lifo,
+    /// Moving weighted average
+    WeightedAverage,
+}
+
+impl fmt::Display for CostingMethod {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(match self {
+            CostingMethod::Fifo => "FIFO",
+            CostingMethod::Lifo => "LIFO",
+            CostingMethod::WeightedAverage => "Weighted Average",
+        })
+    }
+}
+
+impl FromStr for CostingMethod {
+    type Err = String;
+
+    /// Parses `fifo`, `lifo` or `average`, ignoring case. The names printed
+    /// by `Display` are accepted too.
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        match input.trim().to_ascii_lowercase().as_str() {
+            "fifo" => Ok(CostingMethod::Fifo),
+            "lifo" => Ok(CostingMethod::Lifo),
+            "average" | "weighted average" | "weighted-average" | "wavg" => {
+                Ok(CostingMethod::WeightedAverage)
+            }
+            _ => Err(format!("'{}' is not a costing method", input.trim())),
+        }
+    }
+}
+
+/// Units received together at the same unit cost.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct CostLayer {
+    pub quantity: u32,
+    pub unit_cost: Money,
+}
+
+/// Adds `quantity` units at `unit_cost`. Under the weighted average the
+/// layers collapse into one at the new average cost.
+pub fn receive(
+    layers: &mut Vec<CostLayer>,
+    method: CostingMethod,
+    quantity: u32,
+    unit_cost: Money,
+) {
+    if quantity == 0 {
+        return;
+    }
+    layers.push(CostLayer {
+        quantity,
+        unit_cost,
+    });
+    if method == CostingMethod::WeightedAverage {
+        average(layers);
+    }
+}
+
+/// Takes `quantity` units out of the layers and returns what they cost.
+/// Units beyond what the layers hold are costed at nothing, so callers check
+/// the stock first.
+pub fn issue(layers: &mut Vec<CostLayer>, method: CostingMethod, quantity: u32) -> Money {
+    let mut remaining = quantity;
+    let mut cost = Money::ZERO;
+    while remaining > 0 {
+        let index = match method {
+            CostingMethod::Lifo => layers.len().checked_sub(1),
+            CostingMethod::Fifo | CostingMethod::WeightedAverage => {
+                (!layers.is_empty()).then_some(0)
+            }
+        };
+        let Some(index) = index else { break };
+
+        let layer = &mut layers[index];
+        let taken = remaining.min(layer.quantity);
+        cost += layer.unit_cost * taken;
+        layer.quantity -= taken;
+        remaining -= taken;
+        if layer.quantity == 0 {
+            layers.remove(index);
+        }
+    }
+    cost
+}
+
+/// Takes back `quantity` units that were received at `unit_cost`, such as a
+/// voided purchase, from the newest layers at that cost whatever the method.
+/// Under the weighted average their value comes out of the blended layer.
+/// Returns how many units no layer at that cost still held.
+pub fn remove(
+    layers: &mut Vec<CostLayer>,
+    method: CostingMethod,
+    quantity: u32,
+    unit_cost: Money,
+) -> u32 {
+    if method == CostingMethod::WeightedAverage {
+        let held: u32 = layers.iter().map(|layer| layer.quantity).sum();
+        let taken = quantity.min(held);
+        let left = value(layers) - unit_cost * taken;
+        *layers = vec![CostLayer {
+            quantity: held - taken,
+            unit_cost: left.max(Money::ZERO).div_round((held - taken).max(1)),
+        }];
+        layers.retain(|layer| layer.quantity > 0);
+        return quantity - taken;
+    }
+
+    let mut remaining = quantity;
+    for index in (0..layers.len()).rev() {
+        if remaining == 0 {
+            break;
+        }
+        if layers[index].unit_cost != unit_cost {
+            continue;
+        }
+        let taken = remaining.min(layers[index].quantity);
+        layers[index].quantity -= taken;
+        remaining -= taken;
+        if layers[index].quantity == 0 {
+            layers.remove(index);
+        }
+    }
+    remaining
+}
+
+/// What the units in the layers cost.
+pub fn value(layers: &[CostLayer]) -> Money {
+    layers
+        .iter()
+        .map(|layer| layer.unit_cost * layer.quantity)
+        .sum()
+}
+
+/// Collapses the layers into one at their weighted average unit cost.
+pub fn average(layers: &mut Vec<CostLayer>) {
+    let quantity: u32 = layers.iter().map(|layer| layer.quantity).sum();
+    if quantity == 0 {
+        layers.clear();
+        return;
+    }
+    let unit_cost = value(layers).div_round(quantity);
+    *layers = vec![CostLayer {
+        quantity,
+        unit_cost,
+    }];
+}
+
+/// Makes the layers hold exactly `quantity` units. Stock the layers do not
+/// know about, such as units from before layers were kept or added by hand,
+/// becomes the oldest layer at `unit_cost`. Surplus comes off the newest.
+pub fn reconcile(layers: &mut Vec<CostLayer>, quantity: u32, unit_cost: Money) {
+    let held: u32 = layers.iter().map(|layer| layer.quantity).sum();
+    if held < quantity {
+        layers.insert(
+            0,
+            CostLayer {
+                quantity: quantity - held,
+                unit_cost,
+            },
+        );
+    } else if held > quantity {
+        issue(layers, CostingMethod::Lifo, held - quantity);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn layers() -> Vec<CostLayer> {
+        let mut layers = Vec::new();
+        receive(&mut layers, CostingMethod::Fifo, 10, Money::from_cents(100));
+        receive(&mut layers, CostingMethod::Fifo, 10, Money::from_cents(150));
+        layers
+    }
+
+    #[test]
+    fn fifo_sells_the_oldest_units_first() {
+        let mut layers = layers();
+        assert_eq!(
+            issue(&mut layers, CostingMethod::Fifo, 12),
+            Money::from_cents(1300)
+        );
+        assert_eq!(value(&layers), Money::from_cents(1200)); // 8 at 1.50
+    }
+
+    #[test]
+    fn lifo_sells_the_newest_units_first() {
+        let mut layers = layers();
+        assert_eq!(
+            issue(&mut layers, CostingMethod::Lifo, 12),
+            Money::from_cents(1700)
+        );
+        assert_eq!(value(&layers), Money::from_cents(800)); // 8 at 1.00
+    }
+
+    #[test]
+    fn weighted_average_blends_each_receipt() {
+        let mut layers = Vec::new();
+        let method = CostingMethod::WeightedAverage;
+        receive(&mut layers, method, 10, Money::from_cents(100));
+        receive(&mut layers, method, 10, Money::from_cents(150));
+        assert_eq!(layers.len(), 1);
+        assert_eq!(layers[0].unit_cost, Money::from_cents(125));
+
+        assert_eq!(issue(&mut layers, method, 12), Money::from_cents(1500));
+        receive(&mut layers, method, 2, Money::from_cents(200));
+        assert_eq!(layers[0].unit_cost, Money::from_cents(140)); // (8 * 1.25 + 2 * 2.00) / 10
+    }
+
+    #[test]
+    fn removes_the_units_a_receipt_added() {
+        for method in [CostingMethod::Fifo, CostingMethod::Lifo] {
+            let mut layers = layers();
+            receive(&mut layers, method, 5, Money::from_cents(100));
+            assert_eq!(remove(&mut layers, method, 5, Money::from_cents(100)), 0);
+            assert_eq!(layers, self::layers());
+            assert_eq!(remove(&mut layers, method, 12, Money::from_cents(150)), 2);
+            assert_eq!(value(&layers), Money::from_cents(1000)); // 10 at 1.00
+        }
+
+        let method = CostingMethod::WeightedAverage;
+        let mut layers = Vec::new();
+        receive(&mut layers, method, 10, Money::from_cents(100));
+        receive(&mut layers, method, 10, Money::from_cents(150));
+        assert_eq!(remove(&mut layers, method, 10, Money::from_cents(150)), 0);
+        assert_eq!(
+            layers,
+            [CostLayer {
+                quantity: 10,
+                unit_cost: Money::from_cents(100)
+            }]
+        );
+    }
+
+    #[test]
+    fn reconciles_with_the_stock_count() {
+        let mut layers = layers();
+        reconcile(&mut layers, 25, Money::from_cents(90));
+        assert_eq!(layers[0].quantity, 5);
+        assert_eq!(layers[0].unit_cost, Money::from_cents(90));
+
+        reconcile(&mut layers, 12, Money::from_cents(90));
+        assert_eq!(value(&layers), Money::from_cents(1150)); // 5 at 0.90 and 7 at 1.00
+    }
+
+    #[test]
+    fn parses_method_names() {
+        assert_eq!("FIFO".parse(), Ok(CostingMethod::Fifo));
+        assert_eq!("lifo".parse(), Ok(CostingMethod::Lifo));
+        assert_eq!(
+            "Weighted Average".parse(),
+            Ok(CostingMethod::WeightedAverage)
+        );
+        assert!("newest".parse::<CostingMethod>().is_err());
+    }
+}
@@ -1,52 +1,135 @@
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
-use std::{fs, io};
 
-use crate::util;
+use crate::costing::{self, CostLayer, CostingMethod};
+use crate::output::{self, OutputFormat, Section};
+use crate::{money::Money, util};
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Product {
+    /// Unique stock keeping unit. Transactions refer to products by SKU, so
+    /// the display name can change without breaking history.
+    pub sku: String,
     pub name: String,
     pub description: String,
-    pub price: f64,
+    /// List price a unit sells for
+    pub price: Money,
+    /// What one unit costs the store: the latest purchase price, or the
+    /// running average under the weighted average method. Stock that no cost
+    /// layer accounts for is valued at it.
+    pub cost: Money,
     pub quantity: u32,
+    /// The units on hand grouped by what they cost, oldest first.
+    pub layers: Vec<CostLayer>,
 }
 
-trait InventoryManagement {
+impl Product {
+    /// Adds `quantity` units bought or taken back at `unit_cost`. Under the
+    /// weighted average the unit cost becomes the new average.
+    pub fn receive(&mut self, quantity: u32, unit_cost: Money, method: CostingMethod) {
+        self.reconcile_layers();
+        costing::receive(&mut self.layers, method, quantity, unit_cost);
+        self.quantity += quantity;
+        if method == CostingMethod::WeightedAverage {
+            if let Some(layer) = self.layers.first() {
+                self.cost = layer.unit_cost;
+            }
+        }
+    }
+
+    /// Takes `quantity` units out of stock and returns what they cost under
+    /// `method`. The caller checks there is enough stock.
+    pub fn issue(&mut self, quantity: u32, method: CostingMethod) -> Money {
+        self.reconcile_layers();
+        self.quantity -= quantity;
+        costing::issue(&mut self.layers, method, quantity)
+    }
+
+    /// Takes back `quantity` units that came in at `unit_cost`, such as a
+    /// voided purchase, from the cost layer they made. Any the layers no
+    /// longer hold at that cost come out as `method` would issue them.
+    pub fn take_back(&mut self, quantity: u32, unit_cost: Money, method: CostingMethod) {
+        self.reconcile_layers();
+        self.quantity -= quantity;
+        let rest = costing::remove(&mut self.layers, method, quantity, unit_cost);
+        costing::issue(&mut self.layers, method, rest);
+        if method == CostingMethod::WeightedAverage {
+            if let Some(layer) = self.layers.first() {
+                self.cost = layer.unit_cost;
+            }
+        }
+    }
+
+    /// What the units on hand cost.
+    pub fn stock_value(&self) -> Money {
+        let mut layers = self.layers.clone();
+        costing::reconcile(&mut layers, self.quantity, self.cost);
+        costing::value(&layers)
+    }
+
+    /// Brings the cost layers in line with a quantity that changed without
+    /// them, such as stock from older versions or a manual edit.
+    fn reconcile_layers(&mut self) {
+        costing::reconcile(&mut self.layers, self.quantity, self.cost);
+    }
+}
+
+/// The product operations behind both the menu and the command line.
+pub trait InventoryManagement {
     fn add_product(&mut self, product: Product) -> Result<(), String>;
     fn edit_product(
         &mut self,
-        product_name: &str,
+        sku: &str,
         description: Option<String>,
-        price: Option<f64>,
+        price: Option<Money>,
+        cost: Option<Money>,
         quantity: Option<u32>,
     ) -> Result<(), String>;
-    fn delete_product(&mut self, product_name: &str) -> Result<(), String>;
+    fn rename_product(&mut self, sku: &str, new_name: &str) -> Result<(), String>;
+    fn delete_product(&mut self, sku: &str) -> Result<(), String>;
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Products keyed by SKU.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct Inventory {
     pub products: HashMap<String, Product>,
+    /// How sales are costed and the stock on hand is valued
+    #[serde(default)]
+    pub costing_method: CostingMethod,
 }
 
 impl InventoryManagement for Inventory {
-    fn add_product(&mut self, product: Product) -> Result<(), String> {
-        if product.price < 0.0 {
+    fn add_product(&mut self, mut product: Product) -> Result<(), String> {
+        if product.sku.trim().is_empty() {
+            return Err("SKU cannot be empty!".to_string());
+        }
+        if product.price.is_negative() {
             return Err("Price cannot be negative!".to_string());
         }
+        if product.cost.is_negative() {
+            return Err("Cost cannot be negative!".to_string());
+        }
+        if self.products.contains_key(&product.sku) {
+            return Err(format!(
+                "A product with SKU '{}' already exists.",
+                product.sku
+            ));
+        }
 
-        self.products.insert(product.name.clone(), product);
+        product.reconcile_layers();
+        self.products.insert(product.sku.clone(), product);
         Ok(())
     }
 
     fn edit_product(
         &mut self,
-        product_name: &str,
+        sku: &str,
         description: Option<String>,
-        price: Option<f64>,
+        price: Option<Money>,
+        cost: Option<Money>,
         quantity: Option<u32>,
     ) -> Result<(), String> {
-        if let Some(product) = self.products.get_mut(product_name) {
+        if let Some(product) = self.products.get_mut(sku) {
             let mut updated = false;
 
             if let Some(new_description) = description {
@@ -55,15 +138,24 @@ impl InventoryManagement for Inventory {
             }
 
             if let Some(new_price) = price {
-                if new_price < 0.0 {
+                if new_price.is_negative() {
                     return Err("Price cannot be negative!".to_string());
                 }
                 product.price = new_price;
                 updated = true;
             }
 
+            if let Some(new_cost) = cost {
+                if new_cost.is_negative() {
+                    return Err("Cost cannot be negative!".to_string());
+                }
+                product.cost = new_cost;
+                updated = true;
+            }
+
             if let Some(new_quantity) = quantity {
                 product.quantity = new_quantity;
+                product.reconcile_layers();
                 updated = true;
             }
 
@@ -73,13 +165,26 @@ impl InventoryManagement for Inventory {
 
             Ok(())
         } else {
-            Err(format!("Product '{}' not found.", product_name))
+            Err(format!("Product '{}' not found.", sku))
+        }
+    }
+
+    fn rename_product(&mut self, sku: &str, new_name: &str) -> Result<(), String> {
+        if new_name.trim().is_empty() {
+            return Err("Name cannot be empty!".to_string());
+        }
+        match self.products.get_mut(sku) {
+            Some(product) => {
+                product.name = new_name.to_string();
+                Ok(())
+            }
+            None => Err(format!("Product '{}' not found.", sku)),
         }
     }
 
-    fn delete_product(&mut self, product_name: &str) -> Result<(), String> {
-        if self.products.remove(product_name).is_none() {
-            return Err(format!("Product '{}' not found.", product_name));
+    fn delete_product(&mut self, sku: &str) -> Result<(), String> {
+        if self.products.remove(sku).is_none() {
+            return Err(format!("Product '{}' not found.", sku));
         }
         Ok(())
     }
@@ -89,19 +194,37 @@ impl Inventory {
     pub fn new() -> Self {
         Self {
             products: HashMap::new(),
+            costing_method: CostingMethod::default(),
         }
     }
 
-    pub fn save_to_file(&self, file_path: &str) -> io::Result<()> {
-        let json = serde_json::to_string_pretty(&self)?;
-        fs::write(file_path, json)?;
-        Ok(())
+    /// Switches how stock is costed from now on. Moving to the weighted
+    /// average blends each product's layers into one at their average cost.
+    pub fn set_costing_method(&mut self, method: CostingMethod) {
+        if method == CostingMethod::WeightedAverage {
+            for product in self.products.values_mut() {
+                product.reconcile_layers();
+                costing::average(&mut product.layers);
+                if let Some(layer) = product.layers.first() {
+                    product.cost = layer.unit_cost;
+                }
+            }
+        }
+        self.costing_method = method;
+    }
+
+    /// What all the stock on hand cost, under the current costing method.
+    pub fn valuation(&self) -> Money {
+        self.products.values().map(Product::stock_value).sum()
     }
 
-    pub fn load_from_file(file_path: &str) -> io::Result<Self> {
-        let json = fs::read_to_string(file_path)?;
-        let inventory = serde_json::from_str(&json)?;
-        Ok(inventory)
+    /// The current name of the product with `sku`, or the SKU itself once the
+    /// product has been deleted.
+    pub fn product_name<'a>(&'a self, sku: &'a str) -> &'a str {
+        self.products
+            .get(sku)
+            .map(|product| product.name.as_str())
+            .unwrap_or(sku)
     }
 }
 
@@ -109,13 +232,20 @@ impl Inventory {
 pub fn add_product(inventory: &mut Inventory) {
     println!("\n--- Product Details ---");
 
+    let sku = util::get_user_input("Enter product SKU: ");
     let name = util::get_user_input("Enter product name: ");
     let description = util::get_user_input("Enter product description: ");
-    let price: f64 = util::get_user_input("Enter product price: ")
+    let price: Money = util::get_user_input("Enter product price: ")
+        .parse()
+        .unwrap_or_else(|_| {
+            println!("Invalid price! Defaulting to 0.00.");
+            Money::ZERO
+        });
+    let cost: Money = util::get_user_input("Enter product unit cost: ")
         .parse()
         .unwrap_or_else(|_| {
-            println!("Invalid price! Defaulting to 0.0.");
-            0.0
+            println!("Invalid cost! Defaulting to 0.00.");
+            Money::ZERO
         });
     let quantity: u32 = util::get_user_input("Enter product quantity: ")
         .parse()
@@ -125,10 +255,13 @@ pub fn add_product(inventory: &mut Inventory) {
         });
 
     let product = Product {
+        sku,
         name,
         description,
         price,
+        cost,
         quantity,
+        layers: Vec::new(),
     };
 
     match inventory.add_product(product) {
@@ -139,7 +272,18 @@ pub fn add_product(inventory: &mut Inventory) {
 
 /// Public function to edit a product in the inventory
 pub fn edit_product(inventory: &mut Inventory) {
-    let product_name = util::get_user_input("Enter the name of the product to edit: ");
+    let sku = util::get_user_input("Enter the SKU of the product to edit: ");
+
+    let name = util::get_user_input("Enter new name (leave blank to skip): ");
+    if !name.is_empty() {
+        match inventory.rename_product(&sku, &name) {
+            Ok(_) => println!("Product renamed successfully!"),
+            Err(err) => {
+                println!("Error renaming product: {}", err);
+                return;
+            }
+        }
+    }
 
     let description = {
         let input = util::get_user_input("Enter new description (leave blank to skip): ");
@@ -155,7 +299,7 @@ pub fn edit_product(inventory: &mut Inventory) {
         if input.is_empty() {
             None
         } else {
-            match input.parse::<f64>() {
+            match input.parse::<Money>() {
                 Ok(value) => Some(value),
                 Err(_) => {
                     println!("Invalid price! Skipping.");
@@ -165,6 +309,21 @@ pub fn edit_product(inventory: &mut Inventory) {
         }
     };
 
+    let cost = {
+        let input = util::get_user_input("Enter new unit cost (leave blank to skip): ");
+        if input.is_empty() {
+            None
+        } else {
+            match input.parse::<Money>() {
+                Ok(value) => Some(value),
+                Err(_) => {
+                    println!("Invalid cost! Skipping.");
+                    None
+                }
+            }
+        }
+    };
+
     let quantity = {
         let input = util::get_user_input("Enter new quantity (leave blank to skip): ");
         if input.is_empty() {
@@ -180,16 +339,38 @@ pub fn edit_product(inventory: &mut Inventory) {
         }
     };
 
-    match inventory.edit_product(&product_name, description, price, quantity) {
+    if !name.is_empty()
+        && description.is_none()
+        && price.is_none()
+        && cost.is_none()
+        && quantity.is_none()
+    {
+        return;
+    }
+
+    match inventory.edit_product(&sku, description, price, cost, quantity) {
         Ok(_) => println!("Product edited successfully!"),
         Err(err) => println!("Error editing product: {}", err),
     }
 }
 
+/// Public function to choose how stock is costed and valued
+pub fn set_costing_method(inventory: &mut Inventory) {
+    println!("\nCurrent costing method: {}", inventory.costing_method);
+    let input = util::get_user_input("Enter costing method (fifo, lifo, average): ");
+    match input.parse::<CostingMethod>() {
+        Ok(method) => {
+            inventory.set_costing_method(method);
+            println!("Costing method set to {}.", method);
+        }
+        Err(err) => println!("Error setting costing method: {}", err),
+    }
+}
+
 /// Public function to delete atablet from the inventory
 pub fn delete_product(inventory: &mut Inventory) {
-    let product_name = util::get_user_input("Enter the name of the product to delete: ");
-    match inventory.delete_product(&product_name) {
+    let sku = util::get_user_input("Enter the SKU of the product to delete: ");
+    match inventory.delete_product(&sku) {
         Ok(_) => println!("Product deleted successfully!"),
         Err(err) => println!("Error deleting product: {}", err),
     }
@@ -202,44 +383,54 @@ pub fn list_products(inventory: &mut Inventory) {
         return;
     }
 
-    let headers = vec!["No", "Name", "Description", "Price", "Quantity"];
-    let rows: Vec<Vec<String>> = inventory
-        .products
-        .values()
-        .enumerate()
-        .map(|(index, product)| {
+    let format = output::ask_format().unwrap_or(OutputFormat::Table);
+    println!("{}", output::render(format, &[products_section(inventory)]));
+}
+
+/// The products in `inventory`, by SKU.
+pub fn products_section(inventory: &Inventory) -> Section {
+    let headers = ["SKU", "Name", "Description", "Price", "Cost", "Quantity"];
+    let mut products: Vec<&Product> = inventory.products.values().collect();
+    products.sort_by(|a, b| a.sku.cmp(&b.sku));
+    let rows: Vec<Vec<String>> = products
+        .into_iter()
+        .map(|product| {
             vec![
-                (index + 1).to_string(),
+                product.sku.clone(),
                 product.name.clone(),
                 product.description.clone(),
-                format!("{:.2}", product.price),
+                product.price.to_string(),
+                product.cost.to_string(),
                 product.quantity.to_string(),
             ]
         })
         .collect();
 
-    let table = util::format_table(headers, rows);
-    println!("{}", table);
+    Section::new("Products", &headers, rows)
 }
 
 // Tests
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::money::Money;
 
     #[test]
     fn adds_valid_product() {
         let mut inventory = Inventory::new();
 
         let result = inventory.add_product(Product {
+            sku: "LAP-001".to_string(),
             name: "Laptop".to_string(),
             description: "A gaming laptop".to_string(),
-            price: 1200.0,
+            price: Money::from_cents(120000),
+            cost: Money::from_cents(72000),
             quantity: 20,
+            layers: Vec::new(),
         });
 
         assert!(result.is_ok());
-        assert!(inventory.products.contains_key("Laptop"));
+        assert!(inventory.products.contains_key("LAP-001"));
     }
 
     #[test]
@@ -248,15 +439,18 @@ mod tests {
         let mut inventory = Inventory::new();
 
         let result = inventory.add_product(Product {
+            sku: "PHN-001".to_string(),
             name: "Smartphone".to_string(),
             description: "A high-end smartphone".to_string(),
-            price: -999.0, // Invalid Price
+            price: Money::from_cents(-99900), // Invalid Price
+            cost: Money::from_cents(59940),
             quantity: 20,
+            layers: Vec::new(),
         });
 
         assert!(result.is_err());
         assert_eq!(result.unwrap_err(), "Price cannot be negative!".to_string());
-        assert!(!inventory.products.contains_key("Smartphone"));
+        assert!(!inventory.products.contains_key("PHN-001"));
     }
 
     #[test]
@@ -264,17 +458,21 @@ mod tests {
         let mut inventory = Inventory::new();
 
         let _ = inventory.add_product(Product {
+            sku: "TAB-001".to_string(),
             name: "Tablet".to_string(),
             description: "A basic tablet".to_string(),
-            price: 300.0,
+            price: Money::from_cents(30000),
+            cost: Money::from_cents(18000),
             quantity: 50,
+            layers: Vec::new(),
         });
 
-        let result = inventory.edit_product("Tablet", None, Some(280.0), None);
+        let result =
+            inventory.edit_product("TAB-001", None, Some(Money::from_cents(28000)), None, None);
 
         assert!(result.is_ok());
-        let product = inventory.products.get("Tablet").unwrap();
-        assert_eq!(product.price, 280.0);
+        let product = inventory.products.get("TAB-001").unwrap();
+        assert_eq!(product.price, Money::from_cents(28000));
     }
 
     #[test]
@@ -282,24 +480,29 @@ mod tests {
         let mut inventory: Inventory = Inventory::new();
 
         let _ = inventory.add_product(Product {
+            sku: "TST-001".to_string(),
             name: "Test Product".to_string(),
             description: "A product for testing".to_string(),
-            price: 100.0,
+            price: Money::from_cents(10000),
+            cost: Money::from_cents(6000),
             quantity: 20,
+            layers: Vec::new(),
         });
 
         let result = inventory.edit_product(
-            "Test Product",
+            "TST-001",
             Some("Updated description".to_string()),
-            Some(150.0),
+            Some(Money::from_cents(15000)),
+            Some(Money::from_cents(9000)),
             Some(25),
         );
 
-        let product = inventory.products.get("Test Product").unwrap();
+        let product = inventory.products.get("TST-001").unwrap();
 
         assert!(result.is_ok());
         assert_eq!(product.description, "Updated description");
-        assert_eq!(product.price, 150.0);
+        assert_eq!(product.price, Money::from_cents(15000));
+        assert_eq!(product.cost, Money::from_cents(9000));
         assert_eq!(product.quantity, 25);
     }
 
@@ -308,13 +511,16 @@ mod tests {
         let mut inventory = Inventory::new();
 
         let _ = inventory.add_product(Product {
+            sku: "LAP-001".to_string(),
             name: "Laptop".to_string(),
             description: "A gaming laptop".to_string(),
-            price: 1200.0,
+            price: Money::from_cents(120000),
+            cost: Money::from_cents(72000),
             quantity: 10,
+            layers: Vec::new(),
         });
 
-        let result = inventory.edit_product("Laptop", None, None, None);
+        let result = inventory.edit_product("LAP-001", None, None, None, None);
 
         assert!(result.is_err());
         assert_eq!(
@@ -327,7 +533,13 @@ mod tests {
     fn handles_missing_product() {
         let mut inventory = Inventory::new();
 
-        let result = inventory.edit_product("Nonexistent", None, Some(500.0), None);
+        let result = inventory.edit_product(
+            "Nonexistent",
+            None,
+            Some(Money::from_cents(50000)),
+            None,
+            None,
+        );
 
         assert!(result.is_err());
         assert_eq!(
@@ -340,21 +552,24 @@ mod tests {
     fn deletes_product() {
         let mut inventory = Inventory::new();
         let _ = inventory.add_product(Product {
+            sku: "LAP-001".to_string(),
             name: "Laptop".to_string(),
             description: "A gaming laptop".to_string(),
-            price: 1200.0,
+            price: Money::from_cents(120000),
+            cost: Money::from_cents(72000),
             quantity: 10,
+            layers: Vec::new(),
         });
 
-        let product = inventory.products.get("Laptop").unwrap();
+        let product = inventory.products.get("LAP-001").unwrap();
         // Assert that the added product exists
         assert_eq!(product.name, "Laptop");
-        assert!(inventory.products.contains_key("Laptop"));
+        assert!(inventory.products.contains_key("LAP-001"));
 
-        let result = inventory.delete_product("Laptop");
+        let result = inventory.delete_product("LAP-001");
         // Assert that deleted product nolonger exists
         assert!(result.is_ok());
-        assert!(!inventory.products.contains_key("Laptop"));
+        assert!(!inventory.products.contains_key("LAP-001"));
     }
 
     #[test]
@@ -369,4 +584,73 @@ mod tests {
             "Product 'Laptop' not found.".to_string()
         );
     }
+
+    #[test]
+    fn prevents_adding_duplicate_sku() {
+        let mut inventory = Inventory::new();
+        let _ = inventory.add_product(Product {
+            sku: "LAP-001".to_string(),
+            name: "Laptop".to_string(),
+            description: "A gaming laptop".to_string(),
+            price: Money::from_cents(120000),
+            cost: Money::from_cents(72000),
+            quantity: 10,
+            layers: Vec::new(),
+        });
+
+        let result = inventory.add_product(Product {
+            sku: "LAP-001".to_string(),
+            name: "Other Laptop".to_string(),
+            description: "An office laptop".to_string(),
+            price: Money::from_cents(80000),
+            cost: Money::from_cents(48000),
+            quantity: 5,
+            layers: Vec::new(),
+        });
+
+        assert_eq!(
+            result.unwrap_err(),
+            "A product with SKU 'LAP-001' already exists.".to_string()
+        );
+        assert_eq!(inventory.products.get("LAP-001").unwrap().name, "Laptop");
+    }
+
+    #[test]
+    fn allows_products_sharing_a_name() {
+        let mut inventory = Inventory::new();
+        for sku in ["CBL-001", "CBL-002"] {
+            let result = inventory.add_product(Product {
+                sku: sku.to_string(),
+                name: "Cable".to_string(),
+                description: "A charging cable".to_string(),
+                price: Money::from_cents(500),
+                cost: Money::from_cents(300),
+                quantity: 10,
+                layers: Vec::new(),
+            });
+            assert!(result.is_ok());
+        }
+
+        assert_eq!(inventory.products.len(), 2);
+    }
+
+    #[test]
+    fn renames_product_keeping_its_sku() {
+        let mut inventory = Inventory::new();
+        let _ = inventory.add_product(Product {
+            sku: "LAP-001".to_string(),
+            name: "Laptop".to_string(),
+            description: "A gaming laptop".to_string(),
+            price: Money::from_cents(120000),
+            cost: Money::from_cents(72000),
+            quantity: 10,
+            layers: Vec::new(),
+        });
+
+        let result = inventory.rename_product("LAP-001", "Gaming Laptop");
+
+        assert!(result.is_ok());
+        assert_eq!(inventory.product_name("LAP-001"), "Gaming Laptop");
+        assert!(inventory.rename_product("LAP-001", " ").is_err());
+    }
 }
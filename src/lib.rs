@@ -1,41 +1,146 @@
+pub mod audit;
 pub mod auth;
+pub mod cli;
+pub mod closing;
+pub mod costing;
 pub mod inventory;
+pub mod migration;
+pub mod money;
+pub mod output;
+pub mod password;
+pub mod permission;
 pub mod reporting;
+pub mod session;
+pub mod storage;
+pub mod totp;
 pub mod transaction;
 mod util;
 
+use audit::{AuditLog, Change, Snapshot};
+use auth::AuthManager;
 use inventory::Inventory;
+use permission::{Permission, Role};
+use session::Session;
+use storage::Storage;
 use transaction::TransactionManager;
 
-pub struct Store {
+pub struct Store<S: Storage> {
     pub inventory: Inventory,
     pub transaction_manager: TransactionManager,
+    pub audit_log: AuditLog,
+    storage: S,
 }
 
-impl Store {
-    pub fn new() -> Self {
-        Store {
-            inventory: Inventory::load_from_file("db/inventory.json")
-                .unwrap_or_else(|_| Inventory::new()),
-            transaction_manager: TransactionManager::load_from_file("db/transactions.json")
-                .unwrap_or_else(|_| TransactionManager::new()),
+impl<S: Storage> Store<S> {
+    /// Loads the store from `storage`. Data that exists but cannot be read is
+    /// an error, never an empty store that the next save would write over.
+    pub async fn new(storage: S) -> Result<Self, String> {
+        let inventory = storage.load_inventory().await?;
+        let transaction_manager = storage.load_transactions().await?;
+        let audit_log = storage.load_audit_log().await?;
+
+        Ok(Store {
+            inventory,
+            transaction_manager,
+            audit_log,
+            storage,
+        })
+    }
+
+    /// Saves the inventory, transactions and audit log, reporting every part
+    /// that failed.
+    pub async fn try_save(&self) -> Result<(), String> {
+        self.storage
+            .save(&self.inventory, &self.transaction_manager, &self.audit_log)
+            .await
+    }
+
+    /// Loads everything again, picking up changes made by other sessions.
+    pub async fn reload(&mut self) -> Result<(), String> {
+        self.inventory = self.storage.load_inventory().await?;
+        self.transaction_manager = self.storage.load_transactions().await?;
+        self.audit_log = self.storage.load_audit_log().await?;
+        Ok(())
+    }
+
+    /// Saves, or when that fails reports that the change was not saved and
+    /// goes back to what is stored, so the menu never shows unsaved data.
+    pub async fn save(&mut self) {
+        if let Err(e) = self.try_save().await {
+            eprintln!("{}", e);
+            eprintln!("The change was not saved. Reloading the stored data.");
+            if let Err(e) = self.reload().await {
+                eprintln!("Failed to reload: {}", e);
+            }
         }
     }
 
-    pub fn save(&self) {
-        if let Err(e) = self.inventory.save_to_file("db/inventory.json") {
-            eprintln!("Failed to save inventory: {}", e);
+    /// Logs `changes`, then everything that changed since `snapshot`, as made
+    /// by the user in `session`.
+    pub fn log_changes(
+        &mut self,
+        session: &Session,
+        snapshot: &Snapshot,
+        mut changes: Vec<Change>,
+    ) {
+        changes.extend(snapshot.changes(&self.inventory, &self.transaction_manager));
+        for change in changes {
+            self.audit_log.record(session, change);
         }
-        if let Err(e) = self
-            .transaction_manager
-            .save_to_file("db/transactions.json")
-        {
-            eprintln!("Failed to save transactions: {}", e);
+    }
+}
+
+/// The store menu: choice, label and the permission it needs.
+const MENU: &[(&str, &str, Permission)] = &[
+    ("1", "Add Product", Permission::EditProducts),
+    ("2", "Edit Product", Permission::EditProducts),
+    ("3", "Delete Product", Permission::DeleteProducts),
+    ("4", "List Products", Permission::ViewInventory),
+    ("5", "Record Sale", Permission::RecordSales),
+    ("6", "Record Purchase", Permission::RecordPurchases),
+    ("7", "List Transactions", Permission::ViewTransactions),
+    ("8", "Generate Reports", Permission::ViewReports),
+    ("9", "Record Return", Permission::RecordReturns),
+    ("10", "Void Transaction", Permission::VoidTransactions),
+    ("11", "Set Costing Method", Permission::ConfigureCosting),
+    ("12", "Manage Users", Permission::ManageUsers),
+    ("13", "Audit Log", Permission::ViewAuditLog),
+    ("14", "Change Password", Permission::ManageOwnAccount),
+    ("15", "Two-Factor Login", Permission::ManageOwnAccount),
+    ("16", "Close Day", Permission::CloseDay),
+];
+
+/// Checks that `role` may run the menu `choice`. Choices that are not on the
+/// menu, such as exit, need no permission.
+pub fn authorize(role: Role, choice: &str) -> Result<(), String> {
+    match MENU.iter().find(|(key, _, _)| *key == choice) {
+        Some((_, label, permission)) if !role.can(*permission) => {
+            Err(format!("Access denied: a {} cannot use {}.", role, label))
         }
+        _ => Ok(()),
     }
 }
 
-pub fn execute(store: &mut Store, choice: &str) {
+pub async fn execute<S: Storage>(
+    store: &mut Store<S>,
+    auth_manager: &AuthManager,
+    session: &Session,
+    choice: &str,
+) {
+    if let Err(e) = authorize(session.role(), choice) {
+        println!("{}", e);
+        return;
+    }
+
+    // Start from what is stored, which other sessions may have changed
+    if let Err(e) = store.reload().await {
+        println!("{}", e);
+        return;
+    }
+
+    let snapshot = Snapshot::take(&store.inventory, &store.transaction_manager);
+    let mut changes = Vec::new();
+
     match choice {
         "1" => inventory::add_product(&mut store.inventory),
         "2" => inventory::edit_product(&mut store.inventory),
@@ -44,32 +149,77 @@ pub fn execute(store: &mut Store, choice: &str) {
         "5" => transaction::handle_sale_transaction(
             &mut store.transaction_manager,
             &mut store.inventory,
+            session,
         ),
         "6" => transaction::handle_purchase_transaction(
             &mut store.transaction_manager,
             &mut store.inventory,
+            session,
         ),
-        "7" => transaction::list_transactions(&store.transaction_manager),
+        "7" => transaction::list_transactions(&store.transaction_manager, &store.inventory),
         "8" => reporting::generate_reports(&store.transaction_manager, &store.inventory),
-        "9" => {
-            store.save();
+        "9" => transaction::handle_return_transaction(
+            &mut store.transaction_manager,
+            &mut store.inventory,
+            session,
+        ),
+        "10" => transaction::handle_void_transaction(
+            &mut store.transaction_manager,
+            &mut store.inventory,
+            session,
+        ),
+        "11" => inventory::set_costing_method(&mut store.inventory),
+        "12" => changes.extend(auth::manage_users(auth_manager, &session.user).await),
+        "13" => audit::show_audit_log(&store.audit_log),
+        "14" => changes.extend(auth::change_own_password(auth_manager, &session.user).await),
+        "15" => changes.extend(auth::two_factor(auth_manager, &session.user).await),
+        "16" => closing::handle_close_day(&mut store.transaction_manager, session),
+        "0" => {
             println!("Exiting...");
             println!("Goodbye!");
             std::process::exit(0);
         }
         _ => println!("Invalid choice, please try again."),
     }
+
+    store.log_changes(session, &snapshot, changes);
+
+    // Persist after every action that can change the inventory, history or users
+    if matches!(
+        choice,
+        "1" | "2" | "3" | "5" | "6" | "9" | "10" | "11" | "12" | "14" | "15" | "16"
+    ) {
+        store.save().await;
+    }
 }
 
-pub fn display_menu() {
+/// Shows the menu entries `role` is allowed to use.
+pub fn display_menu(role: Role) {
     println!("\n--- Rusty Store Management ---");
-    println!("1. Add Product");
-    println!("2. Edit Product");
-    println!("3. Delete Product");
-    println!("4. List Products");
-    println!("5. Record Sale");
-    println!("6. Record Purchase");
-    println!("7. List Transactions");
-    println!("8. Generate Reports");
-    println!("9. Exit");
+    for (key, label, permission) in MENU {
+        if role.can(*permission) {
+            println!("{}. {}", key, label);
+        }
+    }
+    println!("0. Exit");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checks_menu_choices_against_the_role() {
+        assert!(authorize(Role::Cashier, "5").is_ok());
+        assert_eq!(
+            authorize(Role::Cashier, "3").unwrap_err(),
+            "Access denied: a cashier cannot use Delete Product."
+        );
+        assert!(authorize(Role::Cashier, "8").is_err());
+        assert!(authorize(Role::Manager, "8").is_ok());
+        assert!(authorize(Role::Manager, "12").is_err());
+        assert!(authorize(Role::Cashier, "16").is_err());
+        assert!(authorize(Role::Manager, "16").is_ok());
+        assert!(authorize(Role::Cashier, "0").is_ok());
+    }
 }
@@ -1,38 +1,81 @@
+use std::process::ExitCode;
+
+use clap::Parser;
 use rusty_store::{
     auth::{self, AuthManager},
-    display_menu, execute, Store,
+    cli::{self, Cli},
+    display_menu, execute,
+    session::Session,
+    storage::{self, JsonStorage, SqliteStorage},
+    Store,
 };
 
 #[tokio::main]
-async fn main() {
-    let auth_manager = AuthManager::new("sqlite:db/rusty_store.db").await;
+async fn main() -> ExitCode {
+    let cli = Cli::parse();
+    let auth_manager = AuthManager::new(&cli.database).await;
     auth_manager.initialize().await;
-    let mut store = Store::new();
-
-    println!("--- User Authentication ---");
-    println!("1. Login");
-    println!("2. Register");
-
-    let mut choice = String::new();
-    std::io::stdin().read_line(&mut choice).unwrap();
-    let choice = choice.trim();
-
-    match choice {
-        "1" => auth::login(&auth_manager).await,
-        "2" => auth::register(&auth_manager).await,
-        _ => {
-            println!("Invalid choice, exiting.");
-            return;
+    let storage = SqliteStorage::new(&cli.database).await;
+    // Bring over the JSON backups older versions wrote on exit
+    if let Err(e) = storage::import_once(&storage, &JsonStorage::new("db")).await {
+        eprintln!("Failed to import JSON backups: {}", e);
+        std::process::exit(1);
+    }
+    let mut store = match Store::new(storage).await {
+        Ok(store) => store,
+        Err(e) => {
+            eprintln!("Failed to load store data: {}", e);
+            std::process::exit(1);
         }
+    };
+
+    if let Some(command) = cli.command {
+        return cli::run(
+            &mut store,
+            &auth_manager,
+            &cli.credentials,
+            cli.format,
+            command,
+        )
+        .await;
     }
 
+    let user = match auth_manager.has_users().await {
+        Ok(false) => auth::create_first_admin(&auth_manager).await,
+        Ok(true) => {
+            println!("--- User Authentication ---");
+            println!("1. Login");
+            println!("2. Register with an invitation code");
+
+            let mut choice = String::new();
+            std::io::stdin().read_line(&mut choice).unwrap();
+            match choice.trim() {
+                "1" => auth::login(&auth_manager).await,
+                "2" => auth::register(&auth_manager).await,
+                _ => {
+                    println!("Invalid choice, exiting.");
+                    return ExitCode::FAILURE;
+                }
+            }
+        }
+        Err(e) => {
+            eprintln!("Failed to read users: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    let Some(user) = user else {
+        std::process::exit(1);
+    };
+    let session = Session::new(user);
+
     loop {
-        display_menu();
+        display_menu(session.role());
 
         let mut choice = String::new();
         std::io::stdin().read_line(&mut choice).unwrap();
         let choice = choice.trim();
 
-        execute(&mut store, choice);
+        execute(&mut store, &auth_manager, &session, choice).await;
     }
 }
@@ -0,0 +1,720 @@
+//! Schema versions for everything the store persists.
+//!
+//! JSON files are wrapped in an envelope, `{"version": N, "data": ...}`. Files
+//! written before the envelope existed are version 0. On load each step in
+//! `JSON_MIGRATIONS` upgrades the data by one version until it is current.
+//!
+//! The SQLite database records its version in `PRAGMA user_version`, and each
+//! entry in `SQLITE_MIGRATIONS` holds the statements that move it up a version.
+
+use serde_json::{json, Value};
+use sqlx::sqlite::SqlitePool;
+use sqlx::Row;
+
+use crate::costing::CostingMethod;
+use crate::money::Money;
+
+/// Which kind of document a JSON file holds, so a migration step can change
+/// only the shape it cares about.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Document {
+    Inventory,
+    Transactions,
+    /// First written at version 4, so the earlier steps leave it alone
+    AuditLog,
+}
+
+/// A step gets the document to upgrade and, for the inventory, the
+/// transaction history already brought up to date, so values the SQLite
+/// steps seed from other tables can be seeded the same way.
+type JsonMigration = fn(Document, Value, &Value) -> Result<Value, String>;
+
+/// `JSON_MIGRATIONS[n]` upgrades data from version `n` to `n + 1`. The steps
+/// are numbered like `SQLITE_MIGRATIONS`, and those that change only what the
+/// JSON files do not hold, such as users, leave the data as it is.
+const JSON_MIGRATIONS: &[JsonMigration] = &[
+    // 0 -> 1: bare data is wrapped in the envelope unchanged
+    |_, data, _| Ok(data),
+    // 1 -> 2: products are keyed by SKU and transactions refer to that SKU.
+    // Names were unique before, so each name becomes its product's SKU.
+    |document, mut data, _| {
+        match document {
+            Document::Inventory => {
+                if let Some(products) = data["products"].as_object_mut() {
+                    for (name, product) in products.iter_mut() {
+                        product["sku"] = Value::String(name.clone());
+                    }
+                }
+            }
+            Document::Transactions => {
+                if let Some(transactions) = data["transactions"].as_array_mut() {
+                    for transaction in transactions {
+                        if let Some(object) = transaction.as_object_mut() {
+                            let name = object.remove("product_name").unwrap_or_default();
+                            object.insert("sku".to_string(), name);
+                        }
+                    }
+                }
+            }
+            Document::AuditLog => {}
+        }
+        Ok(data)
+    },
+    // 2 -> 3: transactions get sequential ids and a timestamp. When they were
+    // recorded is unknown, so they are dated at the Unix epoch.
+    |document, mut data, _| {
+        if document == Document::Transactions {
+            if let Some(transactions) = data["transactions"].as_array_mut() {
+                for (index, transaction) in transactions.iter_mut().enumerate() {
+                    transaction["id"] = json!(index + 1);
+                    transaction["recorded_at"] = json!(LEGACY_TIMESTAMP);
+                }
+            }
+        }
+        Ok(data)
+    },
+    // 3 -> 4: prices and totals become whole cents instead of floats
+    |document, mut data, _| {
+        let (collection, fields): (Option<Vec<&mut Value>>, &[&str]) = match document {
+            Document::Inventory => (
+                data["products"]
+                    .as_object_mut()
+                    .map(|p| p.values_mut().collect()),
+                &["price"],
+            ),
+            Document::Transactions => (
+                data["transactions"]
+                    .as_array_mut()
+                    .map(|t| t.iter_mut().collect()),
+                &["price", "total"],
+            ),
+            Document::AuditLog => (None, &[]),
+        };
+        for record in collection.unwrap_or_default() {
+            for field in fields {
+                if let Some(amount) = record[*field].as_f64() {
+                    record[*field] = json!(Money::from_f64(amount).cents());
+                }
+            }
+        }
+        Ok(data)
+    },
+    // 4 -> 5: sale lines can belong to a multi-line order
+    |document, mut data, _| {
+        if document == Document::Transactions {
+            fill_missing(transactions(&mut data), "order_id", Value::Null);
+        }
+        Ok(data)
+    },
+    // 5 -> 6: returns refer back to their sale and may be damaged
+    |document, mut data, _| {
+        if document == Document::Transactions {
+            fill_missing(transactions(&mut data), "reference_id", Value::Null);
+            fill_missing(transactions(&mut data), "damaged", json!(false));
+        }
+        Ok(data)
+    },
+    // 6 -> 7: products keep a unit cost apart from the list price, seeded from
+    // their latest purchase, and sales record their cost of goods sold
+    |document, mut data, history| {
+        match document {
+            Document::Inventory => {
+                for product in products(&mut data) {
+                    if product.get("cost").is_none() {
+                        let sku = product["sku"].as_str().unwrap_or_default();
+                        product["cost"] = json!(latest_purchase_price(history, sku));
+                    }
+                }
+            }
+            Document::Transactions => fill_missing(transactions(&mut data), "cost", json!(0)),
+            Document::AuditLog => {}
+        }
+        Ok(data)
+    },
+    // 7 -> 8: stock on hand is kept in cost layers, starting with one layer
+    // per product at its unit cost, and the costing method is a setting
+    |document, mut data, _| {
+        if document == Document::Inventory {
+            for product in products(&mut data) {
+                if product.get("layers").is_none() {
+                    let quantity = product["quantity"].as_u64().unwrap_or(0);
+                    product["layers"] = if quantity > 0 {
+                        json!([{ "quantity": quantity, "unit_cost": product["cost"] }])
+                    } else {
+                        json!([])
+                    };
+                }
+            }
+            if data.get("costing_method").is_none() {
+                data["costing_method"] = json!(CostingMethod::default());
+            }
+        }
+        Ok(data)
+    },
+    // 8 -> 9: users get a role
+    |_, data, _| Ok(data),
+    // 9 -> 10: transactions name the user who recorded them
+    |document, mut data, _| {
+        if document == Document::Transactions {
+            fill_missing(transactions(&mut data), "recorded_by", Value::Null);
+        }
+        Ok(data)
+    },
+    // 10 -> 11: the audit log, which JSON has kept in its own file since
+    // version 4
+    |_, data, _| Ok(data),
+    // 11 -> 12: users count failed logins
+    |_, data, _| Ok(data),
+    // 12 -> 13: admins can disable accounts
+    |_, data, _| Ok(data),
+    // 13 -> 14: invitation codes
+    |_, data, _| Ok(data),
+    // 14 -> 15: two-factor login
+    |_, data, _| Ok(data),
+    // 15 -> 16: sales record how they were paid, and business days are closed
+    |document, mut data, _| {
+        if document == Document::Transactions {
+            fill_missing(transactions(&mut data), "payment_method", Value::Null);
+            if data.get("closings").is_none() {
+                data["closings"] = json!([]);
+            }
+        }
+        Ok(data)
+    },
+    // 16 -> 17: the last one-time code step each user logged in with
+    |_, data, _| Ok(data),
+    // 17 -> 18: invitation codes expire
+    |_, data, _| Ok(data),
+];
+
+fn products(data: &mut Value) -> Vec<&mut Value> {
+    data["products"]
+        .as_object_mut()
+        .map(|products| products.values_mut().collect())
+        .unwrap_or_default()
+}
+
+fn transactions(data: &mut Value) -> Vec<&mut Value> {
+    data["transactions"]
+        .as_array_mut()
+        .map(|transactions| transactions.iter_mut().collect())
+        .unwrap_or_default()
+}
+
+/// Sets `field` on each record that lacks it. Files written by newer builds
+/// under an older version number already hold every field, so they keep theirs.
+fn fill_missing(records: Vec<&mut Value>, field: &str, value: Value) {
+    for record in records {
+        if let Some(object) = record.as_object_mut() {
+            object.entry(field).or_insert_with(|| value.clone());
+        }
+    }
+}
+
+/// What the most recent purchase of `sku` in `history` paid per unit, or
+/// nothing when it was never purchased.
+fn latest_purchase_price(history: &Value, sku: &str) -> i64 {
+    history["transactions"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter(|transaction| {
+            transaction["transaction_type"] == "Purchase" && transaction["sku"] == sku
+        })
+        .max_by_key(|transaction| transaction["id"].as_u64().unwrap_or(0))
+        .and_then(|purchase| purchase["price"].as_i64())
+        .unwrap_or(0)
+}
+
+/// Stands in for the unknown time of records created before timestamps.
+const LEGACY_TIMESTAMP: &str = "1970-01-01T00:00:00Z";
+
+pub const JSON_VERSION: u64 = JSON_MIGRATIONS.len() as u64;
+
+/// `SQLITE_MIGRATIONS[n]` upgrades the database from version `n` to `n + 1`.
+const SQLITE_MIGRATIONS: &[&[&str]] = &[
+    // 0 -> 1: tables created before versioning existed are left as they are
+    &[
+        r#"
+        CREATE TABLE IF NOT EXISTS users (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            username TEXT UNIQUE NOT NULL,
+            hashed_password TEXT NOT NULL
+        );
+        "#,
+        r#"
+        CREATE TABLE IF NOT EXISTS products (
+            name TEXT PRIMARY KEY NOT NULL,
+            description TEXT NOT NULL,
+            price REAL NOT NULL,
+            quantity INTEGER NOT NULL
+        );
+        "#,
+        r#"
+        CREATE TABLE IF NOT EXISTS transactions (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            transaction_type TEXT NOT NULL,
+            product_name TEXT NOT NULL,
+            quantity INTEGER NOT NULL,
+            price REAL NOT NULL,
+            total REAL NOT NULL
+        );
+        "#,
+    ],
+    // 1 -> 2: products are keyed by SKU, seeded from their unique names
+    &[
+        r#"
+        CREATE TABLE products_v2 (
+            sku TEXT PRIMARY KEY NOT NULL,
+            name TEXT NOT NULL,
+            description TEXT NOT NULL,
+            price REAL NOT NULL,
+            quantity INTEGER NOT NULL
+        );
+        "#,
+        "INSERT INTO products_v2 SELECT name, name, description, price, quantity FROM products;",
+        "DROP TABLE products;",
+        "ALTER TABLE products_v2 RENAME TO products;",
+        "ALTER TABLE transactions RENAME COLUMN product_name TO sku;",
+    ],
+    // 2 -> 3: transactions keep their row id as their id and get a timestamp
+    &["ALTER TABLE transactions ADD COLUMN recorded_at TEXT NOT NULL DEFAULT '1970-01-01T00:00:00Z';"],
+    // 3 -> 4: prices and totals are stored as whole cents
+    &[
+        r#"
+        CREATE TABLE products_v4 (
+            sku TEXT PRIMARY KEY NOT NULL,
+            name TEXT NOT NULL,
+            description TEXT NOT NULL,
+            price INTEGER NOT NULL,
+            quantity INTEGER NOT NULL
+        );
+        "#,
+        r#"
+        INSERT INTO products_v4
+        SELECT sku, name, description, CAST(ROUND(price * 100) AS INTEGER), quantity
+        FROM products;
+        "#,
+        "DROP TABLE products;",
+        "ALTER TABLE products_v4 RENAME TO products;",
+        r#"
+        CREATE TABLE transactions_v4 (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            transaction_type TEXT NOT NULL,
+            sku TEXT NOT NULL,
+            quantity INTEGER NOT NULL,
+            price INTEGER NOT NULL,
+            total INTEGER NOT NULL,
+            recorded_at TEXT NOT NULL
+        );
+        "#,
+        r#"
+        INSERT INTO transactions_v4
+        SELECT id, transaction_type, sku, quantity,
+            CAST(ROUND(price * 100) AS INTEGER), CAST(ROUND(total * 100) AS INTEGER), recorded_at
+        FROM transactions;
+        "#,
+        "DROP TABLE transactions;",
+        "ALTER TABLE transactions_v4 RENAME TO transactions;",
+    ],
+    // 4 -> 5: sale lines can belong to a multi-line order
+    &["ALTER TABLE transactions ADD COLUMN order_id INTEGER;"],
+    // 5 -> 6: returns refer back to their sale and may be damaged
+    &[
+        "ALTER TABLE transactions ADD COLUMN reference_id INTEGER;",
+        "ALTER TABLE transactions ADD COLUMN damaged INTEGER NOT NULL DEFAULT 0;",
+    ],
+    // 6 -> 7: products keep a unit cost apart from the list price, seeded from
+    // their latest purchase, and sales record their cost of goods sold
+    &[
+        "ALTER TABLE products ADD COLUMN cost INTEGER NOT NULL DEFAULT 0;",
+        r#"
+        UPDATE products SET cost = COALESCE(
+            (SELECT price FROM transactions
+             WHERE transactions.sku = products.sku AND transaction_type = 'Purchase'
+             ORDER BY id DESC LIMIT 1),
+            0
+        );
+        "#,
+        "ALTER TABLE transactions ADD COLUMN cost INTEGER NOT NULL DEFAULT 0;",
+    ],
+    // 7 -> 8: stock on hand is kept in cost layers, starting with one layer
+    // per product at its unit cost, and the costing method is a setting
+    &[
+        r#"
+        CREATE TABLE cost_layers (
+            sku TEXT NOT NULL,
+            position INTEGER NOT NULL,
+            quantity INTEGER NOT NULL,
+            unit_cost INTEGER NOT NULL,
+            PRIMARY KEY (sku, position)
+        );
+        "#,
+        "INSERT INTO cost_layers SELECT sku, 0, quantity, cost FROM products WHERE quantity > 0;",
+        r#"
+        CREATE TABLE settings (
+            key TEXT PRIMARY KEY NOT NULL,
+            value TEXT NOT NULL
+        );
+        "#,
+    ],
+    // 8 -> 9: users get a role. Everyone registered so far could do
+    // everything, so they keep full access as admins.
+    &["ALTER TABLE users ADD COLUMN role TEXT NOT NULL DEFAULT 'admin';"],
+    // 9 -> 10: transactions name the user who recorded them, and changes to
+    // products are kept with who made them
+    &[
+        "ALTER TABLE transactions ADD COLUMN recorded_by TEXT;",
+        r#"
+        CREATE TABLE product_changes (
+            id INTEGER PRIMARY KEY,
+            recorded_at TEXT NOT NULL,
+            changed_by TEXT NOT NULL,
+            sku TEXT NOT NULL,
+            details TEXT NOT NULL
+        );
+        "#,
+    ],
+    // 10 -> 11: product changes grow into an audit log of every change, with
+    // the values before and after. Older entries only have their description.
+    &[
+        r#"
+        CREATE TABLE audit_log (
+            id INTEGER PRIMARY KEY,
+            recorded_at TEXT NOT NULL,
+            username TEXT NOT NULL,
+            action TEXT NOT NULL,
+            subject TEXT NOT NULL,
+            details TEXT NOT NULL,
+            before TEXT,
+            after TEXT
+        );
+        "#,
+        r#"
+        INSERT INTO audit_log (id, recorded_at, username, action, subject, details)
+        SELECT id, recorded_at, changed_by,
+            CASE
+                WHEN details LIKE 'added %' THEN 'Product Added'
+                WHEN details LIKE 'deleted %' THEN 'Product Deleted'
+                ELSE 'Product Edited'
+            END,
+            sku, details
+        FROM product_changes;
+        "#,
+        "DROP TABLE product_changes;",
+    ],
+    // 11 -> 12: users count failed logins and can be locked out for a while
+    &[
+        "ALTER TABLE users ADD COLUMN failed_attempts INTEGER NOT NULL DEFAULT 0;",
+        "ALTER TABLE users ADD COLUMN locked_until TEXT;",
+    ],
+    // 12 -> 13: admins can disable accounts
+    &["ALTER TABLE users ADD COLUMN disabled INTEGER NOT NULL DEFAULT 0;"],
+    // 13 -> 14: accounts after the first come from one-time invitation codes
+    &[r#"
+        CREATE TABLE invitations (
+            code TEXT PRIMARY KEY NOT NULL,
+            role TEXT NOT NULL,
+            created_by TEXT NOT NULL,
+            created_at TEXT NOT NULL,
+            used_by TEXT,
+            used_at TEXT
+        );
+        "#],
+    // 14 -> 15: users can turn on two-factor login with a TOTP secret
+    &["ALTER TABLE users ADD COLUMN totp_secret TEXT;"],
+    // 15 -> 16: sales record how they were paid, and business days are closed
+    // with a Z-report and a cash count
+    &[
+        "ALTER TABLE transactions ADD COLUMN payment_method TEXT;",
+        r#"
+        CREATE TABLE day_closings (
+            day TEXT PRIMARY KEY NOT NULL,
+            closed_at TEXT NOT NULL,
+            closed_by TEXT NOT NULL,
+            expected_cash INTEGER NOT NULL,
+            counted_cash INTEGER NOT NULL,
+            over_short INTEGER NOT NULL
+        );
+        "#,
+    ],
+    // 16 -> 17: the last one-time code step each user logged in with, so a
+    // code cannot be used twice
+    &["ALTER TABLE users ADD COLUMN totp_last_step INTEGER;"],
+    // 17 -> 18: invitation codes expire. Unused codes already handed out get
+    // a week from when they were made.
+    &[
+        "ALTER TABLE invitations ADD COLUMN expires_at TEXT;",
+        "UPDATE invitations SET expires_at = strftime('%Y-%m-%dT%H:%M:%SZ', created_at, '+7 days');",
+    ],
+];
+
+pub const SQLITE_VERSION: i64 = SQLITE_MIGRATIONS.len() as i64;
+
+/// Wraps `data` in an envelope stamped with the current version.
+pub fn wrap_json(data: Value) -> Value {
+    json!({ "version": JSON_VERSION, "data": data })
+}
+
+/// Unwraps a loaded JSON file and upgrades its data to the current version.
+/// `history` is the transaction history at the current version, which older
+/// inventories take their unit costs from.
+pub fn upgrade_json(document: Document, value: Value, history: &Value) -> Result<Value, String> {
+    let (version, mut data) = match value {
+        Value::Object(mut envelope)
+            if envelope.contains_key("version") && envelope.contains_key("data") =>
+        {
+            let version = envelope["version"]
+                .as_u64()
+                .ok_or("The version field must be a number")?;
+            (version, envelope.remove("data").unwrap_or_default())
+        }
+        data => (0, data),
+    };
+
+    if version > JSON_VERSION {
+        return Err(format!(
+            "Data is version {} but this build only understands up to version {}",
+            version, JSON_VERSION
+        ));
+    }
+
+    for migration in &JSON_MIGRATIONS[version as usize..] {
+        data = migration(document, data, history)?;
+    }
+    Ok(data)
+}
+
+/// Brings the database schema up to date, applying each pending migration in
+/// its own transaction.
+pub async fn migrate_sqlite(db: &SqlitePool) -> Result<(), String> {
+    let version: i64 = sqlx::query("PRAGMA user_version")
+        .fetch_one(db)
+        .await
+        .map_err(|e| format!("Failed to read schema version: {}", e))?
+        .get(0);
+
+    if version > SQLITE_VERSION {
+        return Err(format!(
+            "Database schema is version {} but this build only understands up to version {}",
+            version, SQLITE_VERSION
+        ));
+    }
+
+    for (index, statements) in SQLITE_MIGRATIONS.iter().enumerate().skip(version as usize) {
+        let next = index + 1;
+        let mut tx = db
+            .begin()
+            .await
+            .map_err(|e| format!("Failed to start migration {}: {}", next, e))?;
+        for statement in statements.iter() {
+            sqlx::query(statement)
+                .execute(&mut *tx)
+                .await
+                .map_err(|e| format!("Migration {} failed: {}", next, e))?;
+        }
+        sqlx::query(&format!("PRAGMA user_version = {}", next))
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| format!("Failed to record schema version {}: {}", next, e))?;
+        tx.commit()
+            .await
+            .map_err(|e| format!("Failed to commit migration {}: {}", next, e))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn upgrades_unversioned_json() {
+        let legacy = json!({ "transactions": [] });
+        let data = upgrade_json(Document::Transactions, legacy, &Value::Null).unwrap();
+        assert_eq!(data, json!({ "transactions": [], "closings": [] }));
+    }
+
+    #[test]
+    fn reads_current_envelope() {
+        let data = json!({ "transactions": [] });
+        let upgraded = upgrade_json(
+            Document::Transactions,
+            wrap_json(data.clone()),
+            &Value::Null,
+        )
+        .unwrap();
+        assert_eq!(upgraded, data);
+    }
+
+    #[test]
+    fn keys_legacy_products_by_sku() {
+        let inventory = json!({ "products": { "Laptop": { "name": "Laptop" } } });
+        let inventory = upgrade_json(Document::Inventory, inventory, &Value::Null).unwrap();
+        assert_eq!(inventory["products"]["Laptop"]["sku"], "Laptop");
+
+        let transactions = json!({ "transactions": [{ "product_name": "Laptop" }] });
+        let transactions =
+            upgrade_json(Document::Transactions, transactions, &Value::Null).unwrap();
+        assert_eq!(transactions["transactions"][0]["sku"], "Laptop");
+        assert!(transactions["transactions"][0]
+            .get("product_name")
+            .is_none());
+    }
+
+    #[test]
+    fn numbers_and_dates_legacy_transactions() {
+        let transactions = json!({ "transactions": [{ "sku": "A" }, { "sku": "B" }] });
+        let envelope = json!({ "version": 2, "data": transactions });
+        let transactions = upgrade_json(Document::Transactions, envelope, &Value::Null).unwrap();
+        assert_eq!(transactions["transactions"][1]["id"], 2);
+        assert_eq!(
+            transactions["transactions"][1]["recorded_at"],
+            LEGACY_TIMESTAMP
+        );
+    }
+
+    #[test]
+    fn converts_float_amounts_to_cents() {
+        let inventory =
+            json!({ "version": 3, "data": { "products": { "A": { "price": 19.99 } } } });
+        let inventory = upgrade_json(Document::Inventory, inventory, &Value::Null).unwrap();
+        assert_eq!(inventory["products"]["A"]["price"], 1999);
+
+        let transactions = json!({
+            "version": 3,
+            "data": { "transactions": [{ "price": 0.1, "total": 0.30000000000000004 }] }
+        });
+        let transactions =
+            upgrade_json(Document::Transactions, transactions, &Value::Null).unwrap();
+        assert_eq!(transactions["transactions"][0]["price"], 10);
+        assert_eq!(transactions["transactions"][0]["total"], 30);
+    }
+
+    #[test]
+    fn seeds_legacy_costs_like_sqlite() {
+        let transactions = json!({ "transactions": [
+            { "transaction_type": "Sale", "product_name": "Laptop", "quantity": 2, "price": 1300.0, "total": 2600.0 },
+            { "transaction_type": "Purchase", "product_name": "Laptop", "quantity": 5, "price": 950.0, "total": 4750.0 }
+        ] });
+        let history = upgrade_json(Document::Transactions, transactions, &Value::Null).unwrap();
+        assert_eq!(history["transactions"][0]["cost"], 0);
+        assert_eq!(history["transactions"][1]["order_id"], Value::Null);
+
+        let inventory = json!({ "products": { "Laptop": {
+            "name": "Laptop", "description": "A gaming laptop", "price": 1200.0, "quantity": 10
+        } } });
+        let inventory = upgrade_json(Document::Inventory, inventory, &history).unwrap();
+        let laptop = &inventory["products"]["Laptop"];
+        assert_eq!(laptop["cost"], 95_000);
+        assert_eq!(
+            laptop["layers"],
+            json!([{ "quantity": 10, "unit_cost": 95_000 }])
+        );
+
+        let current: crate::inventory::Inventory = serde_json::from_value(inventory).unwrap();
+        assert_eq!(
+            current.products["Laptop"].stock_value(),
+            Money::from_cents(950_000)
+        );
+    }
+
+    #[test]
+    fn rejects_json_from_a_newer_version() {
+        let envelope = json!({ "version": JSON_VERSION + 1, "data": {} });
+        assert!(upgrade_json(Document::Inventory, envelope, &Value::Null).is_err());
+    }
+
+    #[tokio::test]
+    async fn migrates_sqlite_once() {
+        let db = SqlitePool::connect("sqlite::memory:").await.unwrap();
+        migrate_sqlite(&db).await.unwrap();
+        migrate_sqlite(&db).await.unwrap();
+
+        let version: i64 = sqlx::query("PRAGMA user_version")
+            .fetch_one(&db)
+            .await
+            .unwrap()
+            .get(0);
+        assert_eq!(version, SQLITE_VERSION);
+    }
+
+    #[tokio::test]
+    async fn keeps_existing_rows_when_migrating_sqlite() {
+        let db = SqlitePool::connect("sqlite::memory:").await.unwrap();
+        for statement in SQLITE_MIGRATIONS[0] {
+            sqlx::query(statement).execute(&db).await.unwrap();
+        }
+        sqlx::query("INSERT INTO products VALUES ('Laptop', 'A gaming laptop', 1200.0, 10)")
+            .execute(&db)
+            .await
+            .unwrap();
+        sqlx::query("INSERT INTO transactions VALUES (1, 'Sale', 'Laptop', 2, 1300.0, 2600.0)")
+            .execute(&db)
+            .await
+            .unwrap();
+        sqlx::query("INSERT INTO transactions VALUES (2, 'Purchase', 'Laptop', 5, 950.0, 4750.0)")
+            .execute(&db)
+            .await
+            .unwrap();
+
+        migrate_sqlite(&db).await.unwrap();
+
+        let row = sqlx::query("SELECT sku, name, price, cost, quantity FROM products")
+            .fetch_one(&db)
+            .await
+            .unwrap();
+        assert_eq!(row.get::<String, _>("sku"), "Laptop");
+        assert_eq!(row.get::<String, _>("name"), "Laptop");
+        assert_eq!(row.get::<i64, _>("quantity"), 10);
+        assert_eq!(row.get::<i64, _>("price"), 120_000);
+        assert_eq!(row.get::<i64, _>("cost"), 95_000);
+
+        let row = sqlx::query("SELECT quantity, unit_cost FROM cost_layers WHERE sku = 'Laptop'")
+            .fetch_one(&db)
+            .await
+            .unwrap();
+        assert_eq!(row.get::<i64, _>("quantity"), 10);
+        assert_eq!(row.get::<i64, _>("unit_cost"), 95_000);
+
+        let row = sqlx::query("SELECT sku, total, recorded_at FROM transactions WHERE id = 1")
+            .fetch_one(&db)
+            .await
+            .unwrap();
+        assert_eq!(row.get::<String, _>("sku"), "Laptop");
+        assert_eq!(row.get::<i64, _>("total"), 260_000);
+        let recorded_at: chrono::DateTime<chrono::Utc> = row.get("recorded_at");
+        assert_eq!(recorded_at.timestamp(), 0);
+    }
+
+    #[tokio::test]
+    async fn moves_product_changes_into_the_audit_log() {
+        let db = SqlitePool::connect("sqlite::memory:").await.unwrap();
+        for statements in &SQLITE_MIGRATIONS[..10] {
+            for statement in *statements {
+                sqlx::query(statement).execute(&db).await.unwrap();
+            }
+        }
+        sqlx::query("PRAGMA user_version = 10")
+            .execute(&db)
+            .await
+            .unwrap();
+        sqlx::query(
+            "INSERT INTO product_changes VALUES (1, '2026-01-10T12:00:00Z', 'boss', 'TAB-001', 'price 300.00 -> 280.00')",
+        )
+        .execute(&db)
+        .await
+        .unwrap();
+
+        migrate_sqlite(&db).await.unwrap();
+
+        let row = sqlx::query("SELECT username, action, subject, before FROM audit_log")
+            .fetch_one(&db)
+            .await
+            .unwrap();
+        assert_eq!(row.get::<String, _>("username"), "boss");
+        assert_eq!(row.get::<String, _>("action"), "Product Edited");
+        assert_eq!(row.get::<String, _>("subject"), "TAB-001");
+        assert_eq!(row.get::<Option<String>, _>("before"), None);
+    }
+}
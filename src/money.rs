@@ -0,0 +1,222 @@
+//! Exact money amounts stored as whole cents.
+//!
+//! Rounding rule: whenever an amount has to lose precision (parsing input with
+//! more than two decimals, converting a legacy `f64`, dividing) it is rounded
+//! to the nearest cent, with halves rounded away from zero.
+
+use std::fmt;
+use std::iter::Sum;
+use std::ops::{Add, AddAssign, Mul, Neg, Sub, SubAssign};
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+)]
+#[serde(transparent)]
+pub struct Money(i64);
+
+impl Money {
+    pub const ZERO: Money = Money(0);
+
+    pub const fn from_cents(cents: i64) -> Self {
+        Money(cents)
+    }
+
+    pub const fn cents(self) -> i64 {
+        self.0
+    }
+
+    /// Converts a floating point amount, rounding to the nearest cent.
+    pub fn from_f64(amount: f64) -> Self {
+        Money((amount * 100.0).round() as i64)
+    }
+
+    pub fn is_negative(self) -> bool {
+        self.0 < 0
+    }
+
+    pub fn abs(self) -> Self {
+        Money(self.0.abs())
+    }
+
+    /// Divides by `divisor`, rounding to the nearest cent.
+    pub fn div_round(self, divisor: u32) -> Self {
+        Money(round_div(self.0 as i128, divisor as i128) as i64)
+    }
+}
+
+/// Integer division rounding half away from zero.
+fn round_div(numerator: i128, denominator: i128) -> i128 {
+    let quotient = numerator / denominator;
+    let remainder = numerator % denominator;
+    if remainder.abs() * 2 >= denominator.abs() {
+        quotient + numerator.signum() * denominator.signum()
+    } else {
+        quotient
+    }
+}
+
+impl fmt::Display for Money {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let sign = if self.0 < 0 { "-" } else { "" };
+        let cents = self.0.unsigned_abs();
+        f.pad(&format!("{}{}.{:02}", sign, cents / 100, cents % 100))
+    }
+}
+
+impl FromStr for Money {
+    type Err = String;
+
+    /// Parses amounts such as `12`, `12.5`, `-3.10` or `$1200.50`.
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("'{}' is not a valid amount", input);
+        let trimmed = input.trim();
+        let (negative, unsigned) = match trimmed.strip_prefix('-') {
+            Some(rest) => (true, rest),
+            None => (false, trimmed),
+        };
+        let unsigned = unsigned.strip_prefix('$').unwrap_or(unsigned);
+        let (whole, fraction) = unsigned.split_once('.').unwrap_or((unsigned, ""));
+
+        let all_digits = |s: &str| s.chars().all(|c| c.is_ascii_digit());
+        if (whole.is_empty() && fraction.is_empty()) || !all_digits(whole) || !all_digits(fraction)
+        {
+            return Err(invalid());
+        }
+
+        let whole: i128 = if whole.is_empty() {
+            0
+        } else {
+            whole.parse().map_err(|_| invalid())?
+        };
+        let scale = 10i128
+            .checked_pow(fraction.len() as u32)
+            .ok_or_else(invalid)?;
+        let fraction: i128 = if fraction.is_empty() {
+            0
+        } else {
+            fraction.parse().map_err(|_| invalid())?
+        };
+
+        let cents = round_div(
+            whole
+                .checked_mul(scale)
+                .and_then(|w| w.checked_add(fraction))
+                .and_then(|v| v.checked_mul(100))
+                .ok_or_else(invalid)?,
+            scale,
+        );
+        let cents = if negative { -cents } else { cents };
+        i64::try_from(cents).map(Money).map_err(|_| invalid())
+    }
+}
+
+impl Add for Money {
+    type Output = Money;
+
+    fn add(self, other: Money) -> Money {
+        Money(self.0 + other.0)
+    }
+}
+
+impl AddAssign for Money {
+    fn add_assign(&mut self, other: Money) {
+        self.0 += other.0;
+    }
+}
+
+impl Sub for Money {
+    type Output = Money;
+
+    fn sub(self, other: Money) -> Money {
+        Money(self.0 - other.0)
+    }
+}
+
+impl SubAssign for Money {
+    fn sub_assign(&mut self, other: Money) {
+        self.0 -= other.0;
+    }
+}
+
+impl Neg for Money {
+    type Output = Money;
+
+    fn neg(self) -> Money {
+        Money(-self.0)
+    }
+}
+
+/// Unit price times quantity, which is always exact.
+impl Mul<u32> for Money {
+    type Output = Money;
+
+    fn mul(self, quantity: u32) -> Money {
+        Money(self.0 * quantity as i64)
+    }
+}
+
+impl Sum for Money {
+    fn sum<I: Iterator<Item = Money>>(iter: I) -> Money {
+        iter.fold(Money::ZERO, Add::add)
+    }
+}
+
+impl<'a> Sum<&'a Money> for Money {
+    fn sum<I: Iterator<Item = &'a Money>>(iter: I) -> Money {
+        iter.copied().sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn money(input: &str) -> Money {
+        input.parse().unwrap()
+    }
+
+    #[test]
+    fn parses_amounts() {
+        assert_eq!(money("12"), Money::from_cents(1200));
+        assert_eq!(money("12.5"), Money::from_cents(1250));
+        assert_eq!(money("$1200.50"), Money::from_cents(120050));
+        assert_eq!(money(".99"), Money::from_cents(99));
+        assert_eq!(money("-3.10"), Money::from_cents(-310));
+    }
+
+    #[test]
+    fn rejects_invalid_amounts() {
+        for input in ["", "abc", "1.2.3", "1,50", "--1", "."] {
+            assert!(input.parse::<Money>().is_err(), "accepted '{}'", input);
+        }
+    }
+
+    #[test]
+    fn rounds_half_away_from_zero() {
+        assert_eq!(money("0.004"), Money::from_cents(0));
+        assert_eq!(money("0.005"), Money::from_cents(1));
+        assert_eq!(money("2.675"), Money::from_cents(268));
+        assert_eq!(money("-0.005"), Money::from_cents(-1));
+        assert_eq!(Money::from_cents(100).div_round(3), Money::from_cents(33));
+        assert_eq!(Money::from_cents(5).div_round(2), Money::from_cents(3));
+        assert_eq!(Money::from_cents(-5).div_round(2), Money::from_cents(-3));
+        assert_eq!(Money::from_f64(19.999), Money::from_cents(2000));
+    }
+
+    #[test]
+    fn formats_with_two_decimals() {
+        assert_eq!(Money::from_cents(120050).to_string(), "1200.50");
+        assert_eq!(Money::from_cents(-5).to_string(), "-0.05");
+        assert_eq!(format!("{:>8}", Money::from_cents(100)), "    1.00");
+    }
+
+    #[test]
+    fn sums_without_drift() {
+        let total: Money = std::iter::repeat_n(money("0.10"), 10_000).sum();
+        assert_eq!(total, Money::from_cents(100_000));
+        assert_eq!(money("19.99") * 3, money("59.97"));
+    }
+}
@@ -0,0 +1,332 @@
+//! Listings and reports as text for people, or as JSON, CSV, Markdown or HTML
+//! for other tools.
+
+use std::fmt;
+use std::str::FromStr;
+
+use serde_json::{Map, Value};
+
+use crate::util;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// Aligned columns
+    Table,
+    /// One block of `Header: value` lines per row
+    List,
+    /// A section as an object with its rows and totals; several sections as
+    /// an array of them
+    Json,
+    /// Each section's header and rows, then its totals as label/value rows,
+    /// with a blank line between sections
+    Csv,
+    Markdown,
+    /// A standalone page with a table per section
+    Html,
+}
+
+impl fmt::Display for OutputFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(match self {
+            OutputFormat::Table => "table",
+            OutputFormat::List => "list",
+            OutputFormat::Json => "json",
+            OutputFormat::Csv => "csv",
+            OutputFormat::Markdown => "markdown",
+            OutputFormat::Html => "html",
+        })
+    }
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        match input.trim().to_ascii_lowercase().as_str() {
+            "table" => Ok(OutputFormat::Table),
+            "list" => Ok(OutputFormat::List),
+            "json" => Ok(OutputFormat::Json),
+            "csv" => Ok(OutputFormat::Csv),
+            "markdown" | "md" => Ok(OutputFormat::Markdown),
+            "html" => Ok(OutputFormat::Html),
+            _ => Err(format!(
+                "'{}' is not an output format. Use table, list, json, csv, markdown or html.",
+                input.trim()
+            )),
+        }
+    }
+}
+
+/// A titled block of rows under column headers, with totals below it. A
+/// section without headers only has totals.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Section {
+    pub title: String,
+    pub headers: Vec<String>,
+    pub rows: Vec<Vec<String>>,
+    pub totals: Vec<(String, String)>,
+}
+
+impl Section {
+    pub fn new(title: &str, headers: &[&str], rows: Vec<Vec<String>>) -> Self {
+        Self {
+            title: title.to_string(),
+            headers: headers.iter().map(|header| header.to_string()).collect(),
+            rows,
+            totals: Vec::new(),
+        }
+    }
+
+    pub fn with_total(mut self, label: &str, value: impl ToString) -> Self {
+        self.totals.push((label.to_string(), value.to_string()));
+        self
+    }
+}
+
+/// Renders `sections` in `format`.
+pub fn render(format: OutputFormat, sections: &[Section]) -> String {
+    match format {
+        OutputFormat::Table | OutputFormat::List => render_text(format, sections),
+        OutputFormat::Json => render_json(sections),
+        OutputFormat::Csv => render_csv(sections),
+        OutputFormat::Markdown => render_markdown(sections),
+        OutputFormat::Html => render_html(sections),
+    }
+}
+
+fn render_text(format: OutputFormat, sections: &[Section]) -> String {
+    let mut text = String::new();
+    for section in sections {
+        let heading = format!("--- {} ---", section.title);
+        text.push_str(&format!("\n{}\n{}\n", heading, "-".repeat(heading.len())));
+        if !section.headers.is_empty() {
+            let headers = section.headers.iter().map(String::as_str).collect();
+            text.push_str(&if format == OutputFormat::List {
+                util::format_list(headers, section.rows.clone())
+            } else {
+                util::format_table(headers, section.rows.clone())
+            });
+        }
+        for (label, value) in &section.totals {
+            text.push_str(&format!("{}: {}\n", label, value));
+        }
+    }
+    text
+}
+
+fn render_json(sections: &[Section]) -> String {
+    let mut values: Vec<Value> = sections
+        .iter()
+        .map(|section| {
+            let rows = section
+                .rows
+                .iter()
+                .map(|row| {
+                    let fields: Map<String, Value> = section
+                        .headers
+                        .iter()
+                        .cloned()
+                        .zip(row.iter().cloned().map(Value::String))
+                        .collect();
+                    Value::Object(fields)
+                })
+                .collect();
+            let totals: Map<String, Value> = section
+                .totals
+                .iter()
+                .map(|(label, value)| (label.clone(), Value::String(value.clone())))
+                .collect();
+            let mut object = Map::new();
+            object.insert("title".to_string(), Value::String(section.title.clone()));
+            object.insert("rows".to_string(), Value::Array(rows));
+            object.insert("totals".to_string(), Value::Object(totals));
+            Value::Object(object)
+        })
+        .collect();
+    let value = if values.len() == 1 {
+        values.remove(0)
+    } else {
+        Value::Array(values)
+    };
+    serde_json::to_string_pretty(&value).unwrap_or_default() + "\n"
+}
+
+/// Quotes a CSV field when it holds a comma, quote or line break.
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+fn csv_line<'a>(fields: impl IntoIterator<Item = &'a String>) -> String {
+    let fields: Vec<String> = fields.into_iter().map(|field| csv_field(field)).collect();
+    fields.join(",") + "\n"
+}
+
+fn render_csv(sections: &[Section]) -> String {
+    let blocks: Vec<String> = sections
+        .iter()
+        .map(|section| {
+            let mut block = String::new();
+            if !section.headers.is_empty() {
+                block.push_str(&csv_line(&section.headers));
+                for row in &section.rows {
+                    block.push_str(&csv_line(row));
+                }
+            }
+            for (label, value) in &section.totals {
+                block.push_str(&csv_line([label, value]));
+            }
+            block
+        })
+        .collect();
+    blocks.join("\n")
+}
+
+fn markdown_cell(cell: &str) -> String {
+    cell.replace('|', "\\|").replace('\n', " ")
+}
+
+fn render_markdown(sections: &[Section]) -> String {
+    let line = |cells: &[String]| {
+        let cells: Vec<String> = cells.iter().map(|cell| markdown_cell(cell)).collect();
+        format!("| {} |\n", cells.join(" | "))
+    };
+    let blocks: Vec<String> = sections
+        .iter()
+        .map(|section| {
+            let mut block = format!("## {}\n\n", section.title);
+            if !section.headers.is_empty() {
+                block.push_str(&line(&section.headers));
+                block.push_str(&line(&vec!["---".to_string(); section.headers.len()]));
+                for row in &section.rows {
+                    block.push_str(&line(row));
+                }
+                block.push('\n');
+            }
+            for (label, value) in &section.totals {
+                block.push_str(&format!(
+                    "- **{}:** {}\n",
+                    markdown_cell(label),
+                    markdown_cell(value)
+                ));
+            }
+            block
+        })
+        .collect();
+    blocks.join("\n")
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn render_html(sections: &[Section]) -> String {
+    let title = sections
+        .first()
+        .map(|section| escape_html(&section.title))
+        .unwrap_or_default();
+    let mut html = format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n</head>\n<body>\n",
+        title
+    );
+    for section in sections {
+        html.push_str(&format!("<h2>{}</h2>\n", escape_html(&section.title)));
+        if !section.headers.is_empty() {
+            html.push_str("<table>\n<thead>\n<tr>");
+            for header in &section.headers {
+                html.push_str(&format!("<th>{}</th>", escape_html(header)));
+            }
+            html.push_str("</tr>\n</thead>\n<tbody>\n");
+            for row in &section.rows {
+                html.push_str("<tr>");
+                for cell in row {
+                    html.push_str(&format!("<td>{}</td>", escape_html(cell)));
+                }
+                html.push_str("</tr>\n");
+            }
+            html.push_str("</tbody>\n</table>\n");
+        }
+        if !section.totals.is_empty() {
+            html.push_str("<dl>\n");
+            for (label, value) in &section.totals {
+                html.push_str(&format!(
+                    "<dt>{}</dt><dd>{}</dd>\n",
+                    escape_html(label),
+                    escape_html(value)
+                ));
+            }
+            html.push_str("</dl>\n");
+        }
+    }
+    html.push_str("</body>\n</html>\n");
+    html
+}
+
+/// Asks which format to show a listing in. A blank or unknown answer gives
+/// `None`, for the listing's usual layout.
+pub fn ask_format() -> Option<OutputFormat> {
+    let input = util::get_user_input(
+        "Output format: table, list, json, csv, markdown or html (leave blank for the usual layout): ",
+    );
+    if input.is_empty() {
+        return None;
+    }
+    input
+        .parse()
+        .map_err(|e| println!("{} Using the usual layout.", e))
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sections() -> Vec<Section> {
+        vec![
+            Section::new(
+                "Sales",
+                &["SKU", "Product"],
+                vec![vec!["WID-001".to_string(), "Widget, \"large\"".to_string()]],
+            ),
+            Section::new("Totals", &[], Vec::new()).with_total("Total Sales", "$20.00"),
+        ]
+    }
+
+    #[test]
+    fn renders_sections_for_other_tools() {
+        assert_eq!(
+            render(OutputFormat::Csv, &sections()),
+            "SKU,Product\nWID-001,\"Widget, \"\"large\"\"\"\n\nTotal Sales,$20.00\n"
+        );
+        assert_eq!(
+            render(OutputFormat::Markdown, &sections()[..1]),
+            "## Sales\n\n| SKU | Product |\n| --- | --- |\n| WID-001 | Widget, \"large\" |\n\n"
+        );
+
+        let json: Value = serde_json::from_str(&render(OutputFormat::Json, &sections())).unwrap();
+        assert_eq!(json[0]["rows"][0]["Product"], "Widget, \"large\"");
+        assert_eq!(json[1]["totals"]["Total Sales"], "$20.00");
+        let json: Value =
+            serde_json::from_str(&render(OutputFormat::Json, &sections()[..1])).unwrap();
+        assert_eq!(json["title"], "Sales");
+    }
+
+    #[test]
+    fn renders_text_as_a_table_or_list() {
+        let table = render(OutputFormat::Table, &sections());
+        assert!(table.contains("SKU     Product"));
+        assert!(table.ends_with("Total Sales: $20.00\n"));
+        assert!(render(OutputFormat::List, &sections()).contains("  Product: Widget, \"large\"\n"));
+        assert_eq!("MD".parse(), Ok(OutputFormat::Markdown));
+        let html = render(OutputFormat::Html, &sections());
+        assert!(html.contains("<tr><td>WID-001</td><td>Widget, &quot;large&quot;</td></tr>"));
+        assert!(html.contains("<dt>Total Sales</dt><dd>$20.00</dd>"));
+        assert!("xml".parse::<OutputFormat>().is_err());
+    }
+}
@@ -0,0 +1,107 @@
+//! Rules new passwords have to follow.
+
+/// Passwords too common to allow, one per line, lowercase.
+const COMMON_PASSWORDS: &str = include_str!("common_passwords.txt");
+
+/// What a new password must satisfy. Every rule that fails is reported at
+/// once, so the user can fix them all in one go.
+#[derive(Debug, Clone, Copy)]
+pub struct PasswordPolicy {
+    pub min_length: usize,
+    pub require_lowercase: bool,
+    pub require_uppercase: bool,
+    pub require_digit: bool,
+    /// Anything that is not a letter or a digit
+    pub require_symbol: bool,
+    /// Refuse passwords that contain the username
+    pub reject_username: bool,
+    /// Refuse passwords on the bundled list of common passwords
+    pub reject_common: bool,
+}
+
+impl Default for PasswordPolicy {
+    fn default() -> Self {
+        Self {
+            min_length: 8,
+            require_lowercase: true,
+            require_uppercase: true,
+            require_digit: true,
+            require_symbol: false,
+            reject_username: true,
+            reject_common: true,
+        }
+    }
+}
+
+impl PasswordPolicy {
+    /// Checks `password` for the account `username`, listing every rule it
+    /// breaks in the error.
+    pub fn check(&self, username: &str, password: &str) -> Result<(), String> {
+        let mut failures = Vec::new();
+        if password.chars().count() < self.min_length {
+            failures.push(format!("be at least {} characters long", self.min_length));
+        }
+        if self.require_lowercase && !password.chars().any(char::is_lowercase) {
+            failures.push("contain a lowercase letter".to_string());
+        }
+        if self.require_uppercase && !password.chars().any(char::is_uppercase) {
+            failures.push("contain an uppercase letter".to_string());
+        }
+        if self.require_digit && !password.chars().any(|c| c.is_ascii_digit()) {
+            failures.push("contain a digit".to_string());
+        }
+        if self.require_symbol && password.chars().all(char::is_alphanumeric) {
+            failures.push("contain a symbol".to_string());
+        }
+
+        let lowercase = password.to_lowercase();
+        let username = username.trim().to_lowercase();
+        if self.reject_username && !username.is_empty() && lowercase.contains(&username) {
+            failures.push("not contain the username".to_string());
+        }
+        if self.reject_common && COMMON_PASSWORDS.lines().any(|common| common == lowercase) {
+            failures.push("not be a commonly used password".to_string());
+        }
+
+        if failures.is_empty() {
+            Ok(())
+        } else {
+            Err(format!("The password must {}.", failures.join("; ")))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lists_every_rule_that_fails() {
+        let policy = PasswordPolicy::default();
+        assert_eq!(
+            policy.check("till", "till"),
+            Err("The password must be at least 8 characters long; contain an uppercase letter; contain a digit; not contain the username.".to_string())
+        );
+        assert_eq!(
+            policy.check("till", "Password1"),
+            Err("The password must not be a commonly used password.".to_string())
+        );
+        assert!(policy.check("till", "Shelf-Stock-42").is_ok());
+    }
+
+    #[test]
+    fn rules_can_be_relaxed_or_tightened() {
+        let policy = PasswordPolicy {
+            min_length: 4,
+            require_uppercase: false,
+            require_digit: false,
+            require_symbol: true,
+            ..PasswordPolicy::default()
+        };
+        assert_eq!(
+            policy.check("till", "shop"),
+            Err("The password must contain a symbol.".to_string())
+        );
+        assert!(policy.check("till", "sh@p").is_ok());
+    }
+}
@@ -0,0 +1,144 @@
+//! Roles store users hold and what each role is allowed to do.
+
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    /// Runs the store, including users and settings
+    Admin,
+    /// Manages stock, purchases and corrections, and sees the books
+    Manager,
+    /// Serves customers at the till
+    Cashier,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Permission {
+    ViewInventory,
+    EditProducts,
+    DeleteProducts,
+    RecordSales,
+    RecordReturns,
+    RecordPurchases,
+    ViewTransactions,
+    /// Reports, including the profit/loss summary
+    ViewReports,
+    VoidTransactions,
+    /// Counting the till and locking the business day with a Z-report
+    CloseDay,
+    ConfigureCosting,
+    ManageUsers,
+    /// Who changed what, across the whole store
+    ViewAuditLog,
+    /// The user's own password and two-factor login
+    ManageOwnAccount,
+}
+
+const CASHIER: &[Permission] = &[
+    Permission::ViewInventory,
+    Permission::RecordSales,
+    Permission::RecordReturns,
+    Permission::ManageOwnAccount,
+];
+
+const MANAGER: &[Permission] = &[
+    Permission::ViewInventory,
+    Permission::EditProducts,
+    Permission::DeleteProducts,
+    Permission::RecordSales,
+    Permission::RecordReturns,
+    Permission::RecordPurchases,
+    Permission::ViewTransactions,
+    Permission::ViewReports,
+    Permission::VoidTransactions,
+    Permission::CloseDay,
+    Permission::ManageOwnAccount,
+];
+
+const ADMIN: &[Permission] = &[
+    Permission::ViewInventory,
+    Permission::EditProducts,
+    Permission::DeleteProducts,
+    Permission::RecordSales,
+    Permission::RecordReturns,
+    Permission::RecordPurchases,
+    Permission::ViewTransactions,
+    Permission::ViewReports,
+    Permission::VoidTransactions,
+    Permission::CloseDay,
+    Permission::ConfigureCosting,
+    Permission::ManageUsers,
+    Permission::ViewAuditLog,
+    Permission::ManageOwnAccount,
+];
+
+impl Role {
+    pub fn permissions(self) -> &'static [Permission] {
+        match self {
+            Role::Admin => ADMIN,
+            Role::Manager => MANAGER,
+            Role::Cashier => CASHIER,
+        }
+    }
+
+    pub fn can(self, permission: Permission) -> bool {
+        self.permissions().contains(&permission)
+    }
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(match self {
+            Role::Admin => "admin",
+            Role::Manager => "manager",
+            Role::Cashier => "cashier",
+        })
+    }
+}
+
+impl FromStr for Role {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        match input.trim().to_ascii_lowercase().as_str() {
+            "admin" => Ok(Role::Admin),
+            "manager" => Ok(Role::Manager),
+            "cashier" => Ok(Role::Cashier),
+            _ => Err(format!(
+                "'{}' is not a role. Use admin, manager or cashier.",
+                input.trim()
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cashier_sells_but_cannot_delete_or_see_profit() {
+        assert!(Role::Cashier.can(Permission::RecordSales));
+        assert!(!Role::Cashier.can(Permission::DeleteProducts));
+        assert!(!Role::Cashier.can(Permission::ViewReports));
+    }
+
+    #[test]
+    fn each_role_extends_the_one_below() {
+        for permission in Role::Cashier.permissions() {
+            assert!(Role::Manager.can(*permission));
+        }
+        for permission in Role::Manager.permissions() {
+            assert!(Role::Admin.can(*permission));
+        }
+        assert!(!Role::Manager.can(Permission::ManageUsers));
+    }
+
+    #[test]
+    fn parses_role_names() {
+        assert_eq!("Manager".parse(), Ok(Role::Manager));
+        assert_eq!(Role::Cashier.to_string().parse(), Ok(Role::Cashier));
+        assert!("owner".parse::<Role>().is_err());
+    }
+}
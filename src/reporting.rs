@@ -1,134 +1,1108 @@
+//! Reports as data: each `*_report` function returns a typed report with its
+//! rows and totals, and `render` turns any of them into text, JSON, CSV,
+//! Markdown or HTML.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::str::FromStr;
+
+use chrono::{DateTime, Datelike, Duration, Local, NaiveDate, Utc};
+use serde::{Serialize, Serializer};
+use serde_json::Value;
+
+use super::costing::CostingMethod;
 use super::inventory::Inventory;
+use super::money::Money;
+use super::output::{self, OutputFormat, Section};
 use super::transaction::TransactionManager;
 use super::util;
 
-pub fn generate_reports(transactions: &TransactionManager, inventory: &Inventory, ) {
+pub fn generate_reports(transactions: &TransactionManager, inventory: &Inventory) {
     println!("\n> Generate Report\n");
 
     // Prompt user to choose a specific report or show all
-    let choice = util::get_user_input("Choose a report: \n 1. Inventory, \n 2. Sales, \n 3. Purchases, \n (Leave blank ). All: ");
-
-    match choice.as_str() {
-        "1" => println!("{}", generate_inventory_report(inventory)),
-        "2" => println!("{}", generate_sales_report(transactions)),
-        "3" => println!("{}", generate_purchase_report(transactions)),
-        _ => {
-            println!("{}", generate_inventory_report(inventory));
-            println!("{}", generate_sales_report(transactions));
-            println!("{}", generate_purchase_report(transactions));
-            println!("{}", generate_profit_loss_summary(transactions));
+    let choice = util::get_user_input(
+        "Choose a report: \n 1. Inventory, \n 2. Sales, \n 3. Purchases, \n 4. Sales by Cashier, \n (Leave blank ). All: ",
+    );
+
+    let report = match choice.as_str() {
+        "1" => Report::Inventory,
+        "2" => Report::Sales,
+        "3" => Report::Purchases,
+        "4" => Report::Cashiers,
+        _ => Report::All,
+    };
+    // The inventory report is a snapshot of the stock now, so has no period
+    let period = if report == Report::Inventory {
+        Period::all()
+    } else {
+        ask_period()
+    };
+    let format = output::ask_format();
+    println!(
+        "{}",
+        generate(report, format, transactions, inventory, period)
+    );
+}
+
+/// Asks which days to report on: a preset, a custom range or all time.
+fn ask_period() -> Period {
+    let choice = util::get_user_input(
+        "Choose a period: \n 1. Today, \n 2. This Week, \n 3. This Month, \n 4. Last Month, \n 5. Custom, \n (Leave blank ). All Time: ",
+    );
+    let preset = match choice.as_str() {
+        "1" => Preset::Today,
+        "2" => Preset::ThisWeek,
+        "3" => Preset::ThisMonth,
+        "4" => Preset::LastMonth,
+        "5" => loop {
+            let from =
+                util::get_date("Enter the first day, YYYY-MM-DD (leave blank for no limit): ");
+            let to = util::get_date("Enter the last day, YYYY-MM-DD (leave blank for no limit): ");
+            match Period::new(from, to) {
+                Ok(period) => return period,
+                Err(e) => println!("{}", e),
+            }
+        },
+        _ => return Period::all(),
+    };
+    preset.period()
+}
+
+/// A report that can be generated on its own, or all the usual ones at once.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Report {
+    Inventory,
+    Sales,
+    Purchases,
+    Cashiers,
+    ProfitLoss,
+    /// Inventory, sales, purchases and the profit/loss summary
+    All,
+}
+
+impl FromStr for Report {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        match input.trim().to_ascii_lowercase().as_str() {
+            "inventory" => Ok(Report::Inventory),
+            "sales" => Ok(Report::Sales),
+            "purchases" => Ok(Report::Purchases),
+            "cashiers" => Ok(Report::Cashiers),
+            "profit-loss" => Ok(Report::ProfitLoss),
+            "all" => Ok(Report::All),
+            _ => Err(format!(
+                "'{}' is not a report. Use inventory, sales, purchases, cashiers, profit-loss or all.",
+                input.trim()
+            )),
         }
     }
 }
 
-/// Generates an inventory report
-fn generate_inventory_report(inventory: &Inventory) -> String {
-    let headers = vec!["Product", "Description", "Price", "Quantity"];
-    let rows: Vec<Vec<String>> = inventory
-        .products
+/// The local days a report covers, both inclusive. An open end is unbounded.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct Period {
+    pub from: Option<NaiveDate>,
+    pub to: Option<NaiveDate>,
+}
+
+impl Period {
+    pub fn new(from: Option<NaiveDate>, to: Option<NaiveDate>) -> Result<Self, String> {
+        if let (Some(from), Some(to)) = (from, to) {
+            if from > to {
+                return Err(format!(
+                    "The period starts on {} after it ends on {}.",
+                    from, to
+                ));
+            }
+        }
+        Ok(Self { from, to })
+    }
+
+    /// Every transaction ever recorded.
+    pub fn all() -> Self {
+        Self::default()
+    }
+
+    /// The transactions recorded in the period.
+    pub fn select(&self, transactions: &TransactionManager) -> TransactionManager {
+        transactions.between(self.from, self.to)
+    }
+
+    /// `title`, followed by the period unless it is all time.
+    fn title(&self, title: &str) -> String {
+        match (self.from, self.to) {
+            (None, None) => title.to_string(),
+            (Some(from), None) => format!("{}, from {}", title, from),
+            (None, Some(to)) => format!("{}, up to {}", title, to),
+            (Some(from), Some(to)) if from == to => format!("{}, {}", title, from),
+            (Some(from), Some(to)) => format!("{}, {} to {}", title, from, to),
+        }
+    }
+}
+
+/// Periods named relative to today. Weeks start on Monday, and the current
+/// week and month run up to today.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Preset {
+    Today,
+    ThisWeek,
+    ThisMonth,
+    LastMonth,
+}
+
+impl Preset {
+    /// The period as of the local date today.
+    pub fn period(self) -> Period {
+        self.period_on(Local::now().date_naive())
+    }
+
+    /// The period as of `today`.
+    pub fn period_on(self, today: NaiveDate) -> Period {
+        let first_of_month = today.with_day(1).unwrap_or(today);
+        let (from, to) = match self {
+            Preset::Today => (today, today),
+            Preset::ThisWeek => (
+                today - Duration::days(today.weekday().num_days_from_monday().into()),
+                today,
+            ),
+            Preset::ThisMonth => (first_of_month, today),
+            Preset::LastMonth => {
+                let last_day = first_of_month.pred_opt().unwrap_or(first_of_month);
+                (last_day.with_day(1).unwrap_or(last_day), last_day)
+            }
+        };
+        Period {
+            from: Some(from),
+            to: Some(to),
+        }
+    }
+}
+
+impl FromStr for Preset {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        match input.trim().to_ascii_lowercase().as_str() {
+            "today" => Ok(Preset::Today),
+            "this-week" => Ok(Preset::ThisWeek),
+            "this-month" => Ok(Preset::ThisMonth),
+            "last-month" => Ok(Preset::LastMonth),
+            _ => Err(format!(
+                "'{}' is not a period. Use today, this-week, this-month or last-month.",
+                input.trim()
+            )),
+        }
+    }
+}
+
+/// Writes amounts as exact decimal strings such as `"12.50"`, so tools
+/// reading the JSON never see rounding.
+pub(crate) fn decimal<S: Serializer>(amount: &Money, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_str(amount)
+}
+
+/// What every report can be turned into.
+pub trait Rendered {
+    /// The report as titled tables with totals, for the text, CSV, Markdown
+    /// and HTML renderers.
+    fn sections(&self) -> Vec<Section>;
+
+    /// The report's fields as JSON.
+    fn to_json(&self) -> Value;
+
+    /// How the report is laid out as text when no format is asked for.
+    fn usual_format(&self) -> OutputFormat {
+        OutputFormat::List
+    }
+}
+
+/// Renders `reports` in `format`: several reports as one JSON array, or one
+/// document with all their sections.
+pub fn render(reports: &[&dyn Rendered], format: OutputFormat) -> String {
+    if format == OutputFormat::Json {
+        let mut values: Vec<Value> = reports.iter().map(|report| report.to_json()).collect();
+        let value = if values.len() == 1 {
+            values.remove(0)
+        } else {
+            Value::Array(values)
+        };
+        return serde_json::to_string_pretty(&value).unwrap_or_default() + "\n";
+    }
+    let sections: Vec<Section> = reports
         .iter()
-        .map(|(_name, product)| {
-            vec![
-                format!("{}", product.name),
-                product.description.clone(),
-                format!("${:.2}", product.price),
-                product.quantity.to_string(),
-            ]
-        })
+        .flat_map(|report| report.sections())
         .collect();
+    output::render(format, &sections)
+}
+
+/// Generates `report` over the transactions in `period`, naming products as
+/// they are in `inventory` now. Without a `format` each report keeps its
+/// usual layout.
+pub fn generate(
+    report: Report,
+    format: Option<OutputFormat>,
+    transactions: &TransactionManager,
+    inventory: &Inventory,
+    period: Period,
+) -> String {
+    let reports: Vec<Box<dyn Rendered>> = match report {
+        Report::Inventory => vec![Box::new(inventory_report(inventory))],
+        Report::Sales => vec![Box::new(sales_report(transactions, inventory, period))],
+        Report::Purchases => vec![Box::new(purchase_report(transactions, inventory, period))],
+        Report::Cashiers => vec![Box::new(cashier_report(transactions, period))],
+        Report::ProfitLoss => vec![Box::new(profit_loss_summary(transactions, period))],
+        Report::All => vec![
+            Box::new(inventory_report(inventory)),
+            Box::new(sales_report(transactions, inventory, period)),
+            Box::new(purchase_report(transactions, inventory, period)),
+            Box::new(profit_loss_summary(transactions, period)),
+        ],
+    };
+    let reports: Vec<&dyn Rendered> = reports.iter().map(|report| report.as_ref()).collect();
+    match format {
+        Some(format) => render(&reports, format),
+        None => reports
+            .iter()
+            .map(|report| render(&[*report], report.usual_format()))
+            .collect(),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct InventoryReport {
+    pub generated_at: DateTime<Utc>,
+    pub costing_method: CostingMethod,
+    pub products: Vec<InventoryLine>,
+    /// What all the stock on hand cost, under the costing method
+    #[serde(serialize_with = "decimal")]
+    pub total_value: Money,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct InventoryLine {
+    pub sku: String,
+    pub name: String,
+    pub description: String,
+    #[serde(serialize_with = "decimal")]
+    pub price: Money,
+    #[serde(serialize_with = "decimal")]
+    pub cost: Money,
+    pub quantity: u32,
+    #[serde(serialize_with = "decimal")]
+    pub value: Money,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SalesReport {
+    pub generated_at: DateTime<Utc>,
+    pub period: Period,
+    pub sales: Vec<SaleLine>,
+    pub returns: Vec<ReturnLine>,
+    #[serde(serialize_with = "decimal")]
+    pub total_sales: Money,
+    #[serde(serialize_with = "decimal")]
+    pub total_refunds: Money,
+    #[serde(serialize_with = "decimal")]
+    pub net_sales: Money,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SaleLine {
+    pub id: u64,
+    pub sku: String,
+    pub product: String,
+    pub quantity: u32,
+    #[serde(serialize_with = "decimal")]
+    pub price: Money,
+    #[serde(serialize_with = "decimal")]
+    pub amount: Money,
+    /// What the units sold cost the store
+    #[serde(serialize_with = "decimal")]
+    pub cost: Money,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ReturnLine {
+    pub id: u64,
+    pub sale_id: u64,
+    pub sku: String,
+    pub product: String,
+    pub quantity: u32,
+    #[serde(serialize_with = "decimal")]
+    pub refund: Money,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PurchaseReport {
+    pub generated_at: DateTime<Utc>,
+    pub period: Period,
+    pub purchases: Vec<PurchaseLine>,
+    #[serde(serialize_with = "decimal")]
+    pub total_purchases: Money,
+}
 
-    let mut report = String::new();
-    report.push_str("\n--- Inventory Report: ---\n");
-    report.push_str("-----------------\n");
-    report.push_str(&util::format_list(headers, rows));
-    report
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PurchaseLine {
+    pub id: u64,
+    pub sku: String,
+    pub product: String,
+    pub quantity: u32,
+    #[serde(serialize_with = "decimal")]
+    pub price: Money,
+    #[serde(serialize_with = "decimal")]
+    pub amount: Money,
 }
 
-/// Generates a sales report
-fn generate_sales_report(transactions: &TransactionManager) -> String {
-    let headers = vec!["Product", "Quantity Sold", "Sale Price", "Amount"];
-    let rows: Vec<Vec<String>> = transactions
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CashierReport {
+    pub generated_at: DateTime<Utc>,
+    pub period: Period,
+    pub cashiers: Vec<CashierLine>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CashierLine {
+    /// The user who rang the sales up, or `(unknown)` for sales from before
+    /// users were tracked
+    pub cashier: String,
+    pub orders: usize,
+    pub units_sold: u32,
+    #[serde(serialize_with = "decimal")]
+    pub sales: Money,
+    #[serde(serialize_with = "decimal")]
+    pub refunds: Money,
+    #[serde(serialize_with = "decimal")]
+    pub net: Money,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ProfitLossSummary {
+    pub generated_at: DateTime<Utc>,
+    pub period: Period,
+    #[serde(serialize_with = "decimal")]
+    pub net_sales: Money,
+    #[serde(serialize_with = "decimal")]
+    pub cost_of_goods_sold: Money,
+    #[serde(serialize_with = "decimal")]
+    pub gross_profit: Money,
+    /// Gross profit as a percentage of net sales, none without sales
+    pub gross_margin: Option<f64>,
+}
+
+/// The stock on hand and what it is worth, by SKU.
+pub fn inventory_report(inventory: &Inventory) -> InventoryReport {
+    let mut products: Vec<_> = inventory.products.values().collect();
+    products.sort_by(|a, b| a.sku.cmp(&b.sku));
+    InventoryReport {
+        generated_at: Utc::now(),
+        costing_method: inventory.costing_method,
+        products: products
+            .into_iter()
+            .map(|product| InventoryLine {
+                sku: product.sku.clone(),
+                name: product.name.clone(),
+                description: product.description.clone(),
+                price: product.price,
+                cost: product.cost,
+                quantity: product.quantity,
+                value: product.stock_value(),
+            })
+            .collect(),
+        total_value: inventory.valuation(),
+    }
+}
+
+/// The sales and returns in `period` that have not been voided.
+pub fn sales_report(
+    transactions: &TransactionManager,
+    inventory: &Inventory,
+    period: Period,
+) -> SalesReport {
+    let transactions = period.select(transactions);
+    let sales: Vec<SaleLine> = transactions
         .sales()
         .iter()
-        .map(|sale| {
-            let amount = sale.price * sale.quantity as f64;
-            vec![
-                format!("{}", sale.product_name),
-                sale.quantity.to_string(),
-                format!("${:.2}", sale.price),
-                format!("${:.2}", amount),
-            ]
+        .map(|sale| SaleLine {
+            id: sale.id,
+            sku: sale.sku.clone(),
+            product: inventory.product_name(&sale.sku).to_string(),
+            quantity: sale.quantity,
+            price: sale.price,
+            amount: sale.total,
+            cost: sale.cost,
         })
         .collect();
-
-    let total_sales: f64 = transactions
-        .sales()
+    let returns: Vec<ReturnLine> = transactions
+        .returns()
         .iter()
-        .map(|sale| sale.price * sale.quantity as f64)
-        .sum();
+        .map(|refund| ReturnLine {
+            id: refund.id,
+            sale_id: refund.reference_id.unwrap_or_default(),
+            sku: refund.sku.clone(),
+            product: inventory.product_name(&refund.sku).to_string(),
+            quantity: refund.quantity,
+            refund: refund.total,
+        })
+        .collect();
 
-    let mut report = String::new();
-    report.push_str("\n--- Sales Report: ---\n");
-    report.push_str("----------------------\n");
-    report.push_str(&util::format_list(headers, rows));
-    report.push_str(&format!("Total Sales: ${:.2}\n", total_sales));
-    report
+    let total_sales: Money = sales.iter().map(|sale| sale.amount).sum();
+    let total_refunds: Money = returns.iter().map(|refund| refund.refund).sum();
+    SalesReport {
+        generated_at: Utc::now(),
+        period,
+        sales,
+        returns,
+        total_sales,
+        total_refunds,
+        net_sales: total_sales - total_refunds,
+    }
 }
 
-/// Generates a purchase report
-fn generate_purchase_report(transactions: &TransactionManager) -> String {
-    let headers = vec!["Product", "Quantity Bought", "Purchase Price", "Amount"];
-    let rows: Vec<Vec<String>> = transactions
+/// The purchases in `period` that have not been voided.
+pub fn purchase_report(
+    transactions: &TransactionManager,
+    inventory: &Inventory,
+    period: Period,
+) -> PurchaseReport {
+    let purchases: Vec<PurchaseLine> = period
+        .select(transactions)
         .purchases()
         .iter()
-        .map(|purchase| {
-            let amount = purchase.price * purchase.quantity as f64;
-            vec![
-                format!("{}", purchase.product_name),
-                purchase.quantity.to_string(),
-                format!("${:.2}", purchase.price),
-                format!("${:.2}", amount),
-            ]
+        .map(|purchase| PurchaseLine {
+            id: purchase.id,
+            sku: purchase.sku.clone(),
+            product: inventory.product_name(&purchase.sku).to_string(),
+            quantity: purchase.quantity,
+            price: purchase.price,
+            amount: purchase.total,
         })
         .collect();
 
-    let total_purchases: f64 = transactions
-        .purchases()
-        .iter()
-        .map(|purchase| purchase.price * purchase.quantity as f64)
-        .sum();
+    PurchaseReport {
+        generated_at: Utc::now(),
+        period,
+        total_purchases: purchases.iter().map(|purchase| purchase.amount).sum(),
+        purchases,
+    }
+}
 
-    let mut report = String::new();
-    report.push_str("\n--- Purchase Report: ---\n");
-    report.push_str("--------------------------\n");
-    report.push_str(&util::format_list(headers, rows));
-    report.push_str(&format!("Total Purchases: ${:.2}\n", total_purchases));
-    report
+/// The sales and refunds each cashier rang up in `period`, by name.
+pub fn cashier_report(transactions: &TransactionManager, period: Period) -> CashierReport {
+    #[derive(Default)]
+    struct Totals {
+        orders: BTreeSet<u64>,
+        units: u32,
+        sales: Money,
+        refunds: Money,
+    }
+
+    let transactions = period.select(transactions);
+    let cashier = |recorded_by: &Option<String>| {
+        recorded_by
+            .clone()
+            .unwrap_or_else(|| "(unknown)".to_string())
+    };
+    let mut totals: BTreeMap<String, Totals> = BTreeMap::new();
+    for sale in transactions.sales() {
+        let entry = totals.entry(cashier(&sale.recorded_by)).or_default();
+        entry.orders.extend(sale.order_id);
+        entry.units += sale.quantity;
+        entry.sales += sale.total;
+    }
+    for refund in transactions.returns() {
+        totals
+            .entry(cashier(&refund.recorded_by))
+            .or_default()
+            .refunds += refund.total;
+    }
+
+    CashierReport {
+        generated_at: Utc::now(),
+        period,
+        cashiers: totals
+            .into_iter()
+            .map(|(cashier, totals)| CashierLine {
+                cashier,
+                orders: totals.orders.len(),
+                units_sold: totals.units,
+                sales: totals.sales,
+                refunds: totals.refunds,
+                net: totals.sales - totals.refunds,
+            })
+            .collect(),
+    }
 }
 
-/// Generates a profit/loss summary
-fn generate_profit_loss_summary(transactions: &TransactionManager) -> String {
-    let total_sales: f64 = transactions
-        .sales()
+/// Profit and loss over `period`. Only the cost of what was actually sold
+/// counts against sales, so stock bought but still on the shelf is no loss.
+pub fn profit_loss_summary(transactions: &TransactionManager, period: Period) -> ProfitLossSummary {
+    let transactions = period.select(transactions);
+    let total_sales: Money = transactions.sales().iter().map(|sale| sale.total).sum();
+    let total_refunds: Money = transactions
+        .returns()
         .iter()
-        .map(|sale| sale.price * sale.quantity as f64)
+        .map(|refund| refund.total)
         .sum();
+    let net_sales = total_sales - total_refunds;
+    let cost_of_goods_sold = transactions.cost_of_goods_sold();
+    let gross_profit = net_sales - cost_of_goods_sold;
 
-    let total_purchases: f64 = transactions
-        .purchases()
-        .iter()
-        .map(|purchase| purchase.price * purchase.quantity as f64)
-        .sum();
+    ProfitLossSummary {
+        generated_at: Utc::now(),
+        period,
+        net_sales,
+        cost_of_goods_sold,
+        gross_profit,
+        gross_margin: (net_sales.cents() > 0)
+            .then(|| gross_profit.cents() as f64 * 100.0 / net_sales.cents() as f64),
+    }
+}
+
+impl Rendered for InventoryReport {
+    fn sections(&self) -> Vec<Section> {
+        let headers = [
+            "SKU",
+            "Product",
+            "Description",
+            "Price",
+            "Cost",
+            "Quantity",
+            "Value",
+        ];
+        let rows = self
+            .products
+            .iter()
+            .map(|line| {
+                vec![
+                    line.sku.clone(),
+                    line.name.clone(),
+                    line.description.clone(),
+                    format!("${}", line.price),
+                    format!("${}", line.cost),
+                    line.quantity.to_string(),
+                    format!("${}", line.value),
+                ]
+            })
+            .collect();
+        vec![Section::new("Inventory Report", &headers, rows).with_total(
+            &format!("Inventory Value ({})", self.costing_method),
+            format!("${}", self.total_value),
+        )]
+    }
+
+    fn to_json(&self) -> Value {
+        serde_json::to_value(self).unwrap_or_default()
+    }
+}
+
+impl Rendered for SalesReport {
+    fn sections(&self) -> Vec<Section> {
+        let headers = [
+            "SKU",
+            "Product",
+            "Quantity Sold",
+            "Sale Price",
+            "Amount",
+            "Cost",
+        ];
+        let rows = self
+            .sales
+            .iter()
+            .map(|line| {
+                vec![
+                    line.sku.clone(),
+                    line.product.clone(),
+                    line.quantity.to_string(),
+                    format!("${}", line.price),
+                    format!("${}", line.amount),
+                    format!("${}", line.cost),
+                ]
+            })
+            .collect();
+        let mut sections = vec![Section::new(
+            &self.period.title("Sales Report"),
+            &headers,
+            rows,
+        )];
+
+        if !self.returns.is_empty() {
+            let headers = ["Sale ID", "SKU", "Product", "Quantity Returned", "Refund"];
+            let rows = self
+                .returns
+                .iter()
+                .map(|line| {
+                    vec![
+                        line.sale_id.to_string(),
+                        line.sku.clone(),
+                        line.product.clone(),
+                        line.quantity.to_string(),
+                        signed(-line.refund),
+                    ]
+                })
+                .collect();
+            sections.push(Section::new("Returns", &headers, rows));
+        }
+
+        sections.push(
+            Section::new("Sales Totals", &[], Vec::new())
+                .with_total("Total Sales", format!("${}", self.total_sales))
+                .with_total("Total Refunds", signed(-self.total_refunds))
+                .with_total("Net Sales", signed(self.net_sales)),
+        );
+        sections
+    }
+
+    fn to_json(&self) -> Value {
+        serde_json::to_value(self).unwrap_or_default()
+    }
+}
+
+impl Rendered for PurchaseReport {
+    fn sections(&self) -> Vec<Section> {
+        let headers = [
+            "SKU",
+            "Product",
+            "Quantity Bought",
+            "Purchase Price",
+            "Amount",
+        ];
+        let rows = self
+            .purchases
+            .iter()
+            .map(|line| {
+                vec![
+                    line.sku.clone(),
+                    line.product.clone(),
+                    line.quantity.to_string(),
+                    format!("${}", line.price),
+                    format!("${}", line.amount),
+                ]
+            })
+            .collect();
+        vec![
+            Section::new(&self.period.title("Purchase Report"), &headers, rows)
+                .with_total("Total Purchases", format!("${}", self.total_purchases)),
+        ]
+    }
+
+    fn to_json(&self) -> Value {
+        serde_json::to_value(self).unwrap_or_default()
+    }
+}
+
+impl Rendered for CashierReport {
+    fn sections(&self) -> Vec<Section> {
+        let headers = ["Cashier", "Orders", "Units Sold", "Sales", "Refunds", "Net"];
+        let rows = self
+            .cashiers
+            .iter()
+            .map(|line| {
+                vec![
+                    line.cashier.clone(),
+                    line.orders.to_string(),
+                    line.units_sold.to_string(),
+                    format!("${}", line.sales),
+                    signed(-line.refunds),
+                    signed(line.net),
+                ]
+            })
+            .collect();
+        vec![Section::new(
+            &self.period.title("Sales by Cashier"),
+            &headers,
+            rows,
+        )]
+    }
+
+    fn to_json(&self) -> Value {
+        serde_json::to_value(self).unwrap_or_default()
+    }
+
+    fn usual_format(&self) -> OutputFormat {
+        OutputFormat::Table
+    }
+}
+
+impl Rendered for ProfitLossSummary {
+    fn sections(&self) -> Vec<Section> {
+        let margin = self
+            .gross_margin
+            .map_or_else(|| "n/a".to_string(), |margin| format!("{:.1}%", margin));
+        vec![
+            Section::new(&self.period.title("Profit and Loss"), &[], Vec::new())
+                .with_total("Net Sales", signed(self.net_sales))
+                .with_total("Cost of Goods Sold", signed(self.cost_of_goods_sold))
+                .with_total("Gross Profit", signed(self.gross_profit))
+                .with_total("Gross Margin", margin),
+        ]
+    }
+
+    fn to_json(&self) -> Value {
+        serde_json::to_value(self).unwrap_or_default()
+    }
 
-    let profit_or_loss = total_sales - total_purchases;
-
-    let mut summary = String::new();
-    summary.push_str("===============================\n");
-    summary.push_str(&format!(
-        "Total Profit/Loss: {}${:.2}\n",
-        if profit_or_loss < 0.0 { "-" } else { "" },
-        profit_or_loss.abs()
-    ));
-    summary.push_str("===============================\n");
-    summary
+    fn usual_format(&self) -> OutputFormat {
+        OutputFormat::Table
+    }
+}
+
+/// Formats an amount with the sign ahead of the dollar sign, as in `-$5.00`.
+pub(crate) fn signed(amount: Money) -> String {
+    if amount.is_negative() {
+        format!("-${}", amount.abs())
+    } else {
+        format!("${}", amount)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::User;
+    use crate::costing::CostingMethod;
+    use crate::inventory::Product;
+    use crate::permission::Role;
+    use crate::session::Session;
+    use crate::transaction::PaymentMethod;
+
+    fn session_for(username: &str) -> Session {
+        Session::new(User {
+            username: username.to_string(),
+            role: Role::Cashier,
+        })
+    }
+
+    fn session() -> Session {
+        session_for("till")
+    }
+
+    #[test]
+    fn nets_refunds_out_of_sales() {
+        let mut inventory = Inventory::new();
+        inventory.products.insert(
+            "WID-001".to_string(),
+            Product {
+                sku: "WID-001".to_string(),
+                name: "Widget".to_string(),
+                description: "A test widget".to_string(),
+                price: Money::from_cents(1000),
+                cost: Money::from_cents(600),
+                quantity: 10,
+                layers: Vec::new(),
+            },
+        );
+        let mut transactions = TransactionManager::new();
+        transactions
+            .record_sale(
+                &session(),
+                &mut inventory,
+                "WID-001",
+                5,
+                Money::from_cents(1000),
+                PaymentMethod::Cash,
+            )
+            .unwrap();
+        transactions
+            .record_return(&session(), &mut inventory, 1, 2, false)
+            .unwrap();
+
+        let report = sales_report(&transactions, &inventory, Period::all());
+
+        assert_eq!(report.total_sales, Money::from_cents(5000));
+        assert_eq!(report.total_refunds, Money::from_cents(2000));
+        assert_eq!(report.net_sales, Money::from_cents(3000));
+        assert_eq!(
+            (report.returns[0].sale_id, report.returns[0].quantity),
+            (1, 2)
+        );
+
+        let summary = profit_loss_summary(&transactions, Period::all());
+        assert_eq!(summary.net_sales, Money::from_cents(3000));
+        assert_eq!(summary.cost_of_goods_sold, Money::from_cents(1800)); // 3 kept at $6.00
+        assert_eq!(summary.gross_profit, Money::from_cents(1200));
+        assert_eq!(summary.gross_margin, Some(40.0));
+    }
+
+    #[test]
+    fn leaves_voided_transactions_out() {
+        let mut inventory = Inventory::new();
+        inventory.products.insert(
+            "WID-001".to_string(),
+            Product {
+                sku: "WID-001".to_string(),
+                name: "Widget".to_string(),
+                description: "A test widget".to_string(),
+                price: Money::from_cents(1000),
+                cost: Money::from_cents(600),
+                quantity: 10,
+                layers: Vec::new(),
+            },
+        );
+        let mut transactions = TransactionManager::new();
+        transactions
+            .record_sale(
+                &session(),
+                &mut inventory,
+                "WID-001",
+                5,
+                Money::from_cents(1000),
+                PaymentMethod::Cash,
+            )
+            .unwrap();
+        transactions
+            .record_sale(
+                &session(),
+                &mut inventory,
+                "WID-001",
+                3,
+                Money::from_cents(1000),
+                PaymentMethod::Cash,
+            )
+            .unwrap();
+        transactions
+            .void_transaction(&session(), &mut inventory, 2)
+            .unwrap();
+
+        let report = sales_report(&transactions, &inventory, Period::all());
+
+        assert_eq!(report.sales.len(), 1);
+        assert_eq!(report.total_sales, Money::from_cents(5000));
+        assert_eq!(
+            profit_loss_summary(&transactions, Period::all()).gross_profit,
+            Money::from_cents(2000)
+        );
+    }
+
+    #[test]
+    fn values_inventory_under_the_costing_method() {
+        let mut inventory = Inventory::new();
+        inventory.products.insert(
+            "WID-001".to_string(),
+            Product {
+                sku: "WID-001".to_string(),
+                name: "Widget".to_string(),
+                description: "A test widget".to_string(),
+                price: Money::from_cents(1000),
+                cost: Money::from_cents(400),
+                quantity: 10,
+                layers: Vec::new(),
+            },
+        );
+        let mut transactions = TransactionManager::new();
+        transactions
+            .record_purchase(
+                &session(),
+                &mut inventory,
+                "WID-001",
+                10,
+                Money::from_cents(600),
+            )
+            .unwrap();
+        inventory.set_costing_method(CostingMethod::WeightedAverage);
+
+        let report = inventory_report(&inventory);
+
+        assert_eq!(report.costing_method, CostingMethod::WeightedAverage);
+        assert_eq!(report.total_value, Money::from_cents(10000));
+        assert!(render(&[&report], OutputFormat::Table)
+            .contains("Inventory Value (Weighted Average): $100.00"));
+    }
+
+    #[test]
+    fn totals_sales_per_cashier() {
+        let mut inventory = Inventory::new();
+        inventory.products.insert(
+            "WID-001".to_string(),
+            Product {
+                sku: "WID-001".to_string(),
+                name: "Widget".to_string(),
+                description: "A test widget".to_string(),
+                price: Money::from_cents(1000),
+                cost: Money::from_cents(600),
+                quantity: 10,
+                layers: Vec::new(),
+            },
+        );
+        let mut transactions = TransactionManager::new();
+        let ann = session_for("ann");
+        transactions
+            .record_sale(
+                &ann,
+                &mut inventory,
+                "WID-001",
+                2,
+                Money::from_cents(1000),
+                PaymentMethod::Cash,
+            )
+            .unwrap();
+        transactions
+            .record_sale(
+                &ann,
+                &mut inventory,
+                "WID-001",
+                1,
+                Money::from_cents(1000),
+                PaymentMethod::Cash,
+            )
+            .unwrap();
+        transactions
+            .record_sale(
+                &session(),
+                &mut inventory,
+                "WID-001",
+                4,
+                Money::from_cents(1000),
+                PaymentMethod::Cash,
+            )
+            .unwrap();
+        transactions
+            .record_return(&session(), &mut inventory, 1, 1, false)
+            .unwrap();
+
+        let report = cashier_report(&transactions, Period::all());
+        let cashiers: Vec<(&str, usize, u32, i64, i64)> = report
+            .cashiers
+            .iter()
+            .map(|line| {
+                (
+                    line.cashier.as_str(),
+                    line.orders,
+                    line.units_sold,
+                    line.sales.cents(),
+                    line.net.cents(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            cashiers,
+            vec![("ann", 2, 3, 3000, 3000), ("till", 1, 4, 4000, 3000)]
+        );
+
+        let report = render(&[&report], OutputFormat::Table);
+        let line = |cashier: &str| {
+            report
+                .lines()
+                .find(|line| line.starts_with(cashier))
+                .unwrap()
+                .split_whitespace()
+                .collect::<Vec<_>>()
+                .join(" ")
+        };
+
+        assert_eq!(line("ann"), "ann 2 3 $30.00 $0.00 $30.00");
+        assert_eq!(line("till"), "till 1 4 $40.00 -$10.00 $30.00");
+    }
+
+    #[test]
+    fn unsold_stock_is_not_a_loss() {
+        let mut inventory = Inventory::new();
+        inventory.products.insert(
+            "WID-001".to_string(),
+            Product {
+                sku: "WID-001".to_string(),
+                name: "Widget".to_string(),
+                description: "A test widget".to_string(),
+                price: Money::from_cents(1000),
+                cost: Money::from_cents(600),
+                quantity: 0,
+                layers: Vec::new(),
+            },
+        );
+        let mut transactions = TransactionManager::new();
+        transactions
+            .record_purchase(
+                &session(),
+                &mut inventory,
+                "WID-001",
+                100,
+                Money::from_cents(400),
+            )
+            .unwrap();
+        transactions
+            .record_sale(
+                &session(),
+                &mut inventory,
+                "WID-001",
+                2,
+                Money::from_cents(1000),
+                PaymentMethod::Cash,
+            )
+            .unwrap();
+
+        let summary = profit_loss_summary(&transactions, Period::all());
+
+        assert_eq!(summary.cost_of_goods_sold, Money::from_cents(800));
+        assert_eq!(summary.gross_profit, Money::from_cents(1200));
+        assert_eq!(summary.gross_margin, Some(60.0));
+        assert!(render(&[&summary], OutputFormat::Table).contains("Gross Margin: 60.0%"));
+    }
+
+    #[test]
+    fn presets_count_from_today() {
+        let today = NaiveDate::from_ymd_opt(2025, 3, 13).unwrap(); // A Thursday
+        let day = |month, day| Some(NaiveDate::from_ymd_opt(2025, month, day).unwrap());
+        let period = |preset: Preset| {
+            let period = preset.period_on(today);
+            (period.from, period.to)
+        };
+
+        assert_eq!(period(Preset::Today), (day(3, 13), day(3, 13)));
+        assert_eq!(period(Preset::ThisWeek), (day(3, 10), day(3, 13)));
+        assert_eq!(period(Preset::ThisMonth), (day(3, 1), day(3, 13)));
+        assert_eq!(period(Preset::LastMonth), (day(2, 1), day(2, 28)));
+        let january = Preset::LastMonth.period_on(NaiveDate::from_ymd_opt(2025, 1, 31).unwrap());
+        assert_eq!(january.from, NaiveDate::from_ymd_opt(2024, 12, 1));
+        assert_eq!("This-Week".parse(), Ok(Preset::ThisWeek));
+        assert!("yesterday".parse::<Preset>().is_err());
+    }
+
+    #[test]
+    fn renders_reports_as_json_and_html() {
+        let mut inventory = Inventory::new();
+        inventory.products.insert(
+            "WID-001".to_string(),
+            Product {
+                sku: "WID-001".to_string(),
+                name: "Widget".to_string(),
+                description: "A test widget".to_string(),
+                price: Money::from_cents(1000),
+                cost: Money::from_cents(600),
+                quantity: 10,
+                layers: Vec::new(),
+            },
+        );
+        let mut transactions = TransactionManager::new();
+        transactions
+            .record_sale(
+                &session(),
+                &mut inventory,
+                "WID-001",
+                3,
+                Money::from_cents(1250),
+                PaymentMethod::Cash,
+            )
+            .unwrap();
+        let today = chrono::Local::now().date_naive();
+        let report = sales_report(
+            &transactions,
+            &inventory,
+            Period::new(Some(today), None).unwrap(),
+        );
+
+        let json: Value = serde_json::from_str(&render(&[&report], OutputFormat::Json)).unwrap();
+        assert_eq!(json["total_sales"], "37.50");
+        assert_eq!(json["sales"][0]["price"], "12.50");
+        assert_eq!(json["period"]["from"], today.to_string());
+
+        let html = render(&[&report], OutputFormat::Html);
+        assert!(html.contains(&format!("<h2>Sales Report, from {}</h2>", today)));
+        assert!(html.contains("<dt>Net Sales</dt><dd>$37.50</dd>"));
+
+        let later = Period::new(Some(today.succ_opt().unwrap()), None).unwrap();
+        assert!(sales_report(&transactions, &inventory, later)
+            .sales
+            .is_empty());
+        assert!(Period::new(Some(today), today.pred_opt()).is_err());
+    }
 }
@@ -0,0 +1,31 @@
+//! Who is operating the store right now.
+
+use chrono::{DateTime, Utc};
+
+use crate::auth::User;
+use crate::permission::Role;
+
+/// The logged-in user, carried into every action so the records it creates
+/// can name who made them.
+#[derive(Debug, Clone)]
+pub struct Session {
+    pub user: User,
+    pub started_at: DateTime<Utc>,
+}
+
+impl Session {
+    pub fn new(user: User) -> Self {
+        Self {
+            user,
+            started_at: Utc::now(),
+        }
+    }
+
+    pub fn username(&self) -> &str {
+        &self.user.username
+    }
+
+    pub fn role(&self) -> Role {
+        self.user.role
+    }
+}
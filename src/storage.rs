@@ -0,0 +1,203 @@
+mod json;
+mod memory;
+mod sqlite;
+
+pub use json::JsonStorage;
+pub use memory::MemoryStorage;
+pub use sqlite::SqliteStorage;
+
+use crate::audit::AuditLog;
+use crate::inventory::Inventory;
+use crate::transaction::TransactionManager;
+
+/// A place the store can load its inventory, transaction history and audit
+/// log from and save them back to. `Store` is generic over this trait so the domain code
+/// does not care whether data lives in SQLite, JSON files or memory.
+#[allow(async_fn_in_trait)]
+pub trait Storage {
+    async fn load_inventory(&self) -> Result<Inventory, String>;
+    /// Stores the current contents of `inventory`. Backends shared between
+    /// sessions refuse to overwrite products another session changed since
+    /// they were loaded.
+    async fn save_inventory(&self, inventory: &Inventory) -> Result<(), String>;
+    async fn load_transactions(&self) -> Result<TransactionManager, String>;
+    async fn save_transactions(
+        &self,
+        transaction_manager: &TransactionManager,
+    ) -> Result<(), String>;
+    async fn load_audit_log(&self) -> Result<AuditLog, String>;
+    /// Stores the entries added to `audit_log` since the last save. Stored
+    /// entries are never changed or removed.
+    async fn save_audit_log(&self, audit_log: &AuditLog) -> Result<(), String>;
+    /// Whether the one-time import of older data has run against this storage.
+    async fn imported(&self) -> Result<bool, String>;
+    /// Records that the one-time import has run, so it never runs again.
+    async fn mark_imported(&self) -> Result<(), String>;
+
+    /// Saves all three. Backends that can should do it atomically; by default
+    /// each part is saved on its own and the failures are collected.
+    async fn save(
+        &self,
+        inventory: &Inventory,
+        transaction_manager: &TransactionManager,
+        audit_log: &AuditLog,
+    ) -> Result<(), String> {
+        let mut failures = Vec::new();
+        if let Err(e) = self.save_inventory(inventory).await {
+            failures.push(format!("Failed to save inventory: {}", e));
+        }
+        if let Err(e) = self.save_transactions(transaction_manager).await {
+            failures.push(format!("Failed to save transactions: {}", e));
+        }
+        if let Err(e) = self.save_audit_log(audit_log).await {
+            failures.push(format!("Failed to save audit log: {}", e));
+        }
+        if failures.is_empty() {
+            Ok(())
+        } else {
+            Err(failures.join("\n"))
+        }
+    }
+}
+
+/// Copies everything from `source` into `target` the first time it runs
+/// against `target`, provided `target` holds no data yet. Later runs do
+/// nothing, even once `target` is emptied. Returns whether anything was
+/// imported.
+pub async fn import_once(target: &impl Storage, source: &impl Storage) -> Result<bool, String> {
+    if target.imported().await? {
+        return Ok(false);
+    }
+
+    let mut imported = false;
+    let inventory = target.load_inventory().await?;
+    let transaction_manager = target.load_transactions().await?;
+    if inventory.products.is_empty() && transaction_manager.transactions.is_empty() {
+        let inventory = source.load_inventory().await?;
+        let transaction_manager = source.load_transactions().await?;
+        if !inventory.products.is_empty() || !transaction_manager.transactions.is_empty() {
+            target.save_inventory(&inventory).await?;
+            target.save_transactions(&transaction_manager).await?;
+            imported = true;
+        }
+    }
+    target.mark_imported().await?;
+    Ok(imported)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::closing::DayClosing;
+    use crate::inventory::Product;
+    use crate::money::Money;
+    use crate::transaction::{Transaction, TransactionType};
+    use chrono::Utc;
+
+    fn sample_inventory() -> Inventory {
+        let mut inventory = Inventory::new();
+        inventory.products.insert(
+            "WID-001".to_string(),
+            Product {
+                sku: "WID-001".to_string(),
+                name: "Widget".to_string(),
+                description: "A test widget".to_string(),
+                price: Money::from_cents(5000),
+                cost: Money::from_cents(3000),
+                quantity: 100,
+                layers: Vec::new(),
+            },
+        );
+        inventory
+    }
+
+    fn sample_transactions() -> TransactionManager {
+        let mut transaction_manager = TransactionManager::new();
+        transaction_manager.transactions.push(Transaction {
+            id: 1,
+            recorded_at: Utc::now(),
+            transaction_type: TransactionType::Sale,
+            sku: "WID-001".to_string(),
+            quantity: 5,
+            price: Money::from_cents(5000),
+            total: Money::from_cents(25000),
+            cost: Money::ZERO,
+            order_id: None,
+            reference_id: None,
+            damaged: false,
+            recorded_by: None,
+            payment_method: None,
+        });
+        transaction_manager.closings.push(DayClosing {
+            day: Utc::now().date_naive(),
+            closed_at: Utc::now(),
+            closed_by: "boss".to_string(),
+            expected_cash: Money::from_cents(25000),
+            counted_cash: Money::from_cents(24900),
+            over_short: Money::from_cents(-100),
+        });
+        transaction_manager
+    }
+
+    async fn round_trips(storage: &impl Storage) {
+        storage.save_inventory(&sample_inventory()).await.unwrap();
+        let sample = sample_transactions();
+        storage.save_transactions(&sample).await.unwrap();
+
+        let inventory = storage.load_inventory().await.unwrap();
+        assert_eq!(inventory.products.get("WID-001").unwrap().quantity, 100);
+        let transaction_manager = storage.load_transactions().await.unwrap();
+        assert_eq!(transaction_manager.transactions.len(), 1);
+        assert_eq!(
+            transaction_manager.transactions[0].total,
+            Money::from_cents(25000)
+        );
+        assert_eq!(transaction_manager.closings, sample.closings);
+    }
+
+    #[tokio::test]
+    async fn every_backend_round_trips() {
+        round_trips(&MemoryStorage::new()).await;
+        round_trips(&SqliteStorage::new("sqlite::memory:").await).await;
+
+        let dir =
+            std::env::temp_dir().join(format!("rusty_store_round_trip_{}", std::process::id()));
+        round_trips(&JsonStorage::new(&dir)).await;
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn imports_only_once() {
+        let source = MemoryStorage::new();
+        source.save_inventory(&sample_inventory()).await.unwrap();
+
+        let target = MemoryStorage::new();
+        assert!(import_once(&target, &source).await.unwrap());
+        assert!(target
+            .load_inventory()
+            .await
+            .unwrap()
+            .products
+            .contains_key("WID-001"));
+
+        // Deleting everything does not bring the old data back
+        target.save_inventory(&Inventory::new()).await.unwrap();
+        assert!(!import_once(&target, &source).await.unwrap());
+        assert!(target.load_inventory().await.unwrap().products.is_empty());
+    }
+
+    #[tokio::test]
+    async fn does_not_import_into_storage_in_use() {
+        let source = MemoryStorage::new();
+        source.save_inventory(&sample_inventory()).await.unwrap();
+
+        let target = MemoryStorage::new();
+        target
+            .save_transactions(&sample_transactions())
+            .await
+            .unwrap();
+        assert!(!import_once(&target, &source).await.unwrap());
+        assert!(target.imported().await.unwrap());
+        assert!(target.load_inventory().await.unwrap().products.is_empty());
+    }
+}
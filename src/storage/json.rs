@@ -0,0 +1,232 @@
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use std::{fs, io};
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+
+use super::Storage;
+use crate::audit::AuditLog;
+use crate::inventory::Inventory;
+use crate::migration::{self, Document};
+use crate::transaction::TransactionManager;
+
+const DEFAULT_BACKUP_COUNT: usize = 5;
+
+/// Stores the inventory, transactions and audit log as `inventory.json`,
+/// `transactions.json` and `audit_log.json` inside a directory.
+///
+/// Saves go to a temporary file that is renamed over the original, so a crash
+/// mid-write leaves the previous version intact. Before each save the current
+/// file is copied to `backups/<name>.<unix millis>.bak`, keeping the newest
+/// `backup_count` copies.
+pub struct JsonStorage {
+    dir: PathBuf,
+    backup_count: usize,
+}
+
+impl JsonStorage {
+    pub fn new(dir: impl AsRef<Path>) -> Self {
+        Self {
+            dir: dir.as_ref().to_path_buf(),
+            backup_count: DEFAULT_BACKUP_COUNT,
+        }
+    }
+
+    /// Sets how many rotating backups are kept per file. Zero disables them.
+    pub fn with_backup_count(mut self, backup_count: usize) -> Self {
+        self.backup_count = backup_count;
+        self
+    }
+
+    fn backup_dir(&self) -> PathBuf {
+        self.dir.join("backups")
+    }
+
+    fn write<T: Serialize>(&self, file_name: &str, value: &T) -> Result<(), String> {
+        let path = self.dir.join(file_name);
+        let json = serde_json::to_value(value)
+            .and_then(|data| serde_json::to_string_pretty(&migration::wrap_json(data)))
+            .map_err(|e| format!("Failed to serialize {}: {}", path.display(), e))?;
+
+        fs::create_dir_all(&self.dir)
+            .map_err(|e| format!("Failed to create {}: {}", self.dir.display(), e))?;
+        self.backup(file_name)
+            .map_err(|e| format!("Failed to back up {}: {}", path.display(), e))?;
+
+        let tmp_path = self.dir.join(format!("{}.tmp", file_name));
+        write_synced(&tmp_path, json.as_bytes())
+            .and_then(|_| fs::rename(&tmp_path, &path))
+            .map_err(|e| {
+                let _ = fs::remove_file(&tmp_path);
+                format!("Failed to write {}: {}", path.display(), e)
+            })
+    }
+
+    /// Copies the current version of `file_name` into the backup directory and
+    /// prunes the oldest copies beyond `backup_count`.
+    fn backup(&self, file_name: &str) -> io::Result<()> {
+        let path = self.dir.join(file_name);
+        if self.backup_count == 0 || !path.exists() {
+            return Ok(());
+        }
+
+        let backup_dir = self.backup_dir();
+        fs::create_dir_all(&backup_dir)?;
+        let millis = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_millis())
+            .unwrap_or_default();
+        fs::copy(
+            &path,
+            backup_dir.join(format!("{}.{}.bak", file_name, millis)),
+        )?;
+
+        let mut backups = self.backups(file_name)?;
+        while backups.len() > self.backup_count {
+            fs::remove_file(backups.remove(0))?;
+        }
+        Ok(())
+    }
+
+    /// Lists the backups of `file_name`, oldest first.
+    pub fn backups(&self, file_name: &str) -> io::Result<Vec<PathBuf>> {
+        let prefix = format!("{}.", file_name);
+        let mut backups: Vec<(u128, PathBuf)> = match fs::read_dir(self.backup_dir()) {
+            Ok(entries) => entries
+                .filter_map(|entry| entry.ok())
+                .filter_map(|entry| {
+                    let name = entry.file_name().into_string().ok()?;
+                    let millis = name.strip_prefix(&prefix)?.strip_suffix(".bak")?;
+                    Some((millis.parse().ok()?, entry.path()))
+                })
+                .collect(),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e),
+        };
+        backups.sort();
+        Ok(backups.into_iter().map(|(_, path)| path).collect())
+    }
+
+    /// Reads a file and upgrades it to the current version, treating a missing
+    /// one as empty so a fresh directory starts with no data. A file that
+    /// exists but cannot be parsed is an error rather than an empty store, so
+    /// nothing overwrites it.
+    fn read<T: DeserializeOwned + Default>(
+        &self,
+        file_name: &str,
+        document: Document,
+        history: &Value,
+    ) -> Result<T, String> {
+        let path = self.dir.join(file_name);
+        let json = match fs::read_to_string(&path) {
+            Ok(json) => json,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(T::default()),
+            Err(e) => return Err(format!("Failed to read {}: {}", path.display(), e)),
+        };
+        serde_json::from_str(&json)
+            .map_err(|e| e.to_string())
+            .and_then(|value| migration::upgrade_json(document, value, history))
+            .and_then(|data| serde_json::from_value(data).map_err(|e| e.to_string()))
+            .map_err(|e| {
+                let mut message = format!("Cannot load {}: {}", path.display(), e);
+                if let Some(latest) = self.backups(file_name).ok().and_then(|b| b.last().cloned()) {
+                    message.push_str(&format!(". The latest backup is {}", latest.display()));
+                }
+                message
+            })
+    }
+}
+
+fn write_synced(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let mut file = fs::File::create(path)?;
+    file.write_all(contents)?;
+    file.sync_all()
+}
+
+impl Storage for JsonStorage {
+    async fn load_inventory(&self) -> Result<Inventory, String> {
+        // Older inventories take their unit costs from the purchase history
+        let history: Value =
+            self.read("transactions.json", Document::Transactions, &Value::Null)?;
+        self.read("inventory.json", Document::Inventory, &history)
+    }
+
+    async fn save_inventory(&self, inventory: &Inventory) -> Result<(), String> {
+        self.write("inventory.json", inventory)
+    }
+
+    async fn load_transactions(&self) -> Result<TransactionManager, String> {
+        self.read("transactions.json", Document::Transactions, &Value::Null)
+    }
+
+    async fn save_transactions(
+        &self,
+        transaction_manager: &TransactionManager,
+    ) -> Result<(), String> {
+        self.write("transactions.json", transaction_manager)
+    }
+
+    async fn load_audit_log(&self) -> Result<AuditLog, String> {
+        self.read("audit_log.json", Document::AuditLog, &Value::Null)
+    }
+
+    async fn save_audit_log(&self, audit_log: &AuditLog) -> Result<(), String> {
+        self.write("audit_log.json", audit_log)
+    }
+
+    // An empty `imported` file marks the import as done
+    async fn imported(&self) -> Result<bool, String> {
+        Ok(self.dir.join("imported").exists())
+    }
+
+    async fn mark_imported(&self) -> Result<(), String> {
+        let path = self.dir.join("imported");
+        fs::create_dir_all(&self.dir)
+            .and_then(|_| write_synced(&path, b""))
+            .map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("rusty_store_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[tokio::test]
+    async fn keeps_only_the_newest_backups() {
+        let dir = temp_dir("backups");
+        let storage = JsonStorage::new(&dir).with_backup_count(2);
+
+        for _ in 0..4 {
+            storage.save_inventory(&Inventory::new()).await.unwrap();
+            std::thread::sleep(std::time::Duration::from_millis(2));
+        }
+
+        // The first save has nothing to back up, the next three each add one
+        assert_eq!(storage.backups("inventory.json").unwrap().len(), 2);
+        assert!(!dir.join("inventory.json.tmp").exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn refuses_to_load_a_corrupt_file() {
+        let dir = temp_dir("corrupt");
+        let storage = JsonStorage::new(&dir);
+        storage.save_inventory(&Inventory::new()).await.unwrap();
+        storage.save_inventory(&Inventory::new()).await.unwrap();
+        fs::write(dir.join("inventory.json"), "{\"products\": {").unwrap();
+
+        let err = storage.load_inventory().await.unwrap_err();
+        assert!(err.contains("Cannot load"));
+        assert!(err.contains("latest backup"));
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,63 @@
+use std::sync::Mutex;
+
+use super::Storage;
+use crate::audit::AuditLog;
+use crate::inventory::Inventory;
+use crate::transaction::TransactionManager;
+
+/// Keeps everything in memory. Nothing survives the process, which makes it
+/// the backend of choice for tests.
+#[derive(Default)]
+pub struct MemoryStorage {
+    inventory: Mutex<Inventory>,
+    transaction_manager: Mutex<TransactionManager>,
+    audit_log: Mutex<AuditLog>,
+    imported: Mutex<bool>,
+}
+
+impl MemoryStorage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl Storage for MemoryStorage {
+    async fn load_inventory(&self) -> Result<Inventory, String> {
+        Ok(self.inventory.lock().unwrap().clone())
+    }
+
+    async fn save_inventory(&self, inventory: &Inventory) -> Result<(), String> {
+        *self.inventory.lock().unwrap() = inventory.clone();
+        Ok(())
+    }
+
+    async fn load_transactions(&self) -> Result<TransactionManager, String> {
+        Ok(self.transaction_manager.lock().unwrap().clone())
+    }
+
+    async fn save_transactions(
+        &self,
+        transaction_manager: &TransactionManager,
+    ) -> Result<(), String> {
+        *self.transaction_manager.lock().unwrap() = transaction_manager.clone();
+        Ok(())
+    }
+
+    async fn load_audit_log(&self) -> Result<AuditLog, String> {
+        Ok(self.audit_log.lock().unwrap().clone())
+    }
+
+    async fn save_audit_log(&self, audit_log: &AuditLog) -> Result<(), String> {
+        *self.audit_log.lock().unwrap() = audit_log.clone();
+        Ok(())
+    }
+
+    async fn imported(&self) -> Result<bool, String> {
+        Ok(*self.imported.lock().unwrap())
+    }
+
+    async fn mark_imported(&self) -> Result<(), String> {
+        *self.imported.lock().unwrap() = true;
+        Ok(())
+    }
+}
@@ -0,0 +1,816 @@
+use std::collections::{BTreeSet, HashMap};
+use std::sync::Mutex;
+
+use chrono::Utc;
+use sqlx::sqlite::{Sqlite, SqliteConnection, SqlitePool};
+use sqlx::{Row, Transaction};
+
+use super::Storage;
+use crate::audit::{AuditEntry, AuditLog};
+use crate::closing::DayClosing;
+use crate::costing::{CostLayer, CostingMethod};
+use crate::inventory::{Inventory, Product};
+use crate::migration;
+use crate::money::Money;
+use crate::transaction::{self, TransactionManager, TransactionType};
+
+/// Persists the inventory, the transaction history and the audit log in the same SQLite
+/// database that holds the store users.
+pub struct SqliteStorage {
+    db: SqlitePool,
+    saved: Mutex<Saved>,
+}
+
+impl SqliteStorage {
+    pub async fn new(database_url: &str) -> Self {
+        let db = SqlitePool::connect(database_url)
+            .await
+            .expect("Failed to connect to DB!");
+        let storage = Self {
+            db,
+            saved: Mutex::new(Saved::default()),
+        };
+        storage.initialize().await;
+        storage
+    }
+
+    async fn initialize(&self) {
+        migration::migrate_sqlite(&self.db)
+            .await
+            .expect("Failed to migrate database");
+    }
+
+    async fn begin(&self) -> Result<Transaction<'static, Sqlite>, String> {
+        self.db
+            .begin()
+            .await
+            .map_err(|e| format!("Failed to start transaction: {}", e))
+    }
+}
+
+impl Storage for SqliteStorage {
+    async fn load_inventory(&self) -> Result<Inventory, String> {
+        let rows =
+            sqlx::query("SELECT sku, name, description, price, cost, quantity FROM products")
+                .fetch_all(&self.db)
+                .await
+                .map_err(|e| format!("Failed to load products: {}", e))?;
+
+        let mut products: HashMap<String, Product> = rows
+            .iter()
+            .map(|row| {
+                let product = Product {
+                    sku: row.get("sku"),
+                    name: row.get("name"),
+                    description: row.get("description"),
+                    price: Money::from_cents(row.get("price")),
+                    cost: Money::from_cents(row.get("cost")),
+                    quantity: row.get("quantity"),
+                    layers: Vec::new(),
+                };
+                (product.sku.clone(), product)
+            })
+            .collect();
+
+        let layers =
+            sqlx::query("SELECT sku, quantity, unit_cost FROM cost_layers ORDER BY sku, position")
+                .fetch_all(&self.db)
+                .await
+                .map_err(|e| format!("Failed to load cost layers: {}", e))?;
+        for row in layers {
+            let sku: String = row.get("sku");
+            if let Some(product) = products.get_mut(&sku) {
+                product.layers.push(CostLayer {
+                    quantity: row.get("quantity"),
+                    unit_cost: Money::from_cents(row.get("unit_cost")),
+                });
+            }
+        }
+
+        let costing_method =
+            match sqlx::query("SELECT value FROM settings WHERE key = 'costing_method'")
+                .fetch_optional(&self.db)
+                .await
+                .map_err(|e| format!("Failed to load settings: {}", e))?
+            {
+                Some(row) => row.get::<String, _>("value").parse()?,
+                None => CostingMethod::default(),
+            };
+
+        let inventory = Inventory {
+            products,
+            costing_method,
+        };
+        self.saved.lock().unwrap().inventory = inventory.clone();
+        Ok(inventory)
+    }
+
+    async fn save_inventory(&self, inventory: &Inventory) -> Result<(), String> {
+        let saved = self.saved.lock().unwrap().inventory.clone();
+        let mut tx = self.begin().await?;
+        write_inventory(&mut tx, inventory, &saved).await?;
+        commit(tx).await?;
+        self.saved.lock().unwrap().inventory = inventory.clone();
+        Ok(())
+    }
+
+    async fn load_transactions(&self) -> Result<TransactionManager, String> {
+        let rows = sqlx::query(
+            "SELECT id, recorded_at, transaction_type, sku, quantity, price, total, cost, order_id, reference_id, damaged, recorded_by, payment_method FROM transactions ORDER BY id",
+        )
+        .fetch_all(&self.db)
+        .await
+        .map_err(|e| format!("Failed to load transactions: {}", e))?;
+
+        let mut transactions = Vec::with_capacity(rows.len());
+        for row in rows {
+            let transaction_type: String = row.get("transaction_type");
+            let transaction_type = match transaction_type.as_str() {
+                "Sale" => TransactionType::Sale,
+                "Purchase" => TransactionType::Purchase,
+                "Return" => TransactionType::Return,
+                "Void" => TransactionType::Void,
+                other => return Err(format!("Unknown transaction type '{}'", other)),
+            };
+            transactions.push(transaction::Transaction {
+                id: row.get::<i64, _>("id") as u64,
+                recorded_at: row.get("recorded_at"),
+                transaction_type,
+                sku: row.get("sku"),
+                quantity: row.get("quantity"),
+                price: Money::from_cents(row.get("price")),
+                total: Money::from_cents(row.get("total")),
+                cost: Money::from_cents(row.get("cost")),
+                order_id: row.get::<Option<i64>, _>("order_id").map(|id| id as u64),
+                reference_id: row
+                    .get::<Option<i64>, _>("reference_id")
+                    .map(|id| id as u64),
+                damaged: row.get("damaged"),
+                recorded_by: row.get("recorded_by"),
+                payment_method: row
+                    .get::<Option<String>, _>("payment_method")
+                    .map(|method| method.parse())
+                    .transpose()?,
+            });
+        }
+
+        let rows = sqlx::query(
+            "SELECT day, closed_at, closed_by, expected_cash, counted_cash, over_short FROM day_closings ORDER BY day",
+        )
+        .fetch_all(&self.db)
+        .await
+        .map_err(|e| format!("Failed to load day closings: {}", e))?;
+        let closings = rows
+            .into_iter()
+            .map(|row| DayClosing {
+                day: row.get("day"),
+                closed_at: row.get("closed_at"),
+                closed_by: row.get("closed_by"),
+                expected_cash: Money::from_cents(row.get("expected_cash")),
+                counted_cash: Money::from_cents(row.get("counted_cash")),
+                over_short: Money::from_cents(row.get("over_short")),
+            })
+            .collect();
+
+        let transaction_manager = TransactionManager {
+            transactions,
+            closings,
+        };
+        self.saved
+            .lock()
+            .unwrap()
+            .remember_transactions(&transaction_manager);
+        Ok(transaction_manager)
+    }
+    async fn save_transactions(
+        &self,
+        transaction_manager: &TransactionManager,
+    ) -> Result<(), String> {
+        let saved = self.saved.lock().unwrap().clone_counts();
+        let mut tx = self.begin().await?;
+        write_transactions(&mut tx, transaction_manager, &saved).await?;
+        commit(tx).await?;
+        self.saved
+            .lock()
+            .unwrap()
+            .remember_transactions(transaction_manager);
+        Ok(())
+    }
+
+    async fn load_audit_log(&self) -> Result<AuditLog, String> {
+        let rows = sqlx::query(
+            "SELECT id, recorded_at, username, action, subject, details, before, after FROM audit_log ORDER BY id",
+        )
+        .fetch_all(&self.db)
+        .await
+        .map_err(|e| format!("Failed to load audit log: {}", e))?;
+
+        let value = |text: Option<String>| {
+            text.map(|text| serde_json::from_str(&text))
+                .transpose()
+                .map_err(|e| format!("Failed to parse audit values: {}", e))
+        };
+        let mut entries = Vec::with_capacity(rows.len());
+        for row in rows {
+            entries.push(AuditEntry {
+                id: row.get::<i64, _>("id") as u64,
+                recorded_at: row.get("recorded_at"),
+                username: row.get("username"),
+                action: row.get::<String, _>("action").parse()?,
+                subject: row.get("subject"),
+                details: row.get("details"),
+                before: value(row.get("before"))?,
+                after: value(row.get("after"))?,
+            });
+        }
+
+        self.saved.lock().unwrap().audit_entries = entries.len();
+        Ok(AuditLog { entries })
+    }
+    async fn save_audit_log(&self, audit_log: &AuditLog) -> Result<(), String> {
+        let saved = self.saved.lock().unwrap().audit_entries;
+        let mut tx = self.begin().await?;
+        write_audit_log(&mut tx, audit_log, saved).await?;
+        commit(tx).await?;
+        self.saved.lock().unwrap().audit_entries = audit_log.entries.len();
+        Ok(())
+    }
+
+    async fn imported(&self) -> Result<bool, String> {
+        let marker: Option<String> =
+            sqlx::query_scalar("SELECT value FROM settings WHERE key = 'json_imported'")
+                .fetch_optional(&self.db)
+                .await
+                .map_err(|e| format!("Failed to read settings: {}", e))?;
+        Ok(marker.is_some())
+    }
+
+    // The marker holds when the import ran
+    async fn mark_imported(&self) -> Result<(), String> {
+        sqlx::query("INSERT OR IGNORE INTO settings (key, value) VALUES ('json_imported', ?)")
+            .bind(Utc::now().to_rfc3339())
+            .execute(&self.db)
+            .await
+            .map_err(|e| format!("Failed to save settings: {}", e))?;
+        Ok(())
+    }
+
+    // All or nothing: a sale is never saved without the stock it took
+    async fn save(
+        &self,
+        inventory: &Inventory,
+        transaction_manager: &TransactionManager,
+        audit_log: &AuditLog,
+    ) -> Result<(), String> {
+        let saved = {
+            let saved = self.saved.lock().unwrap();
+            (saved.inventory.clone(), saved.clone_counts())
+        };
+        let mut tx = self.begin().await?;
+        write_inventory(&mut tx, inventory, &saved.0).await?;
+        write_transactions(&mut tx, transaction_manager, &saved.1).await?;
+        write_audit_log(&mut tx, audit_log, saved.1.audit_entries).await?;
+        commit(tx).await?;
+
+        let mut saved = self.saved.lock().unwrap();
+        saved.inventory = inventory.clone();
+        saved.remember_transactions(transaction_manager);
+        saved.audit_entries = audit_log.entries.len();
+        Ok(())
+    }
+}
+
+/// What this process last loaded from or saved to the database. Saves only
+/// write what differs from it, so sessions running side by side, such as the
+/// menu and a script, do not undo each other.
+#[derive(Default)]
+struct Saved {
+    inventory: Inventory,
+    last_transaction_id: u64,
+    transactions: usize,
+    closings: usize,
+    audit_entries: usize,
+}
+
+impl Saved {
+    fn remember_transactions(&mut self, transaction_manager: &TransactionManager) {
+        self.last_transaction_id = transaction_manager
+            .transactions
+            .iter()
+            .map(|transaction| transaction.id)
+            .max()
+            .unwrap_or(0);
+        self.transactions = transaction_manager.transactions.len();
+        self.closings = transaction_manager.closings.len();
+    }
+
+    /// Everything but the inventory, which is cloned separately when needed.
+    fn clone_counts(&self) -> Saved {
+        Saved {
+            inventory: Inventory::default(),
+            last_transaction_id: self.last_transaction_id,
+            transactions: self.transactions,
+            closings: self.closings,
+            audit_entries: self.audit_entries,
+        }
+    }
+}
+
+fn changed_elsewhere(what: &str) -> String {
+    format!(
+        "{} was changed by another session since it was loaded, so nothing was saved.",
+        what
+    )
+}
+
+async fn commit(tx: Transaction<'_, Sqlite>) -> Result<(), String> {
+    tx.commit()
+        .await
+        .map_err(|e| format!("Failed to commit changes: {}", e))
+}
+
+/// The product `sku` as stored now, with its cost layers.
+async fn stored_product(conn: &mut SqliteConnection, sku: &str) -> Result<Option<Product>, String> {
+    let Some(row) = sqlx::query(
+        "SELECT sku, name, description, price, cost, quantity FROM products WHERE sku = ?",
+    )
+    .bind(sku)
+    .fetch_optional(&mut *conn)
+    .await
+    .map_err(|e| format!("Failed to read product '{}': {}", sku, e))?
+    else {
+        return Ok(None);
+    };
+    let layers =
+        sqlx::query("SELECT quantity, unit_cost FROM cost_layers WHERE sku = ? ORDER BY position")
+            .bind(sku)
+            .fetch_all(&mut *conn)
+            .await
+            .map_err(|e| format!("Failed to read cost layers of '{}': {}", sku, e))?
+            .iter()
+            .map(|row| CostLayer {
+                quantity: row.get("quantity"),
+                unit_cost: Money::from_cents(row.get("unit_cost")),
+            })
+            .collect();
+    Ok(Some(Product {
+        sku: row.get("sku"),
+        name: row.get("name"),
+        description: row.get("description"),
+        price: Money::from_cents(row.get("price")),
+        cost: Money::from_cents(row.get("cost")),
+        quantity: row.get("quantity"),
+        layers,
+    }))
+}
+
+/// Writes the products that differ from `saved`, each only if the stored row
+/// is still the one `saved` holds.
+async fn write_inventory(
+    conn: &mut SqliteConnection,
+    inventory: &Inventory,
+    saved: &Inventory,
+) -> Result<(), String> {
+    let skus: BTreeSet<&String> = saved
+        .products
+        .keys()
+        .chain(inventory.products.keys())
+        .collect();
+    for sku in skus {
+        let before = saved.products.get(sku);
+        let after = inventory.products.get(sku);
+        if before == after {
+            continue;
+        }
+        if stored_product(conn, sku).await?.as_ref() != before {
+            return Err(changed_elsewhere(&format!("Product '{}'", sku)));
+        }
+
+        sqlx::query("DELETE FROM products WHERE sku = ?")
+            .bind(sku)
+            .execute(&mut *conn)
+            .await
+            .map_err(|e| format!("Failed to remove product '{}': {}", sku, e))?;
+        sqlx::query("DELETE FROM cost_layers WHERE sku = ?")
+            .bind(sku)
+            .execute(&mut *conn)
+            .await
+            .map_err(|e| format!("Failed to remove cost layers of '{}': {}", sku, e))?;
+        let Some(product) = after else {
+            continue;
+        };
+        sqlx::query(
+            "INSERT INTO products (sku, name, description, price, cost, quantity) VALUES (?, ?, ?, ?, ?, ?)",
+        )
+        .bind(&product.sku)
+        .bind(&product.name)
+        .bind(&product.description)
+        .bind(product.price.cents())
+        .bind(product.cost.cents())
+        .bind(product.quantity)
+        .execute(&mut *conn)
+        .await
+        .map_err(|e| format!("Failed to save product '{}': {}", product.sku, e))?;
+        for (position, layer) in product.layers.iter().enumerate() {
+            sqlx::query(
+                "INSERT INTO cost_layers (sku, position, quantity, unit_cost) VALUES (?, ?, ?, ?)",
+            )
+            .bind(&product.sku)
+            .bind(position as i64)
+            .bind(layer.quantity)
+            .bind(layer.unit_cost.cents())
+            .execute(&mut *conn)
+            .await
+            .map_err(|e| format!("Failed to save cost layers of '{}': {}", product.sku, e))?;
+        }
+    }
+
+    if inventory.costing_method != saved.costing_method {
+        let stored: Option<String> =
+            sqlx::query_scalar("SELECT value FROM settings WHERE key = 'costing_method'")
+                .fetch_optional(&mut *conn)
+                .await
+                .map_err(|e| format!("Failed to read settings: {}", e))?;
+        let stored = match stored {
+            Some(value) => value.parse()?,
+            None => CostingMethod::default(),
+        };
+        if stored != saved.costing_method {
+            return Err(changed_elsewhere("The costing method"));
+        }
+        sqlx::query(
+            "INSERT INTO settings (key, value) VALUES ('costing_method', ?) ON CONFLICT(key) DO UPDATE SET value = excluded.value",
+        )
+        .bind(inventory.costing_method.to_string())
+        .execute(&mut *conn)
+        .await
+        .map_err(|e| format!("Failed to save costing method: {}", e))?;
+    }
+    Ok(())
+}
+
+/// Appends the transactions and closings added since `saved`. Transaction ids
+/// follow on from the last one this process saw, so it refuses if another
+/// session recorded any in the meantime.
+async fn write_transactions(
+    conn: &mut SqliteConnection,
+    transaction_manager: &TransactionManager,
+    saved: &Saved,
+) -> Result<(), String> {
+    let new = &transaction_manager.transactions[saved
+        .transactions
+        .min(transaction_manager.transactions.len())..];
+    if !new.is_empty() {
+        let stored: i64 = sqlx::query_scalar("SELECT COALESCE(MAX(id), 0) FROM transactions")
+            .fetch_one(&mut *conn)
+            .await
+            .map_err(|e| format!("Failed to read last transaction id: {}", e))?;
+        if stored as u64 != saved.last_transaction_id {
+            return Err(changed_elsewhere("The transaction history"));
+        }
+    }
+    for transaction in new {
+        sqlx::query(
+            "INSERT INTO transactions (id, recorded_at, transaction_type, sku, quantity, price, total, cost, order_id, reference_id, damaged, recorded_by, payment_method) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+        )
+        .bind(transaction.id as i64)
+        .bind(transaction.recorded_at)
+        .bind(transaction.transaction_type.to_string())
+        .bind(&transaction.sku)
+        .bind(transaction.quantity)
+        .bind(transaction.price.cents())
+        .bind(transaction.total.cents())
+        .bind(transaction.cost.cents())
+        .bind(transaction.order_id.map(|id| id as i64))
+        .bind(transaction.reference_id.map(|id| id as i64))
+        .bind(transaction.damaged)
+        .bind(&transaction.recorded_by)
+        .bind(transaction.payment_method.map(|method| method.to_string()))
+        .execute(&mut *conn)
+        .await
+        .map_err(|e| format!("Failed to save transaction: {}", e))?;
+    }
+
+    for closing in transaction_manager.closings.iter().skip(saved.closings) {
+        let result = sqlx::query(
+            "INSERT OR IGNORE INTO day_closings (day, closed_at, closed_by, expected_cash, counted_cash, over_short) VALUES (?, ?, ?, ?, ?, ?)",
+        )
+        .bind(closing.day)
+        .bind(closing.closed_at)
+        .bind(&closing.closed_by)
+        .bind(closing.expected_cash.cents())
+        .bind(closing.counted_cash.cents())
+        .bind(closing.over_short.cents())
+        .execute(&mut *conn)
+        .await
+        .map_err(|e| format!("Failed to save day closing: {}", e))?;
+        if result.rows_affected() == 0 {
+            return Err(changed_elsewhere(&format!(
+                "The business day {}",
+                closing.day
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Appends the entries added since the first `saved`. The database numbers
+/// them, so entries other sessions logged meanwhile are kept alongside.
+async fn write_audit_log(
+    conn: &mut SqliteConnection,
+    audit_log: &AuditLog,
+    saved: usize,
+) -> Result<(), String> {
+    for entry in audit_log.entries.iter().skip(saved) {
+        sqlx::query(
+            "INSERT INTO audit_log (recorded_at, username, action, subject, details, before, after) VALUES (?, ?, ?, ?, ?, ?, ?)",
+        )
+        .bind(entry.recorded_at)
+        .bind(&entry.username)
+        .bind(entry.action.to_string())
+        .bind(&entry.subject)
+        .bind(&entry.details)
+        .bind(entry.before.as_ref().map(|value| value.to_string()))
+        .bind(entry.after.as_ref().map(|value| value.to_string()))
+        .execute(&mut *conn)
+        .await
+        .map_err(|e| format!("Failed to save audit entry: {}", e))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audit::{AuditAction, Change};
+    use crate::auth::User;
+    use crate::permission::Role;
+    use crate::session::Session;
+    use crate::transaction::PaymentMethod;
+
+    async fn memory_storage() -> SqliteStorage {
+        SqliteStorage::new("sqlite::memory:").await
+    }
+
+    #[tokio::test]
+    async fn saves_and_loads_inventory() {
+        let storage = memory_storage().await;
+        let mut inventory = Inventory::new();
+        inventory.products.insert(
+            "WID-001".to_string(),
+            Product {
+                sku: "WID-001".to_string(),
+                name: "Widget".to_string(),
+                description: "A test widget".to_string(),
+                price: Money::from_cents(5000),
+                cost: Money::from_cents(3000),
+                quantity: 100,
+                layers: Vec::new(),
+            },
+        );
+
+        storage.save_inventory(&inventory).await.unwrap();
+        inventory.products.remove("WID-001");
+        storage.save_inventory(&inventory).await.unwrap();
+        assert!(storage.load_inventory().await.unwrap().products.is_empty());
+
+        inventory.products.insert(
+            "GAD-001".to_string(),
+            Product {
+                sku: "GAD-001".to_string(),
+                name: "Gadget".to_string(),
+                description: "A test gadget".to_string(),
+                price: Money::from_cents(3000),
+                cost: Money::from_cents(1800),
+                quantity: 15,
+                layers: Vec::new(),
+            },
+        );
+        storage.save_inventory(&inventory).await.unwrap();
+
+        let loaded = storage.load_inventory().await.unwrap();
+        let product = loaded.products.get("GAD-001").unwrap();
+        assert_eq!(product.price, Money::from_cents(3000));
+        assert_eq!(product.cost, Money::from_cents(1800));
+        assert_eq!(product.quantity, 15);
+        assert!(loaded.products.get("GAD-001").unwrap().layers.is_empty());
+
+        inventory.costing_method = CostingMethod::Lifo;
+        inventory.products.get_mut("GAD-001").unwrap().receive(
+            5,
+            Money::from_cents(2000),
+            CostingMethod::Lifo,
+        );
+        storage.save_inventory(&inventory).await.unwrap();
+
+        let loaded = storage.load_inventory().await.unwrap();
+        assert_eq!(loaded.costing_method, CostingMethod::Lifo);
+        let product = loaded.products.get("GAD-001").unwrap();
+        assert_eq!(product.layers, inventory.products["GAD-001"].layers);
+        assert_eq!(product.stock_value(), Money::from_cents(37000)); // 15 at 18.00, 5 at 20.00
+    }
+
+    #[tokio::test]
+    async fn appends_only_new_transactions() {
+        let storage = memory_storage().await;
+        let mut transaction_manager = TransactionManager::new();
+        transaction_manager
+            .transactions
+            .push(transaction::Transaction {
+                id: 1,
+                recorded_at: Utc::now(),
+                transaction_type: TransactionType::Purchase,
+                sku: "WID-001".to_string(),
+                quantity: 10,
+                price: Money::from_cents(3000),
+                total: Money::from_cents(30000),
+                cost: Money::ZERO,
+                order_id: None,
+                reference_id: None,
+                damaged: false,
+                recorded_by: None,
+                payment_method: None,
+            });
+        storage
+            .save_transactions(&transaction_manager)
+            .await
+            .unwrap();
+
+        transaction_manager
+            .transactions
+            .push(transaction::Transaction {
+                id: 2,
+                recorded_at: Utc::now(),
+                transaction_type: TransactionType::Sale,
+                sku: "WID-001".to_string(),
+                quantity: 5,
+                price: Money::from_cents(5000),
+                total: Money::from_cents(25000),
+                cost: Money::from_cents(15000),
+                order_id: None,
+                reference_id: None,
+                damaged: false,
+                recorded_by: Some("till".to_string()),
+                payment_method: Some(PaymentMethod::Card),
+            });
+        let closing = DayClosing {
+            day: Utc::now().date_naive(),
+            closed_at: Utc::now(),
+            closed_by: "boss".to_string(),
+            expected_cash: Money::ZERO,
+            counted_cash: Money::from_cents(150),
+            over_short: Money::from_cents(150),
+        };
+        transaction_manager.closings.push(closing.clone());
+        storage
+            .save_transactions(&transaction_manager)
+            .await
+            .unwrap();
+        storage
+            .save_transactions(&transaction_manager)
+            .await
+            .unwrap();
+
+        let loaded = storage.load_transactions().await.unwrap();
+        assert_eq!(loaded.transactions.len(), 2);
+        assert_eq!(
+            loaded.transactions[0].transaction_type,
+            TransactionType::Purchase
+        );
+        assert_eq!(
+            loaded.transactions[1].transaction_type,
+            TransactionType::Sale
+        );
+        assert_eq!(loaded.transactions[1].id, 2);
+        assert_eq!(loaded.transactions[1].total, Money::from_cents(25000));
+        assert_eq!(loaded.transactions[1].cost, Money::from_cents(15000));
+        assert_eq!(loaded.transactions[1].recorded_by.as_deref(), Some("till"));
+        assert_eq!(loaded.transactions[0].recorded_by, None);
+        assert_eq!(
+            loaded.transactions[1].payment_method,
+            Some(PaymentMethod::Card)
+        );
+        assert_eq!(loaded.closings, vec![closing]);
+    }
+
+    #[tokio::test]
+    async fn appends_audit_entries_with_their_values() {
+        let storage = memory_storage().await;
+        let session = Session::new(User {
+            username: "boss".to_string(),
+            role: Role::Admin,
+        });
+        let mut audit_log = AuditLog::new();
+        audit_log.record(&session, Change::role("till", Role::Cashier, Role::Manager));
+        storage.save_audit_log(&audit_log).await.unwrap();
+        audit_log.record(
+            &session,
+            Change {
+                action: AuditAction::ProductDeleted,
+                subject: "GAD-001".to_string(),
+                details: "deleted with quantity 0".to_string(),
+                before: Some(serde_json::json!({ "quantity": 0 })),
+                after: None,
+            },
+        );
+        storage.save_audit_log(&audit_log).await.unwrap();
+        storage.save_audit_log(&audit_log).await.unwrap();
+
+        let loaded = storage.load_audit_log().await.unwrap();
+        assert_eq!(loaded.entries.len(), 2);
+        assert_eq!(loaded.entries[0].username, "boss");
+        assert_eq!(loaded.entries[0].action, AuditAction::UserChanged);
+        assert_eq!(loaded.entries[0].after.as_ref().unwrap()["role"], "manager");
+        assert_eq!(loaded.entries[1].before.as_ref().unwrap()["quantity"], 0);
+        assert_eq!(loaded.entries[1].after, None);
+    }
+
+    #[tokio::test]
+    async fn remembers_the_import() {
+        let storage = memory_storage().await;
+        assert!(!storage.imported().await.unwrap());
+        storage.mark_imported().await.unwrap();
+        storage.mark_imported().await.unwrap();
+        assert!(storage.imported().await.unwrap());
+    }
+
+    fn product(sku: &str, quantity: u32) -> Product {
+        Product {
+            sku: sku.to_string(),
+            name: sku.to_string(),
+            description: String::new(),
+            price: Money::from_cents(1000),
+            cost: Money::from_cents(600),
+            quantity,
+            layers: Vec::new(),
+        }
+    }
+
+    #[tokio::test]
+    async fn keeps_changes_made_by_other_sessions() {
+        let path =
+            std::env::temp_dir().join(format!("rusty_store_sessions_{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let url = format!("sqlite:{}?mode=rwc", path.display());
+        let first = SqliteStorage::new(&url).await;
+        let mut inventory = Inventory::new();
+        inventory
+            .products
+            .insert("WID-001".to_string(), product("WID-001", 10));
+        inventory
+            .products
+            .insert("GAD-001".to_string(), product("GAD-001", 5));
+        first.save_inventory(&inventory).await.unwrap();
+
+        // Both sessions load the same data, then change different products
+        let second = SqliteStorage::new(&url).await;
+        let mut theirs = second.load_inventory().await.unwrap();
+        let mut ours = first.load_inventory().await.unwrap();
+        theirs.products.get_mut("GAD-001").unwrap().quantity = 4;
+        second.save_inventory(&theirs).await.unwrap();
+        ours.products.get_mut("WID-001").unwrap().quantity = 9;
+        first.save_inventory(&ours).await.unwrap();
+
+        let stored = second.load_inventory().await.unwrap();
+        assert_eq!(stored.products["WID-001"].quantity, 9);
+        assert_eq!(stored.products["GAD-001"].quantity, 4);
+
+        // A stale copy of a product the other session changed is refused
+        ours.products.get_mut("GAD-001").unwrap().quantity = 3;
+        assert!(first
+            .save_inventory(&ours)
+            .await
+            .unwrap_err()
+            .contains("Product 'GAD-001' was changed by another session"));
+        assert_eq!(
+            second.load_inventory().await.unwrap().products["GAD-001"].quantity,
+            4
+        );
+
+        // So is a transaction history that another session has added to
+        let session = Session::new(User {
+            username: "boss".to_string(),
+            role: Role::Admin,
+        });
+        let mut transactions = first.load_transactions().await.unwrap();
+        let mut other = second.load_transactions().await.unwrap();
+        other
+            .record_purchase(&session, &mut theirs, "GAD-001", 1, Money::from_cents(600))
+            .unwrap();
+        second.save_transactions(&other).await.unwrap();
+        transactions
+            .record_purchase(&session, &mut ours, "WID-001", 1, Money::from_cents(600))
+            .unwrap();
+        assert!(first
+            .save_transactions(&transactions)
+            .await
+            .unwrap_err()
+            .contains("The transaction history was changed by another session"));
+        assert_eq!(
+            second.load_transactions().await.unwrap().transactions.len(),
+            1
+        );
+
+        let _ = std::fs::remove_file(&path);
+    }
+}
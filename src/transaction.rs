@@ -1,36 +1,135 @@
-use std::{fmt, fs, io};
+use chrono::{DateTime, Local, NaiveDate, Utc};
 use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::str::FromStr;
 
-use crate::{
-    inventory::{Inventory, Product},
-    util,
-};
+use crate::closing::DayClosing;
+use crate::output::{self, OutputFormat, Section};
+use crate::{costing::CostingMethod, inventory::Inventory, money::Money, session::Session, util};
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum TransactionType {
     Sale,
     Purchase,
+    /// A customer returning items from an earlier sale, refunded at the price
+    /// they were sold for
+    Return,
+    /// Cancels the transaction in `reference_id`, reversing its effect on the
+    /// stock. The original stays in the history.
+    Void,
+}
+
+impl fmt::Display for TransactionType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(match self {
+            TransactionType::Sale => "Sale",
+            TransactionType::Purchase => "Purchase",
+            TransactionType::Return => "Return",
+            TransactionType::Void => "Void",
+        })
+    }
+}
+
+/// How a customer paid for a sale. Refunds and voids go back the same way.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum PaymentMethod {
+    Cash,
+    Card,
+    /// Vouchers, bank transfers and anything else
+    Other,
+}
+
+impl fmt::Display for PaymentMethod {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(match self {
+            PaymentMethod::Cash => "Cash",
+            PaymentMethod::Card => "Card",
+            PaymentMethod::Other => "Other",
+        })
+    }
+}
+
+impl FromStr for PaymentMethod {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        match input.trim().to_ascii_lowercase().as_str() {
+            "cash" => Ok(PaymentMethod::Cash),
+            "card" => Ok(PaymentMethod::Card),
+            "other" => Ok(PaymentMethod::Other),
+            _ => Err(format!(
+                "'{}' is not a payment method. Use cash, card or other.",
+                input.trim()
+            )),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Transaction {
+    /// Unique, increasing identifier assigned when the transaction is recorded
+    pub id: u64,
+    /// When the transaction was recorded. Transactions from before timestamps
+    /// were kept carry the Unix epoch.
+    pub recorded_at: DateTime<Utc>,
     pub transaction_type: TransactionType,
-    pub product_name: String,
+    pub sku: String,
+    pub quantity: u32,
+    pub price: Money, //Sale Price or Cost Price
+    pub total: Money, // Total Cost or Revenue
+    /// Cost of goods sold: what the units on a sale cost the store, or on a
+    /// return what went back into stock at that cost. Zero on purchases.
+    #[serde(default)]
+    pub cost: Money,
+    /// The sale order this line belongs to. Purchases, and sales recorded
+    /// before orders existed, have none.
+    pub order_id: Option<u64>,
+    /// The transaction this one refers back to, such as the sale a return
+    /// is made against.
+    #[serde(default)]
+    pub reference_id: Option<u64>,
+    /// Set on returns whose items came back damaged and were not restocked,
+    /// and on voids of such returns.
+    #[serde(default)]
+    pub damaged: bool,
+    /// The user who recorded the transaction. Transactions from before users
+    /// were tracked have none.
+    #[serde(default)]
+    pub recorded_by: Option<String>,
+    /// How a sale was paid, and so how its returns and voids were refunded.
+    /// Purchases, and sales from before payments were tracked, have none.
+    #[serde(default)]
+    pub payment_method: Option<PaymentMethod>,
+}
+
+/// One line of a sale order that has not been recorded yet.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OrderLine {
+    pub sku: String,
     pub quantity: u32,
-    pub price: f64, //Sale Price or Cost Price
-    pub total: f64, // Total Cost or Revenue
+    pub price: Money,
+}
+
+/// A recorded sale order: the sale transactions sharing an order id.
+#[derive(Debug)]
+pub struct Order<'a> {
+    pub id: u64,
+    /// The sale lines that have not been voided
+    pub lines: Vec<&'a Transaction>,
+    /// What the lines came to, less what was refunded for them
+    pub total: Money,
 }
 
 impl fmt::Display for Transaction {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(
             f,
-            "{:<10} {:<20} {:<10} {:<10.2} {:<10.2}",
-            match self.transaction_type {
-                TransactionType::Sale => "Sale",
-                TransactionType::Purchase => "Purchase",
-            },
-            self.product_name,
+            "{:<6} {:<17} {:<10} {:<20} {:<10} {:<10} {:<10}",
+            self.id,
+            format_timestamp(&self.recorded_at),
+            self.transaction_type,
+            self.sku,
             self.quantity,
             self.price,
             self.total
@@ -38,162 +137,633 @@ impl fmt::Display for Transaction {
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct TransactionManager {
     pub transactions: Vec<Transaction>,
+    /// Business days closed with a Z-report. Nothing can be recorded on a
+    /// closed day, and its transactions can no longer be voided.
+    #[serde(default)]
+    pub closings: Vec<DayClosing>,
 }
 
 impl TransactionManager {
     pub fn new() -> Self {
         Self {
             transactions: Vec::new(),
+            closings: Vec::new(),
         }
     }
 
-    pub fn save_to_file(&self, file_path: &str) -> io::Result<()> {
-        let json = serde_json::to_string_pretty(&self)?;
-        fs::write(file_path, json)?;
-        Ok(())
+    /// The closing of the local day `day`, if it has been closed.
+    pub fn closing(&self, day: NaiveDate) -> Option<&DayClosing> {
+        self.closings.iter().find(|closing| closing.day == day)
     }
 
-    pub fn load_from_file(file_path: &str) -> io::Result<Self> {
-        let json = fs::read_to_string(file_path)?;
-        let transactions = serde_json::from_str(&json)?;
-        Ok(transactions)
+    /// Refuses changes once the local day of `at` has been closed.
+    fn check_open(&self, at: DateTime<Utc>) -> Result<(), String> {
+        let day = business_day(at);
+        match self.closing(day) {
+            Some(_) => Err(format!(
+                "The business day {} has been closed, so it cannot be changed.",
+                day
+            )),
+            None => Ok(()),
+        }
+    }
+
+    /// The id the next recorded transaction will get.
+    fn next_id(&self) -> u64 {
+        self.transactions.iter().map(|t| t.id).max().unwrap_or(0) + 1
+    }
+
+    pub fn find(&self, id: u64) -> Option<&Transaction> {
+        self.transactions
+            .iter()
+            .find(|transaction| transaction.id == id)
     }
 
-    fn record_sale(
+    fn next_order_id(&self) -> u64 {
+        self.transactions
+            .iter()
+            .filter_map(|t| t.order_id)
+            .max()
+            .unwrap_or(0)
+            + 1
+    }
+
+    /// Looks up a recorded sale order by id. Returns and voids carry the
+    /// order id of their sale too, but only the sales are its lines.
+    pub fn order(&self, id: u64) -> Option<Order<'_>> {
+        let lines: Vec<&Transaction> = self
+            .sales()
+            .into_iter()
+            .filter(|sale| sale.order_id == Some(id))
+            .collect();
+        if lines.is_empty() {
+            return None;
+        }
+        let sold: Money = lines.iter().map(|line| line.total).sum();
+        let refunded: Money = self
+            .returns()
+            .iter()
+            .filter(|refund| refund.order_id == Some(id))
+            .map(|refund| refund.total)
+            .sum();
+        Some(Order {
+            id,
+            lines,
+            total: sold - refunded,
+        })
+    }
+
+    /// Records a single-line sale paid with `payment_method`.
+    pub fn record_sale(
         &mut self,
+        session: &Session,
         inventory: &mut Inventory,
-        product_name: &str,
+        sku: &str,
         quantity: u32,
-        sale_price: f64,
+        sale_price: Money,
+        payment_method: PaymentMethod,
     ) -> Result<(), String> {
-        let product = inventory.products.get_mut(product_name);
-        if let Some(product) = product {
-            if product.quantity < quantity {
-                return Err("Not enough stock to complete the sale.".to_string());
-            }
+        let line = OrderLine {
+            sku: sku.to_string(),
+            quantity,
+            price: sale_price,
+        };
+        self.record_order(session, inventory, &[line], payment_method)
+            .map(|_| ())
+    }
 
-            product.quantity -= quantity;
-            let total_revenue = sale_price * quantity as f64;
+    /// Records every line of a sale order and returns the order id. All lines
+    /// are checked against the stock first, so either every line decrements
+    /// the inventory or nothing is recorded.
+    pub fn record_order(
+        &mut self,
+        session: &Session,
+        inventory: &mut Inventory,
+        lines: &[OrderLine],
+        payment_method: PaymentMethod,
+    ) -> Result<u64, String> {
+        let recorded_at = Utc::now();
+        self.check_open(recorded_at)?;
+        if lines.is_empty() {
+            return Err("An order needs at least one line.".to_string());
+        }
 
+        // The same SKU may appear on several lines, so check the combined quantity
+        let mut requested: HashMap<&str, u32> = HashMap::new();
+        for line in lines {
+            if line.quantity == 0 {
+                return Err("Quantity must be greater than zero.".to_string());
+            }
+            *requested.entry(line.sku.as_str()).or_default() += line.quantity;
+        }
+        for (sku, quantity) in &requested {
+            match inventory.products.get(*sku) {
+                Some(product) if product.quantity < *quantity => {
+                    return Err("Not enough stock to complete the sale.".to_string())
+                }
+                Some(_) => {}
+                None => return Err(format!("Product '{}' not found.", sku)),
+            }
+        }
+
+        let order_id = self.next_order_id();
+        let method = inventory.costing_method;
+        for line in lines {
+            let cost = inventory
+                .products
+                .get_mut(&line.sku)
+                .map(|product| product.issue(line.quantity, method))
+                .unwrap_or_default();
             self.transactions.push(Transaction {
+                id: self.next_id(),
+                recorded_at,
                 transaction_type: TransactionType::Sale,
-                product_name: product_name.to_string(),
-                quantity,
-                price: sale_price,
-                total: total_revenue,
+                sku: line.sku.clone(),
+                quantity: line.quantity,
+                price: line.price,
+                total: line.price * line.quantity,
+                cost,
+                order_id: Some(order_id),
+                reference_id: None,
+                damaged: false,
+                recorded_by: Some(session.username().to_string()),
+                payment_method: Some(payment_method),
             });
-
-            Ok(())
-        } else {
-            Err("Product not found.".to_string())
         }
+
+        Ok(order_id)
     }
 
-    fn record_purchase(
+    /// Records stock bought for a product already in the inventory as a new
+    /// cost layer at the purchase price. The list price is left alone. Unknown
+    /// SKUs are rejected so a typo cannot create a phantom product.
+    pub fn record_purchase(
         &mut self,
+        session: &Session,
         inventory: &mut Inventory,
-        product_name: &str,
+        sku: &str,
         quantity: u32,
-        purchase_price: f64,
+        purchase_price: Money,
     ) -> Result<(), String> {
-        let product = inventory.products.get_mut(product_name);
-
-        if let Some(product) = product {
-            product.quantity += quantity;
-        } else {
-            inventory.products.insert(
-                product_name.to_string(),
-                Product {
-                    name: product_name.to_string(),
-                    description: "Purchased Product".to_string(),
-                    price: purchase_price,
-                    quantity,
-                },
-            );
+        let recorded_at = Utc::now();
+        self.check_open(recorded_at)?;
+        match inventory.products.get_mut(sku) {
+            Some(product) => {
+                product.receive(quantity, purchase_price, inventory.costing_method);
+                if inventory.costing_method != CostingMethod::WeightedAverage {
+                    product.cost = purchase_price;
+                }
+            }
+            None => {
+                return Err(format!(
+                    "Product '{}' not found. Add it to the inventory first.",
+                    sku
+                ))
+            }
         }
 
-        let total_cost = purchase_price * quantity as f64;
+        let total_cost = purchase_price * quantity;
 
         self.transactions.push(Transaction {
+            id: self.next_id(),
+            recorded_at,
             transaction_type: TransactionType::Purchase,
-            product_name: product_name.to_string(),
+            sku: sku.to_string(),
             quantity,
             price: purchase_price,
             total: total_cost,
+            cost: Money::ZERO,
+            order_id: None,
+            reference_id: None,
+            damaged: false,
+            recorded_by: Some(session.username().to_string()),
+            payment_method: None,
         });
 
         Ok(())
     }
 
+    /// How many units of the sale with `sale_id` have already been returned.
+    pub fn returned_quantity(&self, sale_id: u64) -> u32 {
+        self.returns()
+            .iter()
+            .filter(|transaction| transaction.reference_id == Some(sale_id))
+            .map(|transaction| transaction.quantity)
+            .sum()
+    }
+
+    /// Records a customer return against the sale with `sale_id` and returns
+    /// the new transaction id. The refund uses the original sale price. The
+    /// items go back into stock unless they are `damaged`.
+    pub fn record_return(
+        &mut self,
+        session: &Session,
+        inventory: &mut Inventory,
+        sale_id: u64,
+        quantity: u32,
+        damaged: bool,
+    ) -> Result<u64, String> {
+        let recorded_at = Utc::now();
+        self.check_open(recorded_at)?;
+        let sale = match self.find(sale_id) {
+            Some(sale) if sale.transaction_type == TransactionType::Sale => sale.clone(),
+            _ => return Err(format!("Sale {} not found.", sale_id)),
+        };
+        if self.is_voided(sale_id) {
+            return Err(format!("Sale {} has been voided.", sale_id));
+        }
+        if quantity == 0 {
+            return Err("Quantity must be greater than zero.".to_string());
+        }
+        let returnable = sale.quantity - self.returned_quantity(sale_id);
+        if quantity > returnable {
+            return Err(format!(
+                "Only {} unit(s) of sale {} can still be returned.",
+                returnable, sale_id
+            ));
+        }
+
+        if !damaged {
+            match inventory.products.get_mut(&sale.sku) {
+                Some(product) => product.receive(
+                    quantity,
+                    sale.cost.div_round(sale.quantity),
+                    inventory.costing_method,
+                ),
+                None => {
+                    return Err(format!(
+                        "Product '{}' is no longer in the inventory. Record the return as damaged.",
+                        sale.sku
+                    ))
+                }
+            }
+        }
+
+        // Restocked units take their share of the sale's cost back out of COGS
+        let cost = if damaged {
+            Money::ZERO
+        } else {
+            (sale.cost * quantity).div_round(sale.quantity)
+        };
+
+        let id = self.next_id();
+        self.transactions.push(Transaction {
+            id,
+            recorded_at,
+            transaction_type: TransactionType::Return,
+            sku: sale.sku,
+            quantity,
+            price: sale.price,
+            total: sale.price * quantity,
+            cost,
+            order_id: sale.order_id,
+            reference_id: Some(sale_id),
+            damaged,
+            recorded_by: Some(session.username().to_string()),
+            payment_method: sale.payment_method,
+        });
+
+        Ok(id)
+    }
+
+    /// The void entry that cancelled the transaction with `id`, if any.
+    pub fn voided_by(&self, id: u64) -> Option<&Transaction> {
+        self.transactions.iter().find(|transaction| {
+            transaction.transaction_type == TransactionType::Void
+                && transaction.reference_id == Some(id)
+        })
+    }
+
+    pub fn is_voided(&self, id: u64) -> bool {
+        self.voided_by(id).is_some()
+    }
+
+    /// The ids of every voided transaction, for checking many at once.
+    pub fn voided_ids(&self) -> HashSet<u64> {
+        self.transactions
+            .iter()
+            .filter(|transaction| transaction.transaction_type == TransactionType::Void)
+            .filter_map(|transaction| transaction.reference_id)
+            .collect()
+    }
+
+    /// Voids the transaction with `id` and returns the id of the compensating
+    /// entry. Sales are put back into stock, while purchases and restocked
+    /// returns are taken out of it again. Nothing is deleted: the original
+    /// keeps its place in the history and reports skip it from then on.
+    pub fn void_transaction(
+        &mut self,
+        session: &Session,
+        inventory: &mut Inventory,
+        id: u64,
+    ) -> Result<u64, String> {
+        let recorded_at = Utc::now();
+        self.check_open(recorded_at)?;
+        let original = match self.find(id) {
+            Some(original) => original.clone(),
+            None => return Err(format!("Transaction {} not found.", id)),
+        };
+        if let Some(closing) = self.closing(business_day(original.recorded_at)) {
+            return Err(format!(
+                "Transaction {} belongs to the business day {}, which has been closed.",
+                id, closing.day
+            ));
+        }
+        if original.transaction_type == TransactionType::Void {
+            return Err("A void cannot itself be voided.".to_string());
+        }
+        if let Some(void) = self.voided_by(id) {
+            return Err(format!(
+                "Transaction {} was already voided by transaction {}.",
+                id, void.id
+            ));
+        }
+        if original.transaction_type == TransactionType::Sale && self.returned_quantity(id) > 0 {
+            return Err(format!(
+                "Sale {} has returns against it. Void the returns first.",
+                id
+            ));
+        }
+
+        // A voided sale puts its units back at what they cost, while voiding a
+        // purchase or a restocked return takes the units out again. Sales of no
+        // units, left over from older data, have nothing to put back.
+        let restocks = original.transaction_type == TransactionType::Sale && original.quantity > 0;
+        let takes_back = original.transaction_type == TransactionType::Purchase
+            || (original.transaction_type == TransactionType::Return && !original.damaged);
+        // Units come back out of the layer the original made, at its unit cost
+        let received_at = if original.transaction_type == TransactionType::Return {
+            match original.reference_id.and_then(|sale_id| self.find(sale_id)) {
+                Some(sale) if sale.quantity > 0 => sale.cost.div_round(sale.quantity),
+                _ => original.cost.div_round(original.quantity.max(1)),
+            }
+        } else {
+            original.price
+        };
+        // The cost the product had before the voided purchase set it
+        let earlier_cost = self
+            .purchases()
+            .into_iter()
+            .rev()
+            .find(|purchase| purchase.sku == original.sku && purchase.id < id)
+            .map(|purchase| purchase.price);
+        if restocks || takes_back {
+            let method = inventory.costing_method;
+            let Some(product) = inventory.products.get_mut(&original.sku) else {
+                return Err(format!(
+                    "Product '{}' is no longer in the inventory.",
+                    original.sku
+                ));
+            };
+            if restocks {
+                let unit_cost = original.cost.div_round(original.quantity);
+                product.receive(original.quantity, unit_cost, method);
+            } else if product.quantity < original.quantity {
+                return Err(format!(
+                    "Only {} unit(s) of '{}' are in stock, so the void cannot take back {}.",
+                    product.quantity, original.sku, original.quantity
+                ));
+            } else {
+                product.take_back(original.quantity, received_at, method);
+                if original.transaction_type == TransactionType::Purchase
+                    && method != CostingMethod::WeightedAverage
+                    && product.cost == original.price
+                {
+                    let newest = product.layers.last().map(|layer| layer.unit_cost);
+                    if let Some(cost) = earlier_cost.or(newest) {
+                        product.cost = cost;
+                    }
+                }
+            }
+        }
+
+        let void_id = self.next_id();
+        self.transactions.push(Transaction {
+            id: void_id,
+            recorded_at,
+            transaction_type: TransactionType::Void,
+            sku: original.sku,
+            quantity: original.quantity,
+            price: original.price,
+            total: original.total,
+            cost: original.cost,
+            order_id: original.order_id,
+            reference_id: Some(id),
+            damaged: original.damaged,
+            recorded_by: Some(session.username().to_string()),
+            payment_method: original.payment_method,
+        });
+
+        Ok(void_id)
+    }
+
     fn list_transactions(&self) -> &Vec<Transaction> {
         &self.transactions
     }
 
-    pub fn sales(&self) -> Vec<&Transaction> {
+    /// The transactions of one type that have not been voided.
+    fn effective(&self, transaction_type: TransactionType) -> Vec<&Transaction> {
+        let voided = self.voided_ids();
         self.transactions
             .iter()
-            .filter(|transaction| transaction.transaction_type == TransactionType::Sale)
+            .filter(|transaction| transaction.transaction_type == transaction_type)
+            .filter(|transaction| !voided.contains(&transaction.id))
             .collect()
     }
 
+    /// Sales that have not been voided.
+    pub fn sales(&self) -> Vec<&Transaction> {
+        self.effective(TransactionType::Sale)
+    }
+
+    /// Purchases that have not been voided.
     pub fn purchases(&self) -> Vec<&Transaction> {
-        self.transactions
+        self.effective(TransactionType::Purchase)
+    }
+
+    /// Returns that have not been voided.
+    pub fn returns(&self) -> Vec<&Transaction> {
+        self.effective(TransactionType::Return)
+    }
+
+    /// Cost of the goods actually sold: sales less restocked returns.
+    pub fn cost_of_goods_sold(&self) -> Money {
+        let sold: Money = self.sales().iter().map(|sale| sale.cost).sum();
+        let restocked: Money = self.returns().iter().map(|refund| refund.cost).sum();
+        sold - restocked
+    }
+
+    /// The transactions recorded from `from` to `to`, both local days
+    /// inclusive, along with any voids of them so they stay cancelled. An
+    /// open end is unbounded.
+    pub fn between(&self, from: Option<NaiveDate>, to: Option<NaiveDate>) -> TransactionManager {
+        let in_range = |transaction: &Transaction| {
+            let day = business_day(transaction.recorded_at);
+            from.is_none_or(|from| day >= from) && to.is_none_or(|to| day <= to)
+        };
+        let kept: HashSet<u64> = self
+            .transactions
             .iter()
-            .filter(|transaction| transaction.transaction_type == TransactionType::Purchase)
-            .collect()
+            .filter(|transaction| in_range(transaction))
+            .map(|transaction| transaction.id)
+            .collect();
+        TransactionManager {
+            transactions: self
+                .transactions
+                .iter()
+                .filter(|transaction| {
+                    kept.contains(&transaction.id)
+                        || (transaction.transaction_type == TransactionType::Void
+                            && transaction
+                                .reference_id
+                                .is_some_and(|id| kept.contains(&id)))
+                })
+                .cloned()
+                .collect(),
+            closings: self.closings.clone(),
+        }
     }
 }
 
-/// Handles Sales transactions.
+/// Handles Sales transactions. The cashier adds lines until the order is
+/// finished, then the whole order is recorded at once.
 ///
 /// # Arguments
 ///
 /// * transaction_manager - A mutable instance of `TransactionManager`
 /// * inventory - The Inventory list of products to sale from
+/// * session - The logged-in user the sale is recorded under
 ///
 pub fn handle_sale_transaction(
     transaction_manager: &mut TransactionManager,
     inventory: &mut Inventory,
+    session: &Session,
 ) {
     println!("\n--- Record Sale ---");
+    println!("Add the items in the order. Leave the SKU blank to finish.");
 
-    let product_name = util::get_user_input("Enter product name: ");
-    let quantity = util::get_user_input("Enter quantity sold: ")
-        .parse::<u32>()
-        .unwrap_or_else(|_| {
-            println!("Invalid quantity. Defaulting to 0.");
-            0
-        });
-    let price = util::get_user_input("Enter sale price: ")
-        .parse::<f64>()
-        .unwrap_or_else(|_| {
-            println!("Invalid price. Defaulting to 0.0.");
-            0.0
+    let mut lines: Vec<OrderLine> = Vec::new();
+    loop {
+        let sku = util::get_user_input("Enter product SKU: ");
+        if sku.is_empty() {
+            break;
+        }
+        let Some(product) = inventory.products.get(&sku) else {
+            println!("Product '{}' not found.", sku);
+            continue;
+        };
+
+        let quantity = match util::get_user_input("Enter quantity sold: ").parse::<u32>() {
+            Ok(quantity) if quantity > 0 => quantity,
+            _ => {
+                println!("Invalid quantity. Line skipped.");
+                continue;
+            }
+        };
+        let input = util::get_user_input(&format!(
+            "Enter sale price (leave blank for ${}): ",
+            product.price
+        ));
+        let price = if input.is_empty() {
+            product.price
+        } else {
+            match input.parse::<Money>() {
+                Ok(price) => price,
+                Err(_) => {
+                    println!("Invalid price. Line skipped.");
+                    continue;
+                }
+            }
+        };
+
+        lines.push(OrderLine {
+            sku,
+            quantity,
+            price,
         });
+        let running_total: Money = lines.iter().map(|line| line.price * line.quantity).sum();
+        println!("Line added. Order total so far: ${}", running_total);
+    }
+
+    if lines.is_empty() {
+        println!("No items entered. Sale cancelled.");
+        return;
+    }
+
+    let input =
+        util::get_user_input("Payment method (cash, card or other; leave blank for cash): ");
+    let payment_method = if input.is_empty() {
+        PaymentMethod::Cash
+    } else {
+        match input.parse::<PaymentMethod>() {
+            Ok(payment_method) => payment_method,
+            Err(e) => {
+                println!("{} Sale cancelled.", e);
+                return;
+            }
+        }
+    };
 
-    match transaction_manager.record_sale(inventory, &product_name, quantity, price) {
-        Ok(_) => println!("Sale recorded successfully."),
-        Err(e) => println!("Error recording sale: {}", e),
+    match transaction_manager.record_order(session, inventory, &lines, payment_method) {
+        Ok(order_id) => {
+            println!("Sale recorded successfully.");
+            if let Some(order) = transaction_manager.order(order_id) {
+                print_receipt(&order, inventory);
+            }
+        }
+        Err(e) => println!("Error recording sale: {} Nothing was recorded.", e),
     }
 }
 
+/// Prints the lines and total of a recorded order.
+fn print_receipt(order: &Order<'_>, inventory: &Inventory) {
+    let headers = vec![
+        "ID",
+        "SKU",
+        "Product",
+        "Quantity",
+        "Price per Unit",
+        "Amount",
+    ];
+    let rows: Vec<Vec<String>> = order
+        .lines
+        .iter()
+        .map(|line| {
+            vec![
+                line.id.to_string(),
+                line.sku.clone(),
+                inventory.product_name(&line.sku).to_string(),
+                line.quantity.to_string(),
+                format!("${}", line.price),
+                format!("${}", line.total),
+            ]
+        })
+        .collect();
+
+    println!("\n--- Receipt: Order #{} ---", order.id);
+    println!("{}", util::format_table(headers, rows));
+    println!("Order Total: ${}", order.total);
+}
+
 /// Handles Purchase transactions.
 ///
 /// # Arguments
 ///
 /// * transaction_manager - A mutable instance of `TransactionManager`
 /// * inventory - The Inventory list of products to sale from
+/// * session - The logged-in user the purchase is recorded under
 ///
 pub fn handle_purchase_transaction(
     transaction_manager: &mut TransactionManager,
     inventory: &mut Inventory,
+    session: &Session,
 ) {
     println!("\n--- Record Purchase ---");
 
-    let product_name = util::get_user_input("Enter product name: ");
+    let sku = util::get_user_input("Enter product SKU: ");
     let quantity = util::get_user_input("Enter quantity purchased: ")
         .parse::<u32>()
         .unwrap_or_else(|_| {
@@ -202,62 +772,217 @@ pub fn handle_purchase_transaction(
         });
 
     let price = util::get_user_input("Enter purchase price: ")
-        .parse::<f64>()
+        .parse::<Money>()
         .unwrap_or_else(|_| {
-            println!("Invalid price. Defaulting to 0.0.");
-            0.0
+            println!("Invalid price. Defaulting to 0.00.");
+            Money::ZERO
         });
 
-    match transaction_manager.record_purchase(inventory, &product_name, quantity, price) {
+    match transaction_manager.record_purchase(session, inventory, &sku, quantity, price) {
         Ok(_) => println!("Purchase recorded successfully."),
         Err(e) => println!("Error recording purchase: {}", e),
     }
 }
 
+/// Handles customer returns against an earlier sale.
+///
+/// # Arguments
+///
+/// * transaction_manager - A mutable instance of `TransactionManager`
+/// * inventory - The Inventory the returned items go back into
+/// * session - The logged-in user the return is recorded under
+///
+pub fn handle_return_transaction(
+    transaction_manager: &mut TransactionManager,
+    inventory: &mut Inventory,
+    session: &Session,
+) {
+    println!("\n--- Record Return ---");
+
+    let sale_id =
+        match util::get_user_input("Enter the ID of the sale being returned: ").parse::<u64>() {
+            Ok(id) => id,
+            Err(_) => {
+                println!("Invalid transaction ID.");
+                return;
+            }
+        };
+    if let Some(sale) = transaction_manager.find(sale_id) {
+        println!(
+            "Sale {}: {} x {} at ${} ({} already returned)",
+            sale.id,
+            sale.quantity,
+            inventory.product_name(&sale.sku),
+            sale.price,
+            transaction_manager.returned_quantity(sale_id)
+        );
+    }
+
+    let quantity = util::get_user_input("Enter quantity returned: ")
+        .parse::<u32>()
+        .unwrap_or_else(|_| {
+            println!("Invalid quantity. Defaulting to 0.");
+            0
+        });
+    let damaged = util::get_user_input("Are the items damaged? (y/N): ").eq_ignore_ascii_case("y");
+
+    match transaction_manager.record_return(session, inventory, sale_id, quantity, damaged) {
+        Ok(id) => {
+            let refund = transaction_manager
+                .find(id)
+                .map(|t| t.total)
+                .unwrap_or_default();
+            println!("Return recorded successfully. Refund: ${}", refund);
+            if damaged {
+                println!("Items marked as damaged and not restocked.");
+            }
+        }
+        Err(e) => println!("Error recording return: {}", e),
+    }
+}
+
+/// Handles voiding a mis-keyed transaction by ID.
+///
+/// # Arguments
+///
+/// * transaction_manager - A mutable instance of `TransactionManager`
+/// * inventory - The Inventory whose stock the void corrects
+/// * session - The logged-in user the void is recorded under
+///
+pub fn handle_void_transaction(
+    transaction_manager: &mut TransactionManager,
+    inventory: &mut Inventory,
+    session: &Session,
+) {
+    println!("\n--- Void Transaction ---");
+
+    let id = match util::get_user_input("Enter the ID of the transaction to void: ").parse::<u64>()
+    {
+        Ok(id) => id,
+        Err(_) => {
+            println!("Invalid transaction ID.");
+            return;
+        }
+    };
+    let Some(transaction) = transaction_manager.find(id) else {
+        println!("Transaction {} not found.", id);
+        return;
+    };
+    println!(
+        "{} {}: {} x {} at ${}, total ${}",
+        transaction.transaction_type,
+        transaction.id,
+        transaction.quantity,
+        inventory.product_name(&transaction.sku),
+        transaction.price,
+        transaction.total
+    );
+    if !util::get_user_input("Void this transaction? (y/N): ").eq_ignore_ascii_case("y") {
+        println!("Void cancelled.");
+        return;
+    }
+
+    match transaction_manager.void_transaction(session, inventory, id) {
+        Ok(void_id) => println!("Transaction {} voided by transaction {}.", id, void_id),
+        Err(e) => println!("Error voiding transaction: {}", e),
+    }
+}
+
 /// A public function that lists all the transactions.
 ///
 /// # Arguments
 ///
 /// * transaction_manager - An instance of `TransactionManager`
+/// * inventory - The Inventory used to look up current product names
 ///
-pub fn list_transactions(transaction_manager: &TransactionManager) {
+pub fn list_transactions(transaction_manager: &TransactionManager, inventory: &Inventory) {
+    let format = output::ask_format().unwrap_or(OutputFormat::Table);
+    let section = transactions_section(transaction_manager, inventory);
+    println!("{}", output::render(format, &[section]));
+}
+
+/// Every transaction recorded, oldest first, with voided ones marked.
+pub fn transactions_section(
+    transaction_manager: &TransactionManager,
+    inventory: &Inventory,
+) -> Section {
     // Headers for the transaction table
-    let headers = vec![
-        "No",
+    let headers = [
+        "ID",
+        "Order",
+        "Recorded At",
         "Type",
+        "Ref",
+        "SKU",
         "Product",
         "Quantity",
         "Price per Unit",
         "Total Amount",
+        "By",
     ];
 
     // Collect all transactions (sales and purchases) into a single list
     let transactions = transaction_manager.list_transactions();
+    let voided = transaction_manager.voided_ids();
 
     let mut rows: Vec<Vec<String>> = Vec::new();
-    for (i, transaction) in transactions.iter().enumerate() {
-        let trans_type = match transaction.transaction_type {
-            TransactionType::Sale => "Sale",
-            TransactionType::Purchase => "Purchase",
-        };
-        let total_amount = transaction.price * transaction.quantity as f64;
+    for transaction in transactions.iter() {
+        let mut trans_type = transaction.transaction_type.to_string();
+        if transaction.damaged {
+            trans_type.push_str(" (damaged)");
+        }
+        if voided.contains(&transaction.id) {
+            trans_type.push_str(" (voided)");
+        }
         rows.push(vec![
-            (i + 1).to_string(),
-            trans_type.to_string(),
-            transaction.product_name.clone(),
+            transaction.id.to_string(),
+            transaction
+                .order_id
+                .map(|id| id.to_string())
+                .unwrap_or_else(|| "-".to_string()),
+            format_timestamp(&transaction.recorded_at),
+            trans_type,
+            transaction
+                .reference_id
+                .map(|id| id.to_string())
+                .unwrap_or_else(|| "-".to_string()),
+            transaction.sku.clone(),
+            inventory.product_name(&transaction.sku).to_string(),
             transaction.quantity.to_string(),
-            format!("${:.2}", transaction.price),
-            format!("${:.2}", total_amount),
+            transaction.price.to_string(),
+            transaction.total.to_string(),
+            transaction
+                .recorded_by
+                .clone()
+                .unwrap_or_else(|| "-".to_string()),
         ])
     }
 
-    let formatted_table = util::format_table(headers, rows);
-    println!("{}", formatted_table);
+    Section::new("Transactions", &headers, rows)
+}
+
+/// The local calendar day `at` falls on, which is the business day it
+/// belongs to.
+pub fn business_day(at: DateTime<Utc>) -> NaiveDate {
+    at.with_timezone(&Local).date_naive()
+}
+
+/// Formats a timestamp in the store's local time zone.
+pub fn format_timestamp(timestamp: &DateTime<Utc>) -> String {
+    timestamp
+        .with_timezone(&Local)
+        .format("%Y-%m-%d %H:%M")
+        .to_string()
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::auth::User;
+    use crate::costing::CostLayer;
+    use crate::inventory::Product;
+    use crate::money::Money;
+    use crate::permission::Role;
 
     #[test]
     fn records_a_valid_sale() {
@@ -265,27 +990,39 @@ mod tests {
         let mut transaction_manager = TransactionManager::new();
 
         inventory.products.insert(
-            "Widget".to_string(),
+            "WID-001".to_string(),
             Product {
+                sku: "WID-001".to_string(),
                 name: "Widget".to_string(),
                 description: "A test widget".to_string(),
-                price: 50.0,
+                price: Money::from_cents(5000),
+                cost: Money::from_cents(3000),
                 quantity: 100,
+                layers: Vec::new(),
             },
         );
 
         // Record a sale
-        let result = transaction_manager.record_sale(&mut inventory, "Widget", 10, 55.0);
+        let result = transaction_manager.record_sale(
+            &session(),
+            &mut inventory,
+            "WID-001",
+            10,
+            Money::from_cents(5500),
+            PaymentMethod::Card,
+        );
         assert!(result.is_ok());
-        assert_eq!(inventory.products.get("Widget").unwrap().quantity, 90);
+        assert_eq!(inventory.products.get("WID-001").unwrap().quantity, 90);
         assert_eq!(transaction_manager.transactions.len(), 1);
 
         let transaction = &transaction_manager.transactions[0];
         assert_eq!(transaction.transaction_type, TransactionType::Sale);
-        assert_eq!(transaction.product_name, "Widget");
+        assert_eq!(transaction.sku, "WID-001");
         assert_eq!(transaction.quantity, 10);
-        assert_eq!(transaction.price, 55.0);
-        assert_eq!(transaction.total, 550.0); // 10 * 55
+        assert_eq!(transaction.price, Money::from_cents(5500));
+        assert_eq!(transaction.total, Money::from_cents(55000)); // 10 * 55
+        assert_eq!(transaction.recorded_by.as_deref(), Some("till"));
+        assert_eq!(transaction.payment_method, Some(PaymentMethod::Card));
     }
 
     #[test]
@@ -295,24 +1032,34 @@ mod tests {
 
         // Add a product to the inventory
         inventory.products.insert(
-            "Widget".to_string(),
+            "WID-001".to_string(),
             Product {
+                sku: "WID-001".to_string(),
                 name: "Widget".to_string(),
                 description: "A test widget".to_string(),
-                price: 50.0,
+                price: Money::from_cents(5000),
+                cost: Money::from_cents(3000),
                 quantity: 5,
+                layers: Vec::new(),
             },
         );
 
         // Attempt to sell more than available stock
-        let result = transaction_manager.record_sale(&mut inventory, "Widget", 10, 55.0);
+        let result = transaction_manager.record_sale(
+            &session(),
+            &mut inventory,
+            "WID-001",
+            10,
+            Money::from_cents(5500),
+            PaymentMethod::Cash,
+        );
 
         assert!(result.is_err());
         assert_eq!(
             result.unwrap_err(),
             "Not enough stock to complete the sale."
         );
-        assert_eq!(inventory.products.get("Widget").unwrap().quantity, 5); // Stock unchanged
+        assert_eq!(inventory.products.get("WID-001").unwrap().quantity, 5); // Stock unchanged
         assert!(transaction_manager.transactions.is_empty());
     }
 
@@ -323,51 +1070,59 @@ mod tests {
 
         // Add a product to the inventory
         inventory.products.insert(
-            "Widget".to_string(),
+            "WID-001".to_string(),
             Product {
+                sku: "WID-001".to_string(),
                 name: "Widget".to_string(),
                 description: "A test widget".to_string(),
-                price: 50.0,
+                price: Money::from_cents(5000),
+                cost: Money::from_cents(3000),
                 quantity: 5,
+                layers: Vec::new(),
             },
         );
 
         // Record a purchase
-        let result = transaction_manager.record_purchase(&mut inventory, "Widget", 20, 45.0);
+        let result = transaction_manager.record_purchase(
+            &session(),
+            &mut inventory,
+            "WID-001",
+            20,
+            Money::from_cents(4500),
+        );
 
         assert!(result.is_ok());
-        assert_eq!(inventory.products.get("Widget").unwrap().quantity, 25); // Stock increased
+        assert_eq!(inventory.products.get("WID-001").unwrap().quantity, 25); // Stock increased
         assert_eq!(transaction_manager.transactions.len(), 1);
 
         let transaction = &transaction_manager.transactions[0];
         assert_eq!(transaction.transaction_type, TransactionType::Purchase);
-        assert_eq!(transaction.product_name, "Widget");
+        assert_eq!(transaction.sku, "WID-001");
         assert_eq!(transaction.quantity, 20);
-        assert_eq!(transaction.price, 45.0);
-        assert_eq!(transaction.total, 900.0); // 20 * 45
+        assert_eq!(transaction.price, Money::from_cents(4500));
+        assert_eq!(transaction.total, Money::from_cents(90000)); // 20 * 45
     }
 
     #[test]
-    fn record_purchase_of_new_product() {
+    fn record_purchase_of_unknown_product_is_rejected() {
         let mut inventory = Inventory::new();
         let mut transaction_manager = TransactionManager::new();
 
-        // Record a purchase for a new product
-        let result = transaction_manager.record_purchase(&mut inventory, "Gadget", 15, 30.0);
-
-        assert!(result.is_ok());
-        let product = inventory.products.get("Gadget").unwrap();
-        assert_eq!(product.quantity, 15); // New stock added
-        assert_eq!(product.price, 30.0); // Price set as purchase price
-
-        assert_eq!(transaction_manager.transactions.len(), 1);
+        // Record a purchase for a SKU that is not in the inventory
+        let result = transaction_manager.record_purchase(
+            &session(),
+            &mut inventory,
+            "GAD-001",
+            15,
+            Money::from_cents(3000),
+        );
 
-        let transaction = &transaction_manager.transactions[0];
-        assert_eq!(transaction.transaction_type, TransactionType::Purchase);
-        assert_eq!(transaction.product_name, "Gadget");
-        assert_eq!(transaction.quantity, 15);
-        assert_eq!(transaction.price, 30.0);
-        assert_eq!(transaction.total, 450.0); // 15 * 30
+        assert_eq!(
+            result.unwrap_err(),
+            "Product 'GAD-001' not found. Add it to the inventory first."
+        );
+        assert!(inventory.products.is_empty()); // No phantom product created
+        assert!(transaction_manager.transactions.is_empty());
     }
 
     #[test]
@@ -376,32 +1131,641 @@ mod tests {
 
         // Add some dummy transactions
         transaction_manager.transactions.push(Transaction {
+            id: 1,
+            recorded_at: Utc::now(),
             transaction_type: TransactionType::Sale,
-            product_name: "Widget".to_string(),
+            sku: "WID-001".to_string(),
             quantity: 5,
-            price: 50.0,
-            total: 250.0,
+            price: Money::from_cents(5000),
+            total: Money::from_cents(25000),
+            cost: Money::ZERO,
+            order_id: None,
+            reference_id: None,
+            damaged: false,
+            recorded_by: None,
+            payment_method: None,
         });
 
         transaction_manager.transactions.push(Transaction {
+            id: 2,
+            recorded_at: Utc::now(),
             transaction_type: TransactionType::Purchase,
-            product_name: "Gadget".to_string(),
+            sku: "GAD-001".to_string(),
             quantity: 10,
-            price: 30.0,
-            total: 300.0,
+            price: Money::from_cents(3000),
+            total: Money::from_cents(30000),
+            cost: Money::ZERO,
+            order_id: None,
+            reference_id: None,
+            damaged: false,
+            recorded_by: None,
+            payment_method: None,
         });
 
         let transactions = transaction_manager.list_transactions();
         assert_eq!(transactions.len(), 2);
 
         let first = &transactions[0];
+        assert_eq!(first.id, 1);
         assert_eq!(first.transaction_type, TransactionType::Sale);
-        assert_eq!(first.product_name, "Widget");
-        assert_eq!(first.total, 250.0);
+        assert_eq!(first.sku, "WID-001");
+        assert_eq!(first.total, Money::from_cents(25000));
 
         let second = &transactions[1];
         assert_eq!(second.transaction_type, TransactionType::Purchase);
-        assert_eq!(second.product_name, "Gadget");
-        assert_eq!(second.total, 300.0);
+        assert_eq!(second.sku, "GAD-001");
+        assert_eq!(second.total, Money::from_cents(30000));
+    }
+
+    #[test]
+    fn assigns_increasing_ids_and_timestamps() {
+        let mut inventory = Inventory::new();
+        let mut transaction_manager = TransactionManager::new();
+
+        inventory.products.insert(
+            "WID-001".to_string(),
+            Product {
+                sku: "WID-001".to_string(),
+                name: "Widget".to_string(),
+                description: "A test widget".to_string(),
+                price: Money::from_cents(5000),
+                cost: Money::from_cents(3000),
+                quantity: 5,
+                layers: Vec::new(),
+            },
+        );
+
+        let before = Utc::now();
+        transaction_manager
+            .record_purchase(
+                &session(),
+                &mut inventory,
+                "WID-001",
+                10,
+                Money::from_cents(4500),
+            )
+            .unwrap();
+        transaction_manager
+            .record_sale(
+                &session(),
+                &mut inventory,
+                "WID-001",
+                2,
+                Money::from_cents(5500),
+                PaymentMethod::Cash,
+            )
+            .unwrap();
+
+        let ids: Vec<u64> = transaction_manager
+            .transactions
+            .iter()
+            .map(|t| t.id)
+            .collect();
+        assert_eq!(ids, vec![1, 2]);
+        assert!(transaction_manager.transactions[0].recorded_at >= before);
+        assert_eq!(
+            transaction_manager.find(2).unwrap().transaction_type,
+            TransactionType::Sale
+        );
+    }
+
+    fn session() -> Session {
+        Session::new(User {
+            username: "till".to_string(),
+            role: Role::Cashier,
+        })
+    }
+
+    fn stocked_inventory() -> Inventory {
+        let mut inventory = Inventory::new();
+        for (sku, name, quantity) in [("WID-001", "Widget", 10), ("GAD-001", "Gadget", 3)] {
+            inventory.products.insert(
+                sku.to_string(),
+                Product {
+                    sku: sku.to_string(),
+                    name: name.to_string(),
+                    description: "A test product".to_string(),
+                    price: Money::from_cents(1000),
+                    cost: Money::from_cents(600),
+                    quantity,
+                    layers: Vec::new(),
+                },
+            );
+        }
+        inventory
+    }
+
+    fn line(sku: &str, quantity: u32, cents: i64) -> OrderLine {
+        OrderLine {
+            sku: sku.to_string(),
+            quantity,
+            price: Money::from_cents(cents),
+        }
+    }
+
+    #[test]
+    fn records_multi_line_order() {
+        let mut inventory = stocked_inventory();
+        let mut transaction_manager = TransactionManager::new();
+
+        let order_id = transaction_manager
+            .record_order(
+                &session(),
+                &mut inventory,
+                &[line("WID-001", 4, 1000), line("GAD-001", 2, 2550)],
+                PaymentMethod::Card,
+            )
+            .unwrap();
+
+        assert_eq!(inventory.products.get("WID-001").unwrap().quantity, 6);
+        assert_eq!(inventory.products.get("GAD-001").unwrap().quantity, 1);
+
+        let order = transaction_manager.order(order_id).unwrap();
+        assert_eq!(order.lines.len(), 2);
+        assert_eq!(order.total, Money::from_cents(9100)); // 4 * 10.00 + 2 * 25.50
+        assert!(order
+            .lines
+            .iter()
+            .all(|l| l.transaction_type == TransactionType::Sale));
+    }
+
+    #[test]
+    fn order_total_goes_down_with_returns_and_voids() {
+        let mut inventory = stocked_inventory();
+        let mut transaction_manager = TransactionManager::new();
+        let order_id = transaction_manager
+            .record_order(
+                &session(),
+                &mut inventory,
+                &[line("WID-001", 4, 1000), line("GAD-001", 2, 2550)],
+                PaymentMethod::Cash,
+            )
+            .unwrap();
+
+        transaction_manager
+            .record_return(&session(), &mut inventory, 1, 1, false)
+            .unwrap();
+        transaction_manager
+            .void_transaction(&session(), &mut inventory, 2)
+            .unwrap();
+
+        let order = transaction_manager.order(order_id).unwrap();
+        assert_eq!(order.lines.len(), 1);
+        assert_eq!(order.lines[0].id, 1);
+        assert_eq!(order.total, Money::from_cents(3000)); // 4 * 10.00 less 1 returned
+    }
+
+    #[test]
+    fn lists_amounts_as_plain_decimals() {
+        let mut inventory = stocked_inventory();
+        let mut transaction_manager = TransactionManager::new();
+        transaction_manager
+            .record_sale(
+                &session(),
+                &mut inventory,
+                "WID-001",
+                2,
+                Money::from_cents(1250),
+                PaymentMethod::Cash,
+            )
+            .unwrap();
+
+        let section = transactions_section(&transaction_manager, &inventory);
+        assert_eq!(section.rows[0][8], "12.50");
+        assert_eq!(section.rows[0][9], "25.00");
+    }
+
+    #[test]
+    fn failing_line_records_nothing() {
+        let mut inventory = stocked_inventory();
+        let mut transaction_manager = TransactionManager::new();
+
+        // Each line alone fits the stock of 3 gadgets, together they do not
+        let result = transaction_manager.record_order(
+            &session(),
+            &mut inventory,
+            &[
+                line("WID-001", 1, 1000),
+                line("GAD-001", 2, 1000),
+                line("GAD-001", 2, 1000),
+            ],
+            PaymentMethod::Cash,
+        );
+
+        assert_eq!(
+            result.unwrap_err(),
+            "Not enough stock to complete the sale."
+        );
+        assert_eq!(inventory.products.get("WID-001").unwrap().quantity, 10);
+        assert_eq!(inventory.products.get("GAD-001").unwrap().quantity, 3);
+        assert!(transaction_manager.transactions.is_empty());
+
+        let result = transaction_manager.record_order(
+            &session(),
+            &mut inventory,
+            &[line("WID-001", 1, 1000), line("NOPE", 1, 1000)],
+            PaymentMethod::Cash,
+        );
+        assert_eq!(result.unwrap_err(), "Product 'NOPE' not found.");
+        assert!(transaction_manager.transactions.is_empty());
+    }
+
+    #[test]
+    fn return_restocks_and_refunds_sale_price() {
+        let mut inventory = stocked_inventory();
+        let mut transaction_manager = TransactionManager::new();
+        transaction_manager
+            .record_sale(
+                &session(),
+                &mut inventory,
+                "WID-001",
+                4,
+                Money::from_cents(1250),
+                PaymentMethod::Cash,
+            )
+            .unwrap();
+
+        let id = transaction_manager
+            .record_return(&session(), &mut inventory, 1, 3, false)
+            .unwrap();
+
+        let refund = transaction_manager.find(id).unwrap();
+        assert_eq!(refund.transaction_type, TransactionType::Return);
+        assert_eq!(refund.reference_id, Some(1));
+        assert_eq!(refund.total, Money::from_cents(3750));
+        assert_eq!(inventory.products.get("WID-001").unwrap().quantity, 9);
+
+        let result = transaction_manager.record_return(&session(), &mut inventory, 1, 2, false);
+        assert_eq!(
+            result.unwrap_err(),
+            "Only 1 unit(s) of sale 1 can still be returned."
+        );
+    }
+
+    #[test]
+    fn damaged_return_is_not_restocked() {
+        let mut inventory = stocked_inventory();
+        let mut transaction_manager = TransactionManager::new();
+        transaction_manager
+            .record_sale(
+                &session(),
+                &mut inventory,
+                "GAD-001",
+                2,
+                Money::from_cents(1000),
+                PaymentMethod::Cash,
+            )
+            .unwrap();
+
+        transaction_manager
+            .record_return(&session(), &mut inventory, 1, 2, true)
+            .unwrap();
+
+        assert_eq!(inventory.products.get("GAD-001").unwrap().quantity, 1);
+        assert!(transaction_manager.returns()[0].damaged);
+    }
+
+    #[test]
+    fn void_reverses_stock_and_keeps_history() {
+        let mut inventory = stocked_inventory();
+        let mut transaction_manager = TransactionManager::new();
+        transaction_manager
+            .record_sale(
+                &session(),
+                &mut inventory,
+                "WID-001",
+                4,
+                Money::from_cents(1000),
+                PaymentMethod::Cash,
+            )
+            .unwrap();
+        transaction_manager
+            .record_purchase(
+                &session(),
+                &mut inventory,
+                "GAD-001",
+                5,
+                Money::from_cents(600),
+            )
+            .unwrap();
+
+        let void_id = transaction_manager
+            .void_transaction(&session(), &mut inventory, 1)
+            .unwrap();
+        transaction_manager
+            .void_transaction(&session(), &mut inventory, 2)
+            .unwrap();
+
+        assert_eq!(inventory.products.get("WID-001").unwrap().quantity, 10);
+        assert_eq!(inventory.products.get("GAD-001").unwrap().quantity, 3);
+        assert_eq!(transaction_manager.transactions.len(), 4);
+        let void = transaction_manager.find(void_id).unwrap();
+        assert_eq!(void.transaction_type, TransactionType::Void);
+        assert_eq!(void.reference_id, Some(1));
+        assert!(transaction_manager.sales().is_empty());
+        assert!(transaction_manager.purchases().is_empty());
+
+        assert_eq!(
+            transaction_manager
+                .void_transaction(&session(), &mut inventory, 1)
+                .unwrap_err(),
+            "Transaction 1 was already voided by transaction 3."
+        );
+        assert_eq!(
+            transaction_manager
+                .void_transaction(&session(), &mut inventory, void_id)
+                .unwrap_err(),
+            "A void cannot itself be voided."
+        );
+        assert_eq!(
+            transaction_manager
+                .record_return(&session(), &mut inventory, 1, 1, false)
+                .unwrap_err(),
+            "Sale 1 has been voided."
+        );
+    }
+
+    #[test]
+    fn void_of_sale_with_returns_is_rejected() {
+        let mut inventory = stocked_inventory();
+        let mut transaction_manager = TransactionManager::new();
+        transaction_manager
+            .record_sale(
+                &session(),
+                &mut inventory,
+                "WID-001",
+                4,
+                Money::from_cents(1000),
+                PaymentMethod::Cash,
+            )
+            .unwrap();
+        transaction_manager
+            .record_return(&session(), &mut inventory, 1, 1, false)
+            .unwrap();
+
+        let result = transaction_manager.void_transaction(&session(), &mut inventory, 1);
+        assert_eq!(
+            result.unwrap_err(),
+            "Sale 1 has returns against it. Void the returns first."
+        );
+
+        // Voiding the return takes the restocked unit back out
+        transaction_manager
+            .void_transaction(&session(), &mut inventory, 2)
+            .unwrap();
+        assert_eq!(inventory.products.get("WID-001").unwrap().quantity, 6);
+        assert_eq!(transaction_manager.returned_quantity(1), 0);
+        transaction_manager
+            .void_transaction(&session(), &mut inventory, 1)
+            .unwrap();
+        assert_eq!(inventory.products.get("WID-001").unwrap().quantity, 10);
+    }
+
+    #[test]
+    fn void_cannot_take_back_sold_stock() {
+        let mut inventory = stocked_inventory();
+        let mut transaction_manager = TransactionManager::new();
+        transaction_manager
+            .record_purchase(
+                &session(),
+                &mut inventory,
+                "GAD-001",
+                2,
+                Money::from_cents(600),
+            )
+            .unwrap();
+        transaction_manager
+            .record_sale(
+                &session(),
+                &mut inventory,
+                "GAD-001",
+                4,
+                Money::from_cents(1000),
+                PaymentMethod::Cash,
+            )
+            .unwrap();
+
+        let result = transaction_manager.void_transaction(&session(), &mut inventory, 1);
+
+        assert_eq!(
+            result.unwrap_err(),
+            "Only 1 unit(s) of 'GAD-001' are in stock, so the void cannot take back 2."
+        );
+        assert!(!transaction_manager.is_voided(1));
+    }
+
+    #[test]
+    fn void_of_purchase_removes_the_units_it_bought() {
+        for method in [CostingMethod::Fifo, CostingMethod::Lifo] {
+            let mut inventory = stocked_inventory();
+            inventory.costing_method = method;
+            let mut transaction_manager = TransactionManager::new();
+            transaction_manager
+                .record_purchase(
+                    &session(),
+                    &mut inventory,
+                    "WID-001",
+                    10,
+                    Money::from_cents(800),
+                )
+                .unwrap();
+
+            transaction_manager
+                .void_transaction(&session(), &mut inventory, 1)
+                .unwrap();
+
+            let product = inventory.products.get("WID-001").unwrap();
+            assert_eq!(product.quantity, 10);
+            assert_eq!(
+                product.layers,
+                [CostLayer {
+                    quantity: 10,
+                    unit_cost: Money::from_cents(600)
+                }]
+            );
+            assert_eq!(product.stock_value(), Money::from_cents(6000));
+            assert_eq!(product.cost, Money::from_cents(600));
+        }
+    }
+
+    #[test]
+    fn voids_a_migrated_sale_of_no_units() {
+        let mut inventory = stocked_inventory();
+        let mut transaction_manager = TransactionManager::new();
+        // Older versions stored unreadable quantities as 0
+        transaction_manager.transactions.push(Transaction {
+            id: 1,
+            recorded_at: Utc::now(),
+            transaction_type: TransactionType::Sale,
+            sku: "WID-001".to_string(),
+            quantity: 0,
+            price: Money::from_cents(1000),
+            total: Money::ZERO,
+            cost: Money::ZERO,
+            order_id: None,
+            reference_id: None,
+            damaged: false,
+            recorded_by: None,
+            payment_method: None,
+        });
+
+        transaction_manager
+            .void_transaction(&session(), &mut inventory, 1)
+            .unwrap();
+
+        assert!(transaction_manager.is_voided(1));
+        let product = inventory.products.get("WID-001").unwrap();
+        assert_eq!(product.quantity, 10);
+        assert_eq!(
+            product.layers,
+            stocked_inventory().products["WID-001"].layers
+        );
+    }
+
+    #[test]
+    fn records_cost_of_goods_sold() {
+        let mut inventory = stocked_inventory();
+        let mut transaction_manager = TransactionManager::new();
+        transaction_manager
+            .record_purchase(
+                &session(),
+                &mut inventory,
+                "WID-001",
+                5,
+                Money::from_cents(700),
+            )
+            .unwrap();
+        let product = inventory.products.get("WID-001").unwrap();
+        assert_eq!(product.cost, Money::from_cents(700));
+        assert_eq!(product.price, Money::from_cents(1000)); // List price untouched
+
+        transaction_manager
+            .record_sale(
+                &session(),
+                &mut inventory,
+                "WID-001",
+                3,
+                Money::from_cents(1000),
+                PaymentMethod::Cash,
+            )
+            .unwrap();
+        // FIFO sells the 10 widgets already on hand at $6.00 first
+        assert_eq!(
+            transaction_manager.find(2).unwrap().cost,
+            Money::from_cents(1800)
+        );
+
+        transaction_manager
+            .record_return(&session(), &mut inventory, 2, 1, false)
+            .unwrap();
+        transaction_manager
+            .record_return(&session(), &mut inventory, 2, 1, true)
+            .unwrap();
+        assert_eq!(
+            transaction_manager.find(3).unwrap().cost,
+            Money::from_cents(600)
+        );
+        assert_eq!(transaction_manager.find(4).unwrap().cost, Money::ZERO);
+        assert_eq!(
+            transaction_manager.cost_of_goods_sold(),
+            Money::from_cents(1200)
+        );
+    }
+
+    #[test]
+    fn costs_sales_under_the_chosen_method() {
+        let costs = |method: CostingMethod| {
+            let mut inventory = stocked_inventory(); // 10 widgets at $6.00
+            inventory.set_costing_method(method);
+            let mut transaction_manager = TransactionManager::new();
+            transaction_manager
+                .record_purchase(
+                    &session(),
+                    &mut inventory,
+                    "WID-001",
+                    10,
+                    Money::from_cents(800),
+                )
+                .unwrap();
+            transaction_manager
+                .record_sale(
+                    &session(),
+                    &mut inventory,
+                    "WID-001",
+                    15,
+                    Money::from_cents(1000),
+                    PaymentMethod::Cash,
+                )
+                .unwrap();
+            (
+                transaction_manager.find(2).unwrap().cost.cents(),
+                inventory.valuation().cents() - 1800, // Less the 3 gadgets at $6.00
+            )
+        };
+
+        assert_eq!(costs(CostingMethod::Fifo), (10 * 600 + 5 * 800, 5 * 800));
+        assert_eq!(costs(CostingMethod::Lifo), (10 * 800 + 5 * 600, 5 * 600));
+        assert_eq!(costs(CostingMethod::WeightedAverage), (15 * 700, 5 * 700));
+    }
+
+    #[test]
+    fn return_must_reference_a_sale() {
+        let mut inventory = stocked_inventory();
+        let mut transaction_manager = TransactionManager::new();
+        transaction_manager
+            .record_purchase(
+                &session(),
+                &mut inventory,
+                "WID-001",
+                5,
+                Money::from_cents(800),
+            )
+            .unwrap();
+
+        let result = transaction_manager.record_return(&session(), &mut inventory, 1, 1, false);
+
+        assert_eq!(result.unwrap_err(), "Sale 1 not found.");
+        assert_eq!(inventory.products.get("WID-001").unwrap().quantity, 15);
+    }
+
+    #[test]
+    fn filters_by_local_day_keeping_voids_of_kept_transactions() {
+        let mut inventory = stocked_inventory();
+        let mut transaction_manager = TransactionManager::new();
+        for _ in 0..3 {
+            transaction_manager
+                .record_sale(
+                    &session(),
+                    &mut inventory,
+                    "WID-001",
+                    1,
+                    Money::from_cents(1000),
+                    PaymentMethod::Cash,
+                )
+                .unwrap();
+        }
+        transaction_manager
+            .void_transaction(&session(), &mut inventory, 1)
+            .unwrap();
+        let day =
+            |days_ago: i64| (Local::now() - chrono::Duration::days(days_ago)).with_timezone(&Utc);
+        transaction_manager.transactions[0].recorded_at = day(10);
+        transaction_manager.transactions[1].recorded_at = day(3);
+
+        let today = Local::now().date_naive();
+        let ids = |manager: TransactionManager| -> Vec<u64> {
+            manager.transactions.iter().map(|t| t.id).collect()
+        };
+        assert_eq!(
+            ids(transaction_manager.between(None, None)),
+            vec![1, 2, 3, 4]
+        );
+        assert_eq!(
+            ids(transaction_manager.between(Some(today), None)),
+            vec![3, 4]
+        );
+        let earlier = transaction_manager.between(None, Some(today - chrono::Duration::days(1)));
+        assert_eq!(ids(earlier.clone()), vec![1, 2, 4]);
+        assert_eq!(earlier.sales().len(), 1);
     }
 }
@@ -1,5 +1,7 @@
 use std::io;
 
+use chrono::NaiveDate;
+
 pub fn format_table(headers: Vec<&str>, rows: Vec<Vec<String>>) -> String {
     let mut table = String::new();
 
@@ -59,4 +61,18 @@ pub fn get_user_input(prompt: &str) -> String {
     let mut input = String::new();
     io::stdin().read_line(&mut input).unwrap();
     input.trim().to_string()
-}
\ No newline at end of file
+}
+
+/// Reads an optional `YYYY-MM-DD` date, re-prompting until it parses.
+pub fn get_date(prompt: &str) -> Option<NaiveDate> {
+    loop {
+        let input = get_user_input(prompt);
+        if input.is_empty() {
+            return None;
+        }
+        match NaiveDate::parse_from_str(&input, "%Y-%m-%d") {
+            Ok(date) => return Some(date),
+            Err(_) => println!("Invalid date! Use YYYY-MM-DD."),
+        }
+    }
+}